@@ -0,0 +1,634 @@
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use avx_config::{
+    json_schema, write_file_key, AvxConfig, ConfigFile, ConfigLoader, ConfigScope, ResolvedConfig,
+    PROFILE_ENV_VAR,
+};
+use clap::{Subcommand, ValueEnum};
+use serde_json::json;
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Mostra a configuração efetiva com a origem de cada valor
+    Show {
+        /// Formato de saída
+        #[arg(long, value_enum, default_value_t = Format::Toml)]
+        format: Format,
+    },
+
+    /// Mostra o valor efetivo de uma chave
+    Get {
+        /// Chave (stack, layer, env, cluster, mesh, namespace, kube_context)
+        key: String,
+    },
+
+    /// Grava uma chave no arquivo de configuração
+    Set {
+        /// Chave (stack, layer, env, cluster, mesh, namespace, kube_context, profile)
+        key: String,
+
+        /// Novo valor
+        value: String,
+
+        /// Arquivo alvo: user (~/.config/avx/config.toml) ou project (avx.toml)
+        #[arg(long, value_enum, default_value_t = Scope::User)]
+        scope: Scope,
+    },
+
+    /// Remove uma chave do arquivo de configuração
+    Unset {
+        /// Chave a remover
+        key: String,
+
+        /// Arquivo alvo: user (~/.config/avx/config.toml) ou project (avx.toml)
+        #[arg(long, value_enum, default_value_t = Scope::User)]
+        scope: Scope,
+    },
+
+    /// Abre o arquivo de configuração no $EDITOR
+    Edit {
+        /// Arquivo alvo: user (~/.config/avx/config.toml) ou project (avx.toml)
+        #[arg(long, value_enum, default_value_t = Scope::User)]
+        scope: Scope,
+    },
+
+    /// Mostra a cadeia de resolução (defaults → arquivos → profile → env → CLI)
+    Explain {
+        /// Chave a explicar (se não passar, explica todas)
+        key: Option<String>,
+    },
+
+    /// Ativa um profile (grava `profile = "<nome>"` no arquivo do usuário, ou no
+    /// avx.toml do projeto quando ele já define `profile`)
+    UseContext {
+        /// Nome do profile (`[profiles.<nome>]`)
+        name: String,
+    },
+
+    /// Lista os profiles definidos nos arquivos de configuração
+    GetContexts,
+
+    /// Mostra o profile ativo e de onde ele foi selecionado
+    CurrentContext,
+
+    /// Valida a configuração efetiva e todos os profiles definidos
+    Validate,
+
+    /// Exporta o JSON Schema do avx.toml (para validação no editor)
+    Schema {
+        /// Caminho do arquivo de saída (se não passar, manda pro stdout)
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Scope {
+    User,
+    Project,
+}
+
+impl From<Scope> for ConfigScope {
+    fn from(scope: Scope) -> Self {
+        match scope {
+            Scope::User => ConfigScope::User,
+            Scope::Project => ConfigScope::Project,
+        }
+    }
+}
+
+pub fn handle_config_command(command: ConfigCommands, loader: &ConfigLoader) -> Result<()> {
+    match command {
+        ConfigCommands::Show { format } => {
+            let resolved = loader.resolve()?;
+            print!("{}", render_show(&resolved, format)?);
+        }
+
+        ConfigCommands::Get { key } => {
+            check_key(&key, false)?;
+            let resolved = loader.resolve()?;
+            match resolved.config.get(&key) {
+                Some(value) => println!("{value}"),
+                None => bail!("`{}` não está definido", key),
+            }
+        }
+
+        ConfigCommands::Set { key, value, scope } => {
+            check_key(&key, true)?;
+            if key == "profile" {
+                check_profile(loader, &value)?;
+            } else {
+                AvxConfig::validate_value(&key, &value)?;
+            }
+
+            let path = scope_path(loader, scope)?;
+            write_file_key(&path, &key, Some(&value))?;
+            println!("✅ {} = {:?} ({})", key, value, path.display());
+            warn_if_shadowed(loader, &key, scope)?;
+        }
+
+        ConfigCommands::Unset { key, scope } => {
+            check_key(&key, true)?;
+            let path = scope_path(loader, scope)?;
+            write_file_key(&path, &key, None)?;
+            println!("✅ {} removido de {}", key, path.display());
+        }
+
+        ConfigCommands::Edit { scope } => {
+            let path = scope_path(loader, scope)?;
+            if !path.exists() {
+                crate::write_to_file(
+                    &path.to_string_lossy(),
+                    "# Configuração Avx - veja `avx-cli config schema`\n",
+                )?;
+            }
+
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+            let status = Command::new(&editor)
+                .arg(&path)
+                .status()
+                .with_context(|| format!("não foi possível abrir o editor `{}`", editor))?;
+            if !status.success() {
+                bail!("editor `{}` terminou com {}", editor, status);
+            }
+
+            ConfigFile::read(&path)?;
+            println!("✅ {} salvo", path.display());
+        }
+
+        ConfigCommands::Explain { key } => {
+            let keys: Vec<&str> = match &key {
+                Some(key) => {
+                    check_key(key, false)?;
+                    vec![key.as_str()]
+                }
+                None => AvxConfig::KEYS.to_vec(),
+            };
+
+            let resolved = loader.resolve()?;
+            match &resolved.profile {
+                Some(active) => println!("profile: {} (via {})\n", active.name, active.selected_by),
+                None => println!("profile: nenhum\n"),
+            }
+
+            for key in keys {
+                let chain = resolved
+                    .chain
+                    .get(key)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                match resolved.config.get(key) {
+                    Some(value) => println!("{} = {:?}", key, value),
+                    None => println!("{} (não definido)", key),
+                }
+                for (i, entry) in chain.iter().enumerate() {
+                    let marker = if i + 1 == chain.len() {
+                        "  ← efetivo"
+                    } else {
+                        ""
+                    };
+                    println!(
+                        "  {}. {:<60} {:?}{}",
+                        i + 1,
+                        entry.source.to_string(),
+                        entry.value,
+                        marker
+                    );
+                }
+                println!();
+            }
+        }
+
+        ConfigCommands::UseContext { name } => {
+            check_profile(loader, &name)?;
+
+            // A `profile` in the project avx.toml shadows the user file, so
+            // it is updated there instead
+            let scope = loader.profile_scope()?;
+            let path = loader
+                .path_for(scope)
+                .with_context(|| format!("não foi possível determinar o arquivo {}", scope))?;
+            write_file_key(&path, "profile", Some(&name))?;
+            println!("✅ Profile ativo: {} ({})", name, path.display());
+            if scope == ConfigScope::Project {
+                println!(
+                    "ℹ️  {} já seleciona um profile e tem precedência sobre o arquivo do usuário",
+                    path.display()
+                );
+            }
+
+            if let Ok(env_profile) = std::env::var(PROFILE_ENV_VAR) {
+                if !env_profile.is_empty() && env_profile != name {
+                    println!(
+                        "⚠️  {}={} está definido e tem precedência neste shell",
+                        PROFILE_ENV_VAR, env_profile
+                    );
+                }
+            }
+        }
+
+        ConfigCommands::GetContexts => {
+            let profiles = loader.profiles()?;
+            if profiles.is_empty() {
+                println!("Nenhum profile definido. Adicione `[profiles.<nome>]` ao avx.toml ou ~/.config/avx/config.toml");
+                return Ok(());
+            }
+
+            let current = loader.selected_profile()?.map(|p| p.name);
+            println!("CURRENT  NAME             ENV        CLUSTER          NAMESPACE    MESH");
+            for (name, profile) in &profiles {
+                let marker = if current.as_deref() == Some(name.as_str()) {
+                    "*"
+                } else {
+                    ""
+                };
+                println!(
+                    "{:<8} {:<16} {:<10} {:<16} {:<12} {}",
+                    marker,
+                    name,
+                    profile.env.as_deref().unwrap_or("-"),
+                    profile.cluster.as_deref().unwrap_or("-"),
+                    profile.namespace.as_deref().unwrap_or("-"),
+                    profile.mesh.as_deref().unwrap_or("-"),
+                );
+            }
+        }
+
+        ConfigCommands::CurrentContext => match loader.selected_profile()? {
+            Some(active) => println!("{} (via {})", active.name, active.selected_by),
+            None => println!("Nenhum profile ativo"),
+        },
+
+        ConfigCommands::Validate => {
+            let resolved = loader.resolve()?;
+            let mut problems = Vec::new();
+
+            if let Err(errors) = resolved.config.validate() {
+                for error in errors.iter() {
+                    let source = resolved
+                        .source(&error.key)
+                        .map(|s| s.to_string())
+                        .unwrap_or_default();
+                    problems.push(format!("{} ({})", error, source));
+                }
+            }
+            for (name, profile) in loader.profiles()? {
+                if let Err(errors) = profile.validate(&name) {
+                    problems.extend(errors.iter().map(|e| e.to_string()));
+                }
+            }
+
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("  ❌ {}", problem);
+                }
+                bail!("{} valor(es) inválido(s) na configuração", problems.len());
+            }
+            println!("✅ Configuração válida");
+        }
+
+        ConfigCommands::Schema { output } => {
+            let schema = serde_json::to_string_pretty(&json_schema())?;
+            match output {
+                Some(path) => {
+                    crate::write_to_file(&path, &schema)?;
+                    println!("✅ Schema gerado em: {}", path);
+                }
+                None => println!("{schema}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// `profile` is only accepted where a file key makes sense (`set`/`unset`).
+fn check_key(key: &str, allow_profile: bool) -> Result<()> {
+    if AvxConfig::KEYS.contains(&key) || (allow_profile && key == "profile") {
+        return Ok(());
+    }
+    bail!(
+        "chave desconhecida `{}` (válidas: {})",
+        key,
+        AvxConfig::KEYS.join(", ")
+    )
+}
+
+/// Fails unless `name` is a profile defined in some config file.
+fn check_profile(loader: &ConfigLoader, name: &str) -> Result<()> {
+    let profiles = loader.profiles()?;
+    if profiles.contains_key(name) {
+        return Ok(());
+    }
+    let available: Vec<&str> = profiles.keys().map(String::as_str).collect();
+    bail!(
+        "profile `{}` não existe (disponíveis: {})",
+        name,
+        if available.is_empty() {
+            "nenhum".to_string()
+        } else {
+            available.join(", ")
+        }
+    )
+}
+
+fn scope_path(loader: &ConfigLoader, scope: Scope) -> Result<std::path::PathBuf> {
+    loader
+        .path_for(scope.into())
+        .with_context(|| format!("não foi possível determinar o arquivo {:?}", scope))
+}
+
+/// Warns when a higher-precedence layer still overrides what was just written.
+fn warn_if_shadowed(loader: &ConfigLoader, key: &str, scope: Scope) -> Result<()> {
+    if key == "profile" {
+        return Ok(());
+    }
+    let resolved = loader.resolve()?;
+    if let Some(source) = resolved.source(key) {
+        let written_here = matches!(
+            source,
+            avx_config::ConfigSource::File { scope: s, .. } if *s == ConfigScope::from(scope)
+        );
+        if !written_here {
+            println!("⚠️  valor efetivo ainda vem de: {}", source);
+        }
+    }
+    Ok(())
+}
+
+fn render_show(resolved: &ResolvedConfig, format: Format) -> Result<String> {
+    let mut out = String::new();
+    match format {
+        Format::Toml => {
+            if let Some(active) = &resolved.profile {
+                out.push_str(&format!(
+                    "# profile: {} (via {})\n",
+                    active.name, active.selected_by
+                ));
+            }
+            for key in AvxConfig::KEYS {
+                let Some(value) = resolved.config.get(key) else {
+                    continue;
+                };
+                let line = format!("{} = {}", key, toml::Value::String(value.to_string()));
+                let source = resolved
+                    .source(key)
+                    .map(|s| s.to_string())
+                    .unwrap_or_default();
+                out.push_str(&format!("{:<40} # {}\n", line, source));
+            }
+        }
+        Format::Json | Format::Yaml => {
+            let mut value = serde_json::Map::new();
+            for key in AvxConfig::KEYS {
+                if let Some(v) = resolved.config.get(key) {
+                    value.insert(
+                        key.to_string(),
+                        json!({ "value": v, "source": resolved.source(key) }),
+                    );
+                }
+            }
+            value.insert("profile".into(), json!(resolved.profile));
+            let value = serde_json::Value::Object(value);
+            if matches!(format, Format::Json) {
+                out = serde_json::to_string_pretty(&value)?;
+                out.push('\n');
+            } else {
+                out = serde_yaml::to_string(&value)?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A loader reading only `<dir>/user.toml` and `<dir>/project/avx.toml`.
+    fn loader(dir: &TempDir) -> ConfigLoader {
+        ConfigLoader::new()
+            .system_path(None)
+            .user_path(Some(dir.path().join("user.toml")))
+            .cwd(Some(dir.path().join("project")))
+            .use_env(false)
+    }
+
+    fn run(loader: &ConfigLoader, command: ConfigCommands) -> Result<()> {
+        handle_config_command(command, loader)
+    }
+
+    #[test]
+    fn profile_is_only_a_key_for_set_and_unset() {
+        assert!(check_key("mesh", false).is_ok());
+        assert!(check_key("profile", true).is_ok());
+        assert!(check_key("profile", false).is_err());
+        let error = check_key("replicas", true).unwrap_err().to_string();
+        assert!(error.contains("`replicas`"), "{}", error);
+        assert!(error.contains("kube_context"), "{}", error);
+    }
+
+    #[test]
+    fn set_and_unset_edit_the_scope_file() {
+        let dir = TempDir::new().unwrap();
+        let loader = loader(&dir);
+        fs::create_dir(dir.path().join("project")).unwrap();
+
+        run(
+            &loader,
+            ConfigCommands::Set {
+                key: "mesh".into(),
+                value: "istio".into(),
+                scope: Scope::Project,
+            },
+        )
+        .unwrap();
+        run(
+            &loader,
+            ConfigCommands::Set {
+                key: "env".into(),
+                value: "staging".into(),
+                scope: Scope::User,
+            },
+        )
+        .unwrap();
+        let cfg = loader.load().unwrap();
+        assert_eq!((cfg.mesh.as_str(), cfg.env.as_str()), ("istio", "staging"));
+        let project = fs::read_to_string(dir.path().join("project/avx.toml")).unwrap();
+        assert_eq!(project.trim(), "mesh = \"istio\"");
+
+        run(
+            &loader,
+            ConfigCommands::Unset {
+                key: "mesh".into(),
+                scope: Scope::Project,
+            },
+        )
+        .unwrap();
+        assert_eq!(loader.load().unwrap().mesh, "none");
+    }
+
+    #[test]
+    fn set_rejects_invalid_values_and_unknown_keys() {
+        let dir = TempDir::new().unwrap();
+        let loader = loader(&dir);
+
+        for (key, value) in [("mesh", "consul"), ("replicas", "3")] {
+            let result = run(
+                &loader,
+                ConfigCommands::Set {
+                    key: key.into(),
+                    value: value.into(),
+                    scope: Scope::User,
+                },
+            );
+            assert!(result.is_err(), "{} = {}", key, value);
+        }
+        assert!(!dir.path().join("user.toml").exists());
+    }
+
+    #[test]
+    fn use_context_writes_where_the_profile_is_selected() {
+        let dir = TempDir::new().unwrap();
+        let loader = loader(&dir);
+        fs::write(
+            dir.path().join("user.toml"),
+            "[profiles.staging]\nenv = \"staging\"\n[profiles.production]\nenv = \"prod\"\n",
+        )
+        .unwrap();
+
+        let error = run(&loader, ConfigCommands::UseContext { name: "qa".into() })
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("production, staging"), "{}", error);
+
+        run(
+            &loader,
+            ConfigCommands::UseContext {
+                name: "staging".into(),
+            },
+        )
+        .unwrap();
+        assert_eq!(loader.load().unwrap().env, "staging");
+
+        fs::create_dir(dir.path().join("project")).unwrap();
+        fs::write(
+            dir.path().join("project/avx.toml"),
+            "profile = \"staging\"\n",
+        )
+        .unwrap();
+        run(
+            &loader,
+            ConfigCommands::UseContext {
+                name: "production".into(),
+            },
+        )
+        .unwrap();
+        let project = fs::read_to_string(dir.path().join("project/avx.toml")).unwrap();
+        assert_eq!(project.trim(), "profile = \"production\"");
+        assert_eq!(loader.load().unwrap().env, "prod");
+    }
+
+    #[test]
+    fn validate_fails_on_any_invalid_value() {
+        let dir = TempDir::new().unwrap();
+        let loader = loader(&dir);
+        fs::write(
+            dir.path().join("user.toml"),
+            "env = \"staging\"\n[profiles.staging]\nmesh = \"istio\"\n",
+        )
+        .unwrap();
+        assert!(run(&loader, ConfigCommands::Validate).is_ok());
+
+        fs::write(
+            dir.path().join("user.toml"),
+            "env = \"qa\"\n[profiles.staging]\nmesh = \"consul\"\n",
+        )
+        .unwrap();
+        let error = run(&loader, ConfigCommands::Validate).unwrap_err();
+        assert_eq!(error.to_string(), "2 valor(es) inválido(s) na configuração");
+    }
+
+    #[test]
+    fn show_annotates_each_value_with_its_source() {
+        let dir = TempDir::new().unwrap();
+        let user = dir.path().join("user.toml");
+        fs::write(
+            &user,
+            "profile = \"production\"\nstack = \"checkout\"\n[profiles.production]\nenv = \"prod\"\n",
+        )
+        .unwrap();
+        let resolved = loader(&dir)
+            .overrides(ConfigFile {
+                mesh: Some("istio".into()),
+                ..ConfigFile::default()
+            })
+            .resolve()
+            .unwrap();
+
+        let text = render_show(&resolved, Format::Toml).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!("# profile: production (via user file {})", user.display())
+        );
+        let line = |key: &str| {
+            *lines
+                .iter()
+                .find(|l| l.starts_with(&format!("{} = ", key)))
+                .unwrap()
+        };
+        assert!(line("stack").starts_with("stack = \"checkout\""));
+        assert!(line("stack").ends_with(&format!("# user file {}", user.display())));
+        assert!(line("env").ends_with(&format!(
+            "# profile production in user file {}",
+            user.display()
+        )));
+        assert!(line("mesh").ends_with("# command line"));
+        assert!(line("layer").ends_with("# default"));
+        assert!(!text.contains("kube_context"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render_show(&resolved, Format::Json).unwrap()).unwrap();
+        assert_eq!(
+            json["mesh"],
+            json!({"value": "istio", "source": {"kind": "cli"}})
+        );
+        assert_eq!(json["profile"]["name"], "production");
+        let yaml: serde_json::Value =
+            serde_yaml::from_str(&render_show(&resolved, Format::Yaml).unwrap()).unwrap();
+        assert_eq!(yaml, json);
+    }
+
+    #[test]
+    fn set_profile_requires_a_defined_profile() {
+        let dir = TempDir::new().unwrap();
+        let loader = loader(&dir);
+        let user = dir.path().join("user.toml");
+        fs::write(&user, "[profiles.staging]\nenv = \"staging\"\n").unwrap();
+        let set = |value: &str| ConfigCommands::Set {
+            key: "profile".into(),
+            value: value.into(),
+            scope: Scope::User,
+        };
+
+        let error = run(&loader, set("stagign")).unwrap_err().to_string();
+        assert_eq!(error, "profile `stagign` não existe (disponíveis: staging)");
+        assert!(!fs::read_to_string(&user).unwrap().contains("stagign"));
+
+        run(&loader, set("staging")).unwrap();
+        assert_eq!(loader.load().unwrap().env, "staging");
+    }
+}
@@ -0,0 +1,684 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use avx_config::{AvxConfig, ConfigLoader};
+use avx_k8s::{
+    diff_manifests, generate_k8s_manifest, generate_manifests, lint, load_objects, load_sources,
+    parse_quantity, render_helm, render_kustomize, render_stack, stack_manifests, wait_for_rollout,
+    ClusterBackend, Finding, HpaSettings, KubeBackend, LintConfig, Manifest, ManifestOptions,
+    ObjectDiff, RenderedFile, ResourceSettings, RolloutState, ServiceCatalog, Severity,
+    SourceObject, StackLayout,
+};
+use clap::{Subcommand, ValueEnum};
+
+#[derive(clap::Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct K8sArgs {
+    #[command(flatten)]
+    render: RenderArgs,
+
+    /// Com --all, grava um único stack.yaml em vez de um arquivo por serviço
+    #[arg(long, requires = "all")]
+    bundle: bool,
+
+    /// Formato de saída
+    #[arg(long, value_enum, default_value_t = OutputFormat::Yaml)]
+    format: OutputFormat,
+
+    /// Arquivo de saída (yaml; se não passar, manda pro stdout) ou diretório (kustomize/helm)
+    #[arg(long)]
+    output: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<K8sCommands>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum K8sCommands {
+    /// Compara os manifests gerados com YAML existente (sai com código 1 se houver diferenças)
+    Diff(DiffArgs),
+
+    /// Verifica manifests (gerados ou arquivos) contra as regras de política
+    Lint(LintArgs),
+
+    /// Aplica os manifests gerados no cluster (server-side apply) e espera o rollout
+    Apply(ApplyArgs),
+
+    /// Mostra o status do rollout do Deployment de um serviço
+    Status(StatusArgs),
+
+    /// Volta o Deployment de um serviço para uma revisão anterior
+    Rollback(RollbackArgs),
+}
+
+/// Intervalo entre consultas enquanto espera um rollout
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(clap::Args, Debug)]
+pub struct ApplyArgs {
+    #[command(flatten)]
+    render: RenderArgs,
+
+    #[command(flatten)]
+    cluster: ClusterArgs,
+
+    /// Só valida no API server (dry-run), sem gravar nada
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Não espera o rollout dos Deployments aplicados
+    #[arg(long)]
+    no_wait: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct StatusArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+
+    #[command(flatten)]
+    cluster: ClusterArgs,
+
+    /// Acompanha até o rollout terminar (ou estourar --timeout)
+    #[arg(long)]
+    watch: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RollbackArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+
+    #[command(flatten)]
+    cluster: ClusterArgs,
+
+    /// Revisão de destino (default: a anterior à atual)
+    #[arg(long)]
+    to_revision: Option<i64>,
+
+    /// Só valida no API server (dry-run), sem gravar nada
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Não espera o rollout depois do rollback
+    #[arg(long)]
+    no_wait: bool,
+}
+
+/// Deployment de um serviço do catálogo; comum a `k8s status` e `k8s rollback`
+#[derive(clap::Args, Debug)]
+pub struct TargetArgs {
+    /// Serviço alvo: id do catálogo (built-in: gateway | api-core | events)
+    #[arg(long)]
+    service: String,
+
+    /// Catálogo de serviços (default: AVX_CATALOG ou avx-services.toml mais próximo)
+    #[arg(long, value_name = "PATH")]
+    catalog: Option<PathBuf>,
+
+    /// Namespace Kubernetes (default: `namespace` da configuração, avx-core)
+    #[arg(long)]
+    namespace: Option<String>,
+}
+
+/// Como chegar no cluster
+#[derive(clap::Args, Debug)]
+pub struct ClusterArgs {
+    /// Contexto do kubeconfig (default: `kube_context` da configuração ou o contexto atual)
+    #[arg(long)]
+    context: Option<String>,
+
+    /// Tempo máximo de espera pelo rollout, em segundos
+    #[arg(long, default_value_t = 300)]
+    timeout: u64,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct LintArgs {
+    #[command(flatten)]
+    render: RenderArgs,
+
+    /// Arquivos ou diretórios YAML (se não passar, verifica o que --service/--all geraria)
+    paths: Vec<PathBuf>,
+
+    /// Formato do relatório
+    #[arg(long, value_enum, default_value_t = LintFormat::Text)]
+    format: LintFormat,
+
+    /// Grava o relatório em arquivo em vez do stdout
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LintFormat {
+    Text,
+    Json,
+    /// SARIF 2.1.0 (anotações de CI / code scanning)
+    Sarif,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    #[command(flatten)]
+    render: RenderArgs,
+
+    /// Arquivo ou diretório YAML: render anterior ou snapshot `kubectl get -o yaml`
+    path: PathBuf,
+}
+
+/// Quais serviços gerar e com quais opções; comum a `k8s` e `k8s diff`
+#[derive(clap::Args, Debug)]
+pub struct RenderArgs {
+    /// Serviço alvo: id do catálogo (built-in: gateway | api-core | events)
+    #[arg(long)]
+    service: Option<String>,
+
+    /// Todos os serviços do catálogo (Namespace + kustomization.yaml em --output)
+    #[arg(long, conflicts_with_all = ["service", "image", "ingress"])]
+    all: bool,
+
+    /// Catálogo de serviços (default: AVX_CATALOG ou avx-services.toml mais próximo)
+    #[arg(long, value_name = "PATH")]
+    catalog: Option<PathBuf>,
+
+    /// Namespace Kubernetes (default: `namespace` da configuração, avx-core)
+    #[arg(long)]
+    namespace: Option<String>,
+
+    /// Imagem do container (se não passar, usa default baseado no service)
+    #[arg(long)]
+    image: Option<String>,
+
+    /// Número de réplicas (ignorado com --hpa: o autoscaler controla as réplicas)
+    #[arg(long, default_value_t = 2)]
+    replicas: u32,
+
+    /// CPU request do container
+    #[arg(long, default_value = "100m", value_parser = parse_quantity)]
+    cpu_request: String,
+
+    /// CPU limit do container
+    #[arg(long, default_value = "500m", value_parser = parse_quantity)]
+    cpu_limit: String,
+
+    /// Memory request do container
+    #[arg(long, default_value = "128Mi", value_parser = parse_quantity)]
+    memory_request: String,
+
+    /// Memory limit do container
+    #[arg(long, default_value = "256Mi", value_parser = parse_quantity)]
+    memory_limit: String,
+
+    /// Não gera liveness/readiness/startup probes
+    #[arg(long)]
+    no_probes: bool,
+
+    /// Gera um HorizontalPodAutoscaler: min:max:cpu% (ex: 2:10:70%)
+    #[arg(long, value_name = "MIN:MAX:CPU%")]
+    hpa: Option<HpaSettings>,
+
+    /// Gera um PodDisruptionBudget (maxUnavailable: 1)
+    #[arg(long)]
+    pdb: bool,
+
+    /// Gera uma ServiceAccount dedicada para os pods
+    #[arg(long)]
+    service_account: bool,
+
+    /// Gera um ConfigMap com as variáveis AVX__* (usado via envFrom)
+    #[arg(long)]
+    config_map: bool,
+
+    /// Gera uma NetworkPolicy que só aceita tráfego de pods da mesma stack
+    #[arg(long)]
+    network_policy: bool,
+
+    /// Gera um Ingress para o host informado
+    #[arg(long, value_name = "HOST")]
+    ingress: Option<String>,
+
+    /// ingressClassName do Ingress
+    #[arg(long, requires = "ingress")]
+    ingress_class: Option<String>,
+
+    /// Namespace do ingress controller liberado pela NetworkPolicy (default: ingress-nginx)
+    #[arg(long, value_name = "NAMESPACE", requires = "ingress")]
+    ingress_namespace: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// YAML multi-documento
+    Yaml,
+    /// base/ + overlays/<env>/ do kustomize
+    Kustomize,
+    /// Chart Helm (Chart.yaml, values.yaml, templates/)
+    Helm,
+}
+
+fn load_catalog(path: Option<&Path>) -> Result<ServiceCatalog> {
+    Ok(match path {
+        Some(path) => ServiceCatalog::load(path)?,
+        None => ServiceCatalog::discover(&std::env::current_dir()?)?,
+    })
+}
+
+impl RenderArgs {
+    fn catalog(&self) -> Result<ServiceCatalog> {
+        load_catalog(self.catalog.as_deref())
+    }
+
+    /// Manifests de --service, ou da stack inteira com --all
+    fn manifests(self, cfg: &AvxConfig) -> Result<Vec<Manifest>> {
+        let catalog = self.catalog()?;
+        let all = self.all;
+        let opts = self.options(cfg, &catalog)?;
+        Ok(if all {
+            stack_manifests(cfg, &catalog, &opts)?
+        } else {
+            generate_manifests(cfg, &opts)?
+        })
+    }
+
+    fn options(self, cfg: &AvxConfig, catalog: &ServiceCatalog) -> Result<ManifestOptions> {
+        let service = match &self.service {
+            Some(id) => catalog.get(id)?.clone(),
+            // only a template for --all; each service replaces it
+            None if self.all => catalog.services()[0].clone(),
+            None => bail!("informe --service <id> ou --all"),
+        };
+        Ok(ManifestOptions {
+            service,
+            namespace: self.namespace.unwrap_or_else(|| cfg.namespace.clone()),
+            image: self.image,
+            replicas: self.replicas,
+            resources: ResourceSettings {
+                cpu_request: self.cpu_request,
+                cpu_limit: self.cpu_limit,
+                memory_request: self.memory_request,
+                memory_limit: self.memory_limit,
+            },
+            probes: !self.no_probes,
+            hpa: self.hpa,
+            pdb: self.pdb,
+            service_account: self.service_account,
+            config_map: self.config_map,
+            network_policy: self.network_policy,
+            ingress: self.ingress,
+            ingress_class: self.ingress_class,
+            ingress_namespace: self.ingress_namespace,
+        })
+    }
+}
+
+impl TargetArgs {
+    /// (namespace, nome do Deployment)
+    fn resolve(&self, cfg: &AvxConfig) -> Result<(String, String)> {
+        let catalog = load_catalog(self.catalog.as_deref())?;
+        let service = catalog.get(&self.service)?;
+        let namespace = self
+            .namespace
+            .clone()
+            .unwrap_or_else(|| cfg.namespace.clone());
+        Ok((namespace, service.name.clone()))
+    }
+}
+
+impl ClusterArgs {
+    async fn connect(&self, cfg: &AvxConfig, loader: &ConfigLoader) -> Result<KubeBackend> {
+        let context = match &self.context {
+            Some(context) => Some(context.clone()),
+            None => loader.kube_context_for(&cfg.cluster)?,
+        };
+        Ok(KubeBackend::connect(context.as_deref()).await?)
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+}
+
+pub async fn handle_k8s_command(args: K8sArgs, loader: &ConfigLoader) -> Result<()> {
+    let cfg = loader.load()?;
+    // linting files on disk does not depend on the avx config values, so
+    // `lint` only validates them when it renders
+    if !matches!(args.command, Some(K8sCommands::Lint(_))) {
+        cfg.validate()?;
+    }
+
+    match args.command {
+        Some(K8sCommands::Diff(diff)) => return handle_diff(diff, &cfg),
+        Some(K8sCommands::Lint(lint)) => return handle_lint(lint, &cfg, loader),
+        Some(K8sCommands::Apply(apply)) => return handle_apply(apply, &cfg, loader).await,
+        Some(K8sCommands::Status(status)) => return handle_status(status, &cfg, loader).await,
+        Some(K8sCommands::Rollback(rollback)) => {
+            return handle_rollback(rollback, &cfg, loader).await
+        }
+        None => {}
+    }
+
+    let all = args.render.all;
+    let catalog = args.render.catalog()?;
+    let opts = args.render.options(&cfg, &catalog)?;
+
+    if all {
+        if args.format != OutputFormat::Yaml {
+            bail!("--all suporta apenas --format yaml");
+        }
+        let Some(dir) = args.output else {
+            bail!("--all precisa de --output <diretório>");
+        };
+        let layout = if args.bundle {
+            StackLayout::Bundle
+        } else {
+            StackLayout::PerService
+        };
+        let files = render_stack(&cfg, &catalog, &opts, layout)?;
+        write_files(Path::new(&dir), &files)?;
+        println!(
+            "✅ Stack ({} serviços) gerada em: {}",
+            catalog.services().len(),
+            dir
+        );
+        return Ok(());
+    }
+
+    let files = match args.format {
+        OutputFormat::Yaml => {
+            let manifest = generate_k8s_manifest(&cfg, &opts)?;
+            if let Some(path) = args.output {
+                crate::write_to_file(&path, &manifest)?;
+                println!("✅ Manifest gerado em: {}", path);
+            } else {
+                println!("{manifest}");
+            }
+            return Ok(());
+        }
+        OutputFormat::Kustomize => render_kustomize(&cfg, &opts)?,
+        OutputFormat::Helm => render_helm(&cfg, &opts)?,
+    };
+
+    let Some(dir) = args.output else {
+        bail!("--format kustomize/helm precisa de --output <diretório>");
+    };
+    write_files(Path::new(&dir), &files)?;
+    println!("✅ {} arquivos gerados em: {}", files.len(), dir);
+
+    Ok(())
+}
+
+fn handle_diff(args: DiffArgs, cfg: &AvxConfig) -> Result<()> {
+    let desired = args.render.manifests(cfg)?;
+    let existing = load_objects(&args.path)?;
+    let diffs = diff_manifests(&desired, &existing)?;
+
+    if diffs.is_empty() {
+        println!("✅ Sem diferenças em relação a {}", args.path.display());
+        return Ok(());
+    }
+
+    for diff in &diffs {
+        match diff {
+            ObjectDiff::Added(key) => println!("+ {}", key),
+            ObjectDiff::Removed(key) => println!("- {}", key),
+            ObjectDiff::Changed(key, changes) => {
+                println!("~ {}", key);
+                for change in changes {
+                    println!(
+                        "    {}: {} → {}",
+                        change.path,
+                        show_value(change.existing.as_ref()),
+                        show_value(change.desired.as_ref())
+                    );
+                }
+            }
+        }
+    }
+    println!("\n⚠️  {} objeto(s) com diferenças", diffs.len());
+    std::process::exit(1);
+}
+
+fn handle_lint(args: LintArgs, cfg: &AvxConfig, loader: &ConfigLoader) -> Result<()> {
+    let config = LintConfig::from_settings(&loader.lint_settings()?)?;
+
+    let objects = if args.paths.is_empty() {
+        cfg.validate()?;
+        let manifests = args.render.manifests(cfg)?;
+        let mut objects = Vec::new();
+        for manifest in &manifests {
+            objects.push(SourceObject {
+                path: PathBuf::new(),
+                line: 0,
+                value: serde_yaml::to_value(manifest)?,
+            });
+        }
+        objects
+    } else {
+        if args.render.service.is_some() || args.render.all {
+            bail!("use --service/--all ou caminhos de arquivos, não os dois");
+        }
+        let mut objects = Vec::new();
+        for path in &args.paths {
+            objects.extend(load_sources(path)?);
+        }
+        objects
+    };
+
+    let findings = lint(&objects, &config);
+    let report = match args.format {
+        LintFormat::Text => lint_text(&findings, objects.len()),
+        LintFormat::Json => serde_json::to_string_pretty(&avx_k8s::lint::to_json(&findings))?,
+        LintFormat::Sarif => {
+            serde_json::to_string_pretty(&avx_k8s::lint::to_sarif(&findings, &config))?
+        }
+    };
+    match &args.output {
+        Some(path) => {
+            crate::write_to_file(path, &report)?;
+            println!("✅ Relatório gerado em: {}", path);
+        }
+        None => println!("{report}"),
+    }
+
+    if findings.iter().any(|f| f.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn handle_apply(args: ApplyArgs, cfg: &AvxConfig, loader: &ConfigLoader) -> Result<()> {
+    let manifests = args.render.manifests(cfg)?;
+    let mut objects = Vec::with_capacity(manifests.len());
+    for manifest in &manifests {
+        objects.push(serde_json::to_value(manifest)?);
+    }
+
+    let backend = args.cluster.connect(cfg, loader).await?;
+    let applied = backend.apply(&objects, args.dry_run).await?;
+    let suffix = if args.dry_run { " (dry-run)" } else { "" };
+    for object in &applied {
+        println!("✅ {}{}", object, suffix);
+    }
+    if args.dry_run || args.no_wait {
+        return Ok(());
+    }
+
+    for object in applied.iter().filter(|o| o.kind == "Deployment") {
+        let namespace = object.namespace.as_deref().unwrap_or(&cfg.namespace);
+        let status = wait_for_rollout(
+            &backend,
+            namespace,
+            &object.name,
+            args.cluster.timeout(),
+            POLL_INTERVAL,
+        )
+        .await?;
+        println!("🚀 {}/{}: {}", namespace, object.name, status);
+    }
+    Ok(())
+}
+
+async fn handle_status(args: StatusArgs, cfg: &AvxConfig, loader: &ConfigLoader) -> Result<()> {
+    let (namespace, name) = args.target.resolve(cfg)?;
+    let backend = args.cluster.connect(cfg, loader).await?;
+
+    let status = if args.watch {
+        wait_for_rollout(
+            &backend,
+            &namespace,
+            &name,
+            args.cluster.timeout(),
+            POLL_INTERVAL,
+        )
+        .await?
+    } else {
+        backend.rollout_status(&namespace, &name).await?
+    };
+    let icon = match status.state() {
+        RolloutState::Complete => "✅",
+        RolloutState::Progressing => "⏳",
+        RolloutState::Failed => "❌",
+    };
+    println!("{} {}/{}: {}", icon, namespace, name, status);
+
+    if status.state() == RolloutState::Failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn handle_rollback(args: RollbackArgs, cfg: &AvxConfig, loader: &ConfigLoader) -> Result<()> {
+    let (namespace, name) = args.target.resolve(cfg)?;
+    let backend = args.cluster.connect(cfg, loader).await?;
+
+    let rollback = backend
+        .rollback(&namespace, &name, args.to_revision, args.dry_run)
+        .await?;
+    let from = rollback
+        .from_revision
+        .map(|r| r.to_string())
+        .unwrap_or_else(|| "?".into());
+    let suffix = if args.dry_run { " (dry-run)" } else { "" };
+    println!(
+        "⏪ {}/{}: revisão {} → {}{}",
+        namespace, name, from, rollback.to_revision, suffix
+    );
+    if args.dry_run || args.no_wait {
+        return Ok(());
+    }
+
+    let status = wait_for_rollout(
+        &backend,
+        &namespace,
+        &name,
+        args.cluster.timeout(),
+        POLL_INTERVAL,
+    )
+    .await?;
+    println!("🚀 {}/{}: {}", namespace, name, status);
+    Ok(())
+}
+
+fn lint_text(findings: &[Finding], objects: usize) -> String {
+    let mut out = String::new();
+    for finding in findings {
+        let location = match (&finding.path, finding.line) {
+            (Some(path), Some(line)) => format!("{}:{}: ", path.display(), line),
+            _ => String::new(),
+        };
+        out.push_str(&format!(
+            "{}{} [{}] {}: {}\n",
+            location, finding.severity, finding.rule, finding.object, finding.message
+        ));
+    }
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    out.push_str(&format!(
+        "\n{} objeto(s) verificados: {} erro(s), {} aviso(s)/nota(s)",
+        objects,
+        errors,
+        findings.len() - errors
+    ));
+    out
+}
+
+fn show_value(value: Option<&serde_yaml::Value>) -> String {
+    match value {
+        Some(value) => serde_json::to_string(value).unwrap_or_default(),
+        None => "(ausente)".to_string(),
+    }
+}
+
+fn write_files(dir: &Path, files: &[RenderedFile]) -> Result<()> {
+    for file in files {
+        crate::write_to_file(&dir.join(&file.path).to_string_lossy(), &file.content)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        k8s: K8sArgs,
+    }
+
+    fn args(argv: &[&str]) -> K8sArgs {
+        Cli::try_parse_from(std::iter::once("k8s").chain(argv.iter().copied()))
+            .unwrap()
+            .k8s
+    }
+
+    /// A loader whose only config file sets an invalid `mesh`.
+    fn invalid_config(dir: &TempDir) -> ConfigLoader {
+        let user = dir.path().join("user.toml");
+        fs::write(&user, "mesh = \"consul\"\n").unwrap();
+        ConfigLoader::new()
+            .system_path(None)
+            .user_path(Some(user))
+            .cwd(None)
+            .use_env(false)
+    }
+
+    #[tokio::test]
+    async fn linting_files_ignores_the_config_values() {
+        let dir = TempDir::new().unwrap();
+        let loader = invalid_config(&dir);
+        let manifest = dir.path().join("config-map.yaml");
+        fs::write(
+            &manifest,
+            "apiVersion: v1\nkind: ConfigMap\n\
+             metadata: { name: settings, labels: { stack: avx, layer: core, env: prod } }\n",
+        )
+        .unwrap();
+
+        let lint = args(&["lint", manifest.to_str().unwrap()]);
+        handle_k8s_command(lint, &loader).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rendering_still_requires_a_valid_config() {
+        let dir = TempDir::new().unwrap();
+        let loader = invalid_config(&dir);
+
+        for argv in [
+            &["lint", "--service", "gateway"][..],
+            &["--service", "gateway"],
+        ] {
+            let error = handle_k8s_command(args(argv), &loader).await.unwrap_err();
+            assert!(error.to_string().contains("mesh"), "{:?}: {}", argv, error);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::io;
+use std::path::PathBuf;
+
+use crate::ValidationErrors;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AvxConfigError {
+    #[error("failed to read config file {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid config file {}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("failed to write config file {}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid config file {}", path.display())]
+    Edit {
+        path: PathBuf,
+        #[source]
+        source: toml_edit::TomlError,
+    },
+
+    #[error("unknown config key: {0}")]
+    UnknownKey(String),
+
+    #[error("profile `{0}` is not defined in any config file")]
+    UnknownProfile(String),
+
+    #[error(transparent)]
+    Invalid(#[from] ValidationErrors),
+}
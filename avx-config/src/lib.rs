@@ -1,6 +1,20 @@
+mod error;
+mod loader;
+mod validate;
+
+pub use error::AvxConfigError;
+pub use loader::{
+    system_config_path, user_config_path, write_file_key, ConfigFile, ConfigLoader, LintSettings,
+    Profile, PROFILE_ENV_VAR, PROJECT_FILE_NAME,
+};
+pub use validate::{
+    check_rfc1123_label, json_schema, Environment, Mesh, ValidationError, ValidationErrors,
+};
+
 use serde::{Deserialize, Serialize};
-use std::env;
-use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AvxConfig {
@@ -9,17 +23,125 @@ pub struct AvxConfig {
     pub env: String,
     pub cluster: String,
     pub mesh: String,
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+    /// Kubeconfig context used to reach `cluster`; the current kubeconfig
+    /// context when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kube_context: Option<String>,
+}
+
+fn default_namespace() -> String {
+    "avx-core".into()
+}
+
+/// Which config file a value was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigScope {
+    System,
+    User,
+    Project,
+}
+
+impl fmt::Display for ConfigScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigScope::System => write!(f, "system"),
+            ConfigScope::User => write!(f, "user"),
+            ConfigScope::Project => write!(f, "project"),
+        }
+    }
+}
+
+/// Where a resolved configuration value came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConfigSource {
+    Default,
+    File {
+        scope: ConfigScope,
+        path: PathBuf,
+    },
+    Profile {
+        name: String,
+        scope: ConfigScope,
+        path: PathBuf,
+    },
+    Env {
+        var: String,
+    },
+    Cli,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File { scope, path } => write!(f, "{} file {}", scope, path.display()),
+            ConfigSource::Profile { name, scope, path } => {
+                write!(f, "profile {} in {} file {}", name, scope, path.display())
+            }
+            ConfigSource::Env { var } => write!(f, "env {}", var),
+            ConfigSource::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// An [`AvxConfig`] together with the source of each of its keys.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedConfig {
+    pub config: AvxConfig,
+    pub sources: BTreeMap<String, ConfigSource>,
+    /// Every value seen for each key, lowest precedence first; the last one
+    /// is the effective value.
+    #[serde(default, skip_serializing)]
+    pub chain: BTreeMap<String, Vec<ConfigValue>>,
+    /// The active profile, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ActiveProfile>,
+}
+
+/// One layer's value for a key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigValue {
+    pub value: String,
+    pub source: ConfigSource,
+}
+
+/// The profile applied on top of the config files and what selected it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveProfile {
+    pub name: String,
+    pub selected_by: ConfigSource,
+}
+
+impl ResolvedConfig {
+    pub fn source(&self, key: &str) -> Option<&ConfigSource> {
+        self.sources.get(key)
+    }
 }
 
 impl AvxConfig {
-    pub fn load() -> Result<Self> {
-        Ok(Self {
-            stack: env::var("AVX__STACK").unwrap_or_else(|_| "default".into()),
-            layer: env::var("AVX__LAYER").unwrap_or_else(|_| "core".into()),
-            env: env::var("AVX__ENV").unwrap_or_else(|_| "dev".into()),
-            cluster: env::var("AVX__CLUSTER").unwrap_or_else(|_| "local".into()),
-            mesh: env::var("AVX__MESH").unwrap_or_else(|_| "default".into()),
-        })
+    /// Every configuration key, in display order.
+    pub const KEYS: [&'static str; 7] = [
+        "stack",
+        "layer",
+        "env",
+        "cluster",
+        "mesh",
+        "namespace",
+        "kube_context",
+    ];
+
+    /// Loads the configuration through the default [`ConfigLoader`] layers.
+    pub fn load() -> Result<Self, AvxConfigError> {
+        ConfigLoader::new().load()
+    }
+
+    /// Loads the configuration and records where each key came from.
+    pub fn resolve() -> Result<ResolvedConfig, AvxConfigError> {
+        ConfigLoader::new().resolve()
     }
 
     pub fn with_defaults() -> Self {
@@ -28,7 +150,47 @@ impl AvxConfig {
             layer: "core".into(),
             env: "dev".into(),
             cluster: "local".into(),
-            mesh: "default".into(),
+            mesh: "none".into(),
+            namespace: default_namespace(),
+            kube_context: None,
+        }
+    }
+
+    /// Returns the value of `key`, or `None` if it is not a known key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            "stack" => Some(&self.stack),
+            "layer" => Some(&self.layer),
+            "env" => Some(&self.env),
+            "cluster" => Some(&self.cluster),
+            "mesh" => Some(&self.mesh),
+            "namespace" => Some(&self.namespace),
+            "kube_context" => self.kube_context.as_deref(),
+            _ => None,
         }
     }
+
+    /// Sets `key` to `value`. Returns `false` if `key` is not a known key.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) -> bool {
+        let field = match key {
+            "stack" => &mut self.stack,
+            "layer" => &mut self.layer,
+            "env" => &mut self.env,
+            "cluster" => &mut self.cluster,
+            "mesh" => &mut self.mesh,
+            "namespace" => &mut self.namespace,
+            "kube_context" => {
+                self.kube_context = Some(value.into());
+                return true;
+            }
+            _ => return false,
+        };
+        *field = value.into();
+        true
+    }
+}
+
+/// Environment variable that overrides `key`, e.g. `AVX__STACK`.
+pub fn env_var_name(key: &str) -> String {
+    format!("AVX__{}", key.to_uppercase())
 }
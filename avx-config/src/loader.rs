@@ -0,0 +1,852 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    env_var_name, ActiveProfile, AvxConfig, AvxConfigError, ConfigScope, ConfigSource, ConfigValue,
+    ResolvedConfig,
+};
+
+/// File name searched for upward from the working directory.
+pub const PROJECT_FILE_NAME: &str = "avx.toml";
+
+/// Environment variable selecting the active profile.
+pub const PROFILE_ENV_VAR: &str = "AVX_PROFILE";
+
+/// Contents of a single config file (or any other layer): every key is
+/// optional and only the keys present override lower layers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kube_context: Option<String>,
+
+    /// Name of the profile to apply, e.g. `profile = "staging"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Named profiles, declared as `[profiles.<name>]` tables.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// `[lint]` table for `avx-cli k8s lint`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lint: Option<LintSettings>,
+}
+
+/// Rule overrides for `avx-cli k8s lint`:
+///
+/// ```toml
+/// [lint]
+/// required_labels = ["stack", "layer", "env", "team"]
+///
+/// [lint.rules]
+/// latest-tag = "error"
+/// missing-probes = "off"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintSettings {
+    /// Severity per rule id: `error`, `warning`, `note` or `off`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rules: BTreeMap<String, String>,
+    /// Labels every object must carry; `stack`, `layer` and `env` when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_labels: Option<Vec<String>>,
+}
+
+impl LintSettings {
+    /// Overlays the settings in `other` on top of `self`; rules are merged
+    /// one by one.
+    fn merge(&mut self, other: &LintSettings) {
+        self.rules
+            .extend(other.rules.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if other.required_labels.is_some() {
+            self.required_labels.clone_from(&other.required_labels);
+        }
+    }
+}
+
+/// A named set of values (`[profiles.<name>]`) switched on with
+/// `profile = "<name>"`, `AVX_PROFILE` or `--profile`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kube_context: Option<String>,
+}
+
+impl Profile {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            "stack" => self.stack.as_deref(),
+            "layer" => self.layer.as_deref(),
+            "env" => self.env.as_deref(),
+            "cluster" => self.cluster.as_deref(),
+            "mesh" => self.mesh.as_deref(),
+            "namespace" => self.namespace.as_deref(),
+            "kube_context" => self.kube_context.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Overlays the keys set in `other` on top of `self`.
+    fn merge(&mut self, other: &Profile) {
+        let fields = [
+            (&mut self.stack, &other.stack),
+            (&mut self.layer, &other.layer),
+            (&mut self.env, &other.env),
+            (&mut self.cluster, &other.cluster),
+            (&mut self.mesh, &other.mesh),
+            (&mut self.namespace, &other.namespace),
+            (&mut self.kube_context, &other.kube_context),
+        ];
+        for (field, value) in fields {
+            if value.is_some() {
+                field.clone_from(value);
+            }
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Reads and parses `path`. A missing file yields `Ok(None)`.
+    pub fn read(path: &Path) -> Result<Option<Self>, AvxConfigError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(AvxConfigError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        };
+
+        toml::from_str(&text)
+            .map(Some)
+            .map_err(|source| AvxConfigError::Parse {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            "stack" => self.stack.as_deref(),
+            "layer" => self.layer.as_deref(),
+            "env" => self.env.as_deref(),
+            "cluster" => self.cluster.as_deref(),
+            "mesh" => self.mesh.as_deref(),
+            "namespace" => self.namespace.as_deref(),
+            "kube_context" => self.kube_context.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: Option<String>) -> Result<(), AvxConfigError> {
+        let field = match key {
+            "stack" => &mut self.stack,
+            "layer" => &mut self.layer,
+            "env" => &mut self.env,
+            "cluster" => &mut self.cluster,
+            "mesh" => &mut self.mesh,
+            "namespace" => &mut self.namespace,
+            "kube_context" => &mut self.kube_context,
+            _ => return Err(AvxConfigError::UnknownKey(key.to_string())),
+        };
+        *field = value;
+        Ok(())
+    }
+
+    /// Layer made of the `AVX__*` environment variables that are set.
+    fn from_env() -> Self {
+        let mut layer = Self::default();
+        for key in AvxConfig::KEYS {
+            if let Ok(value) = env::var(env_var_name(key)) {
+                let _ = layer.set(key, Some(value));
+            }
+        }
+        layer
+    }
+}
+
+/// Resolves [`AvxConfig`] from, lowest to highest precedence: built-in
+/// defaults, the system file, the user file, the project `avx.toml`, the
+/// selected profile, `AVX__*` environment variables and CLI overrides.
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    system_path: Option<PathBuf>,
+    user_path: Option<PathBuf>,
+    cwd: Option<PathBuf>,
+    use_env: bool,
+    overrides: ConfigFile,
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        Self {
+            system_path: system_config_path(),
+            user_path: user_config_path(),
+            cwd: env::current_dir().ok(),
+            use_env: true,
+            overrides: ConfigFile::default(),
+        }
+    }
+
+    pub fn system_path(mut self, path: Option<PathBuf>) -> Self {
+        self.system_path = path;
+        self
+    }
+
+    pub fn user_path(mut self, path: Option<PathBuf>) -> Self {
+        self.user_path = path;
+        self
+    }
+
+    /// Directory the project `avx.toml` search starts from.
+    pub fn cwd(mut self, cwd: Option<PathBuf>) -> Self {
+        self.cwd = cwd;
+        self
+    }
+
+    /// Whether `AVX__*` and `AVX_PROFILE` environment variables are consulted.
+    pub fn use_env(mut self, use_env: bool) -> Self {
+        self.use_env = use_env;
+        self
+    }
+
+    /// Values passed on the command line; they win over every other layer.
+    /// `overrides.profile` selects the profile ahead of `AVX_PROFILE`.
+    pub fn overrides(mut self, overrides: ConfigFile) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Path of the project file: the nearest `avx.toml` at or above the
+    /// working directory.
+    pub fn project_path(&self) -> Option<PathBuf> {
+        let cwd = self.cwd.as_deref()?;
+        cwd.ancestors()
+            .map(|dir| dir.join(PROJECT_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    /// Path of the file backing `scope`. For [`ConfigScope::Project`] this
+    /// falls back to `avx.toml` in the working directory when none exists yet.
+    pub fn path_for(&self, scope: ConfigScope) -> Option<PathBuf> {
+        match scope {
+            ConfigScope::System => self.system_path.clone(),
+            ConfigScope::User => self.user_path.clone(),
+            ConfigScope::Project => self
+                .project_path()
+                .or_else(|| self.cwd.as_ref().map(|cwd| cwd.join(PROJECT_FILE_NAME))),
+        }
+    }
+
+    /// Every config file that exists, lowest precedence first.
+    pub fn read_files(&self) -> Result<Vec<(ConfigScope, PathBuf, ConfigFile)>, AvxConfigError> {
+        let candidates = [
+            (ConfigScope::System, self.system_path.clone()),
+            (ConfigScope::User, self.user_path.clone()),
+            (ConfigScope::Project, self.project_path()),
+        ];
+
+        let mut files = Vec::new();
+        for (scope, path) in candidates {
+            let Some(path) = path else { continue };
+            if let Some(file) = ConfigFile::read(&path)? {
+                files.push((scope, path, file));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Profiles declared across all config files. A profile declared in
+    /// several files is merged key by key, higher-precedence files winning.
+    pub fn profiles(&self) -> Result<BTreeMap<String, Profile>, AvxConfigError> {
+        let mut profiles: BTreeMap<String, Profile> = BTreeMap::new();
+        for (_, _, file) in self.read_files()? {
+            for (name, profile) in &file.profiles {
+                profiles.entry(name.clone()).or_default().merge(profile);
+            }
+        }
+        Ok(profiles)
+    }
+
+    /// `[lint]` settings merged across all config files, higher-precedence
+    /// files winning.
+    pub fn lint_settings(&self) -> Result<LintSettings, AvxConfigError> {
+        let mut settings = LintSettings::default();
+        for (_, _, file) in self.read_files()? {
+            if let Some(lint) = &file.lint {
+                settings.merge(lint);
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Kubeconfig context used to reach `cluster`: the effective
+    /// `kube_context` when `cluster` is the configured cluster (`None` meaning
+    /// the current kubeconfig context), else the `kube_context` of a profile
+    /// targeting `cluster`, else a context named after the cluster.
+    pub fn kube_context_for(&self, cluster: &str) -> Result<Option<String>, AvxConfigError> {
+        let cfg = self.load()?;
+        if cfg.cluster == cluster {
+            return Ok(cfg.kube_context);
+        }
+        let from_profile = self
+            .profiles()?
+            .into_values()
+            .filter(|p| p.cluster.as_deref() == Some(cluster))
+            .find_map(|p| p.kube_context);
+        Ok(Some(from_profile.unwrap_or_else(|| cluster.to_string())))
+    }
+
+    /// Inverse of [`kube_context_for`](Self::kube_context_for): the cluster
+    /// reached through kubeconfig `context`, named after the context when no
+    /// config or profile points at it.
+    pub fn cluster_for_context(&self, context: &str) -> Result<String, AvxConfigError> {
+        let cfg = self.load()?;
+        if cfg.kube_context.as_deref() == Some(context) {
+            return Ok(cfg.cluster);
+        }
+        let from_profile = self
+            .profiles()?
+            .into_values()
+            .filter(|p| p.kube_context.as_deref() == Some(context))
+            .find_map(|p| p.cluster);
+        Ok(from_profile.unwrap_or_else(|| context.to_string()))
+    }
+
+    /// Profile to select when targeting `cluster` instead of the configured
+    /// cluster: the first one (by name) whose `cluster` is `cluster`. `None`
+    /// when `cluster` already is the configured cluster, keeping the active
+    /// profile, or when no profile targets it.
+    pub fn profile_for_cluster(&self, cluster: &str) -> Result<Option<String>, AvxConfigError> {
+        if self.load()?.cluster == cluster {
+            return Ok(None);
+        }
+        Ok(self
+            .profiles()?
+            .into_iter()
+            .find(|(_, p)| p.cluster.as_deref() == Some(cluster))
+            .map(|(name, _)| name))
+    }
+
+    /// The profile that would be applied, without checking it is defined.
+    pub fn selected_profile(&self) -> Result<Option<ActiveProfile>, AvxConfigError> {
+        Ok(self.select_profile(&self.read_files()?))
+    }
+
+    /// Scope of the file a `profile` key has to be written to for it to take
+    /// effect: the project file when it already sets `profile` (it would
+    /// shadow the user file), else the user file.
+    pub fn profile_scope(&self) -> Result<ConfigScope, AvxConfigError> {
+        let project_selects = self
+            .read_files()?
+            .iter()
+            .any(|(scope, _, file)| *scope == ConfigScope::Project && file.profile.is_some());
+        Ok(if project_selects {
+            ConfigScope::Project
+        } else {
+            ConfigScope::User
+        })
+    }
+
+    pub fn load(&self) -> Result<AvxConfig, AvxConfigError> {
+        Ok(self.resolve()?.config)
+    }
+
+    pub fn resolve(&self) -> Result<ResolvedConfig, AvxConfigError> {
+        let defaults = AvxConfig::with_defaults();
+        let mut resolved = ResolvedConfig {
+            config: defaults.clone(),
+            sources: BTreeMap::new(),
+            chain: BTreeMap::new(),
+            profile: None,
+        };
+        resolved.apply(|key| defaults.get(key), |_| ConfigSource::Default);
+
+        let files = self.read_files()?;
+        for (scope, path, file) in &files {
+            resolved.apply(
+                |key| file.get(key),
+                |_| ConfigSource::File {
+                    scope: *scope,
+                    path: path.clone(),
+                },
+            );
+        }
+
+        let profile = self.select_profile(&files);
+        if let Some(active) = &profile {
+            let mut found = false;
+            for (scope, path, file) in &files {
+                let Some(values) = file.profiles.get(&active.name) else {
+                    continue;
+                };
+                found = true;
+                resolved.apply(
+                    |key| values.get(key),
+                    |_| ConfigSource::Profile {
+                        name: active.name.clone(),
+                        scope: *scope,
+                        path: path.clone(),
+                    },
+                );
+            }
+            if !found {
+                return Err(AvxConfigError::UnknownProfile(active.name.clone()));
+            }
+        }
+        resolved.profile = profile;
+
+        if self.use_env {
+            let env = ConfigFile::from_env();
+            resolved.apply(
+                |key| env.get(key),
+                |key| ConfigSource::Env {
+                    var: env_var_name(key),
+                },
+            );
+        }
+
+        resolved.apply(|key| self.overrides.get(key), |_| ConfigSource::Cli);
+
+        Ok(resolved)
+    }
+
+    /// `--profile`, then `AVX_PROFILE`, then the `profile` key of the
+    /// highest-precedence file that sets one.
+    fn select_profile(
+        &self,
+        files: &[(ConfigScope, PathBuf, ConfigFile)],
+    ) -> Option<ActiveProfile> {
+        if let Some(name) = &self.overrides.profile {
+            return Some(ActiveProfile {
+                name: name.clone(),
+                selected_by: ConfigSource::Cli,
+            });
+        }
+
+        if self.use_env {
+            if let Some(name) = env::var(PROFILE_ENV_VAR).ok().filter(|v| !v.is_empty()) {
+                return Some(ActiveProfile {
+                    name,
+                    selected_by: ConfigSource::Env {
+                        var: PROFILE_ENV_VAR.to_string(),
+                    },
+                });
+            }
+        }
+
+        files.iter().rev().find_map(|(scope, path, file)| {
+            file.profile.as_ref().map(|name| ActiveProfile {
+                name: name.clone(),
+                selected_by: ConfigSource::File {
+                    scope: *scope,
+                    path: path.clone(),
+                },
+            })
+        })
+    }
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResolvedConfig {
+    /// Overlays one layer: every key `get` returns a value for wins and is
+    /// recorded with `source`.
+    fn apply<'a>(
+        &mut self,
+        get: impl Fn(&str) -> Option<&'a str>,
+        source: impl Fn(&str) -> ConfigSource,
+    ) {
+        for key in AvxConfig::KEYS {
+            if let Some(value) = get(key) {
+                let source = source(key);
+                self.config.set(key, value);
+                self.sources.insert(key.to_string(), source.clone());
+                self.chain
+                    .entry(key.to_string())
+                    .or_default()
+                    .push(ConfigValue {
+                        value: value.to_string(),
+                        source,
+                    });
+            }
+        }
+    }
+}
+
+/// Sets (or, with `None`, removes) a top-level `key` in the TOML file at
+/// `path`, keeping the rest of the file, comments included, intact. The file
+/// and its parent directories are created if needed.
+pub fn write_file_key(path: &Path, key: &str, value: Option<&str>) -> Result<(), AvxConfigError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(source) => {
+            return Err(AvxConfigError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+
+    let mut doc: toml_edit::DocumentMut = text.parse().map_err(|source| AvxConfigError::Edit {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    match value {
+        Some(value) => doc[key] = toml_edit::value(value),
+        None => {
+            doc.remove(key);
+        }
+    }
+
+    let write_err = |source| AvxConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(write_err)?;
+    }
+    fs::write(path, doc.to_string()).map_err(write_err)
+}
+
+/// `/etc/avx/config.toml` (`%PROGRAMDATA%\avx\config.toml` on Windows).
+pub fn system_config_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("PROGRAMDATA").map(|dir| PathBuf::from(dir).join("avx").join("config.toml"))
+    } else {
+        Some(PathBuf::from("/etc/avx/config.toml"))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/avx/config.toml`, falling back to
+/// `~/.config/avx/config.toml` (`%APPDATA%\avx\config.toml` on Windows).
+pub fn user_config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                env::var_os("APPDATA").map(PathBuf::from)
+            } else {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+            }
+        })?;
+    Some(base.join("avx").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A loader reading only `<dir>/user.toml` and `<dir>/project/avx.toml`.
+    fn loader(dir: &TempDir) -> ConfigLoader {
+        ConfigLoader::new()
+            .system_path(None)
+            .user_path(Some(dir.path().join("user.toml")))
+            .cwd(Some(dir.path().join("project")))
+            .use_env(false)
+    }
+
+    fn write(path: PathBuf, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn profile_scope_follows_the_file_that_selects_the_profile() {
+        let dir = TempDir::new().unwrap();
+        let loader = loader(&dir);
+        write(
+            dir.path().join("user.toml"),
+            "profile = \"staging\"\n[profiles.staging]\nenv = \"staging\"\n[profiles.production]\nenv = \"prod\"\n",
+        );
+        assert_eq!(loader.profile_scope().unwrap(), ConfigScope::User);
+
+        write(
+            dir.path().join("project/avx.toml"),
+            "profile = \"staging\"\n",
+        );
+        assert_eq!(loader.profile_scope().unwrap(), ConfigScope::Project);
+
+        // Writing to the user file would be shadowed by the project file
+        let user = loader.path_for(ConfigScope::User).unwrap();
+        write_file_key(&user, "profile", Some("production")).unwrap();
+        assert_eq!(loader.selected_profile().unwrap().unwrap().name, "staging");
+
+        let project = loader.path_for(loader.profile_scope().unwrap()).unwrap();
+        write_file_key(&project, "profile", Some("production")).unwrap();
+        assert_eq!(
+            loader.selected_profile().unwrap().unwrap().name,
+            "production"
+        );
+        assert_eq!(loader.load().unwrap().env, "prod");
+    }
+
+    #[test]
+    fn profile_for_cluster_picks_the_profile_targeting_it() {
+        let dir = TempDir::new().unwrap();
+        let loader = loader(&dir);
+        write(
+            dir.path().join("user.toml"),
+            "profile = \"staging\"\n\
+             [profiles.staging]\nenv = \"staging\"\ncluster = \"staging-1\"\n\
+             [profiles.production]\nenv = \"prod\"\ncluster = \"us-east-1\"\nmesh = \"istio\"\n",
+        );
+
+        assert_eq!(loader.profile_for_cluster("staging-1").unwrap(), None);
+        assert_eq!(loader.profile_for_cluster("elsewhere").unwrap(), None);
+        let profile = loader.profile_for_cluster("us-east-1").unwrap();
+        assert_eq!(profile.as_deref(), Some("production"));
+
+        let cfg = loader
+            .clone()
+            .overrides(ConfigFile {
+                profile,
+                ..ConfigFile::default()
+            })
+            .load()
+            .unwrap();
+        assert_eq!((cfg.env.as_str(), cfg.mesh.as_str()), ("prod", "istio"));
+    }
+
+    #[test]
+    fn layers_override_each_other_in_precedence_order() {
+        let dir = TempDir::new().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("user.toml");
+        let project = dir.path().join("project/avx.toml");
+        write(
+            system.clone(),
+            "stack = \"base\"\nenv = \"dev\"\ncluster = \"lab\"\nmesh = \"linkerd\"\n",
+        );
+        write(user.clone(), "env = \"staging\"\ncluster = \"staging-1\"\n");
+        write(project.clone(), "cluster = \"us-east-1\"\n");
+
+        let resolved = loader(&dir)
+            .system_path(Some(system.clone()))
+            .overrides(ConfigFile {
+                mesh: Some("istio".to_string()),
+                ..ConfigFile::default()
+            })
+            .resolve()
+            .unwrap();
+
+        let file = |scope, path: &PathBuf| ConfigSource::File {
+            scope,
+            path: path.clone(),
+        };
+        assert_eq!(resolved.config.stack, "base");
+        assert_eq!(
+            resolved.source("stack"),
+            Some(&file(ConfigScope::System, &system))
+        );
+        assert_eq!(resolved.config.env, "staging");
+        assert_eq!(
+            resolved.source("env"),
+            Some(&file(ConfigScope::User, &user))
+        );
+        assert_eq!(resolved.config.cluster, "us-east-1");
+        assert_eq!(
+            resolved.source("cluster"),
+            Some(&file(ConfigScope::Project, &project))
+        );
+        assert_eq!(resolved.config.mesh, "istio");
+        assert_eq!(resolved.source("mesh"), Some(&ConfigSource::Cli));
+        assert_eq!(resolved.source("layer"), Some(&ConfigSource::Default));
+
+        let chain: Vec<&str> = resolved.chain["cluster"]
+            .iter()
+            .map(|entry| entry.value.as_str())
+            .collect();
+        assert_eq!(
+            chain,
+            [
+                AvxConfig::with_defaults().cluster.as_str(),
+                "lab",
+                "staging-1",
+                "us-east-1"
+            ]
+        );
+    }
+
+    #[test]
+    fn project_file_is_found_above_the_working_directory() {
+        let dir = TempDir::new().unwrap();
+        let project = dir.path().join("project/avx.toml");
+        write(project.clone(), "stack = \"checkout\"\n");
+
+        let nested = loader(&dir).cwd(Some(dir.path().join("project/services/api")));
+        assert_eq!(nested.project_path(), Some(project.clone()));
+        assert_eq!(nested.load().unwrap().stack, "checkout");
+
+        let outside = loader(&dir).cwd(Some(dir.path().join("elsewhere")));
+        assert_eq!(outside.project_path(), None);
+        assert_eq!(
+            outside.path_for(ConfigScope::Project),
+            Some(dir.path().join("elsewhere").join(PROJECT_FILE_NAME))
+        );
+        assert_eq!(
+            outside.load().unwrap().stack,
+            AvxConfig::with_defaults().stack
+        );
+    }
+
+    #[test]
+    fn profile_sits_between_files_and_overrides() {
+        let dir = TempDir::new().unwrap();
+        let user = dir.path().join("user.toml");
+        let project = dir.path().join("project/avx.toml");
+        write(
+            user.clone(),
+            "profile = \"production\"\n[profiles.production]\nenv = \"prod\"\ncluster = \"us-east-1\"\n",
+        );
+        write(
+            project.clone(),
+            "env = \"staging\"\nmesh = \"linkerd\"\n[profiles.production]\ncluster = \"us-west-2\"\n",
+        );
+
+        let resolved = loader(&dir)
+            .overrides(ConfigFile {
+                cluster: Some("eu-west-1".to_string()),
+                ..ConfigFile::default()
+            })
+            .resolve()
+            .unwrap();
+
+        let active = resolved.profile.as_ref().unwrap();
+        assert_eq!(active.name, "production");
+        assert_eq!(
+            active.selected_by,
+            ConfigSource::File {
+                scope: ConfigScope::User,
+                path: user.clone(),
+            }
+        );
+        // the profile beats the project file, and its project entry the user one
+        assert_eq!(resolved.config.env, "prod");
+        assert_eq!(
+            resolved.source("env"),
+            Some(&ConfigSource::Profile {
+                name: "production".to_string(),
+                scope: ConfigScope::User,
+                path: user,
+            })
+        );
+        assert_eq!(resolved.config.mesh, "linkerd");
+        assert_eq!(resolved.config.cluster, "eu-west-1");
+        let chain: Vec<&str> = resolved.chain["cluster"]
+            .iter()
+            .skip(1)
+            .map(|entry| entry.value.as_str())
+            .collect();
+        assert_eq!(chain, ["us-east-1", "us-west-2", "eu-west-1"]);
+        assert_eq!(
+            loader(&dir).profiles().unwrap()["production"]
+                .cluster
+                .as_deref(),
+            Some("us-west-2")
+        );
+    }
+
+    #[test]
+    fn unknown_profiles_and_broken_files_are_errors() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path().join("user.toml"), "profile = \"nowhere\"\n");
+        assert!(matches!(
+            loader(&dir).resolve(),
+            Err(AvxConfigError::UnknownProfile(name)) if name == "nowhere"
+        ));
+
+        write(dir.path().join("project/avx.toml"), "stack = [\n");
+        assert!(matches!(
+            loader(&dir).resolve(),
+            Err(AvxConfigError::Parse { path, .. }) if path.ends_with("project/avx.toml")
+        ));
+    }
+
+    #[test]
+    fn environment_wins_over_files_but_not_overrides() {
+        // the only test reading the environment; the others use `use_env(false)`
+        let dir = TempDir::new().unwrap();
+        write(
+            dir.path().join("user.toml"),
+            "profile = \"staging\"\nmesh = \"linkerd\"\nlayer = \"edge\"\n\
+             [profiles.staging]\nenv = \"staging\"\n[profiles.production]\nenv = \"prod\"\n",
+        );
+        env::set_var(env_var_name("mesh"), "istio");
+        env::set_var(env_var_name("layer"), "core");
+        env::set_var(PROFILE_ENV_VAR, "production");
+
+        let loader = loader(&dir).use_env(true).overrides(ConfigFile {
+            layer: Some("data".to_string()),
+            ..ConfigFile::default()
+        });
+        let resolved = loader.resolve();
+        let ignored = loader.clone().use_env(false).resolve();
+        env::remove_var(env_var_name("mesh"));
+        env::remove_var(env_var_name("layer"));
+        env::remove_var(PROFILE_ENV_VAR);
+
+        let resolved = resolved.unwrap();
+        assert_eq!(resolved.config.mesh, "istio");
+        assert_eq!(
+            resolved.source("mesh"),
+            Some(&ConfigSource::Env {
+                var: "AVX__MESH".to_string(),
+            })
+        );
+        assert_eq!(resolved.config.layer, "data");
+        assert_eq!(resolved.config.env, "prod");
+        assert_eq!(
+            resolved.profile.unwrap().selected_by,
+            ConfigSource::Env {
+                var: PROFILE_ENV_VAR.to_string(),
+            }
+        );
+
+        let ignored = ignored.unwrap();
+        assert_eq!(ignored.config.mesh, "linkerd");
+        assert_eq!(ignored.config.env, "staging");
+    }
+}
@@ -0,0 +1,424 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{AvxConfig, Profile};
+
+/// Deployment environment (`env`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    Dev,
+    Staging,
+    Prod,
+}
+
+impl Environment {
+    pub const ALL: [Environment; 3] = [Environment::Dev, Environment::Staging, Environment::Prod];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::Dev => "dev",
+            Environment::Staging => "staging",
+            Environment::Prod => "prod",
+        }
+    }
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|e| e.as_str() == s)
+            .ok_or_else(|| format!("expected one of {}", join(Self::ALL.map(|e| e.as_str()))))
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Service mesh (`mesh`). `default` is accepted as a legacy alias of `none`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mesh {
+    None,
+    Istio,
+    Linkerd,
+}
+
+impl Mesh {
+    pub const ALL: [Mesh; 3] = [Mesh::None, Mesh::Istio, Mesh::Linkerd];
+
+    /// Deprecated spelling of [`Mesh::None`], still accepted when parsing.
+    pub const LEGACY_NONE: &'static str = "default";
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mesh::None => "none",
+            Mesh::Istio => "istio",
+            Mesh::Linkerd => "linkerd",
+        }
+    }
+}
+
+impl FromStr for Mesh {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == Self::LEGACY_NONE {
+            return Ok(Mesh::None);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|m| m.as_str() == s)
+            .ok_or_else(|| format!("expected one of {}", join(Self::ALL.map(|m| m.as_str()))))
+    }
+}
+
+impl fmt::Display for Mesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single invalid value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationError {
+    /// Dotted key, e.g. `env` or `profiles.staging.mesh`.
+    pub key: String,
+    pub value: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {:?}: {}", self.key, self.value, self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Every invalid value found in one pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl ValidationErrors {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ValidationError> {
+        self.0.iter()
+    }
+
+    pub fn extend(&mut self, other: ValidationErrors) {
+        self.0.extend(other.0);
+    }
+
+    fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid config value(s)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl AvxConfig {
+    /// Checks every key and reports all problems at once.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        for key in Self::KEYS {
+            if let Some(value) = self.get(key) {
+                errors.0.extend(check(key, key, value));
+            }
+        }
+        errors.into_result()
+    }
+
+    /// Checks a single `key = value` pair, e.g. before writing it to a file.
+    pub fn validate_value(key: &str, value: &str) -> Result<(), ValidationError> {
+        match check(key, key, value) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Typed `env`; fails if [`validate`](Self::validate) would.
+    pub fn environment(&self) -> Result<Environment, ValidationError> {
+        self.env.parse().map_err(|message| ValidationError {
+            key: "env".into(),
+            value: self.env.clone(),
+            message,
+        })
+    }
+
+    /// Typed `mesh`; fails if [`validate`](Self::validate) would.
+    pub fn mesh_kind(&self) -> Result<Mesh, ValidationError> {
+        self.mesh.parse().map_err(|message| ValidationError {
+            key: "mesh".into(),
+            value: self.mesh.clone(),
+            message,
+        })
+    }
+}
+
+impl Profile {
+    /// Checks the keys set in the profile, reporting them as
+    /// `profiles.<name>.<key>`.
+    pub fn validate(&self, name: &str) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        for key in AvxConfig::KEYS {
+            if let Some(value) = self.get(key) {
+                let path = format!("profiles.{}.{}", name, key);
+                errors.0.extend(check(&path, key, value));
+            }
+        }
+        errors.into_result()
+    }
+}
+
+fn check(path: &str, key: &str, value: &str) -> Option<ValidationError> {
+    let result = match key {
+        "env" => value.parse::<Environment>().map(|_| ()),
+        "mesh" => value.parse::<Mesh>().map(|_| ()),
+        "stack" | "layer" | "cluster" | "namespace" => check_rfc1123_label(value),
+        _ => Ok(()),
+    };
+    result.err().map(|message| ValidationError {
+        key: path.to_string(),
+        value: value.to_string(),
+        message,
+    })
+}
+
+/// RFC 1123 DNS label: what Kubernetes accepts for namespaces and what keeps
+/// the value usable as a label value.
+pub fn check_rfc1123_label(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err("must not be empty".into());
+    }
+    if value.len() > 63 {
+        return Err("must be at most 63 characters".into());
+    }
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err("must contain only lowercase letters, digits and '-'".into());
+    }
+    if value.starts_with('-') || value.ends_with('-') {
+        return Err("must start and end with a letter or digit".into());
+    }
+    Ok(())
+}
+
+/// JSON Schema (draft 2020-12) for `avx.toml` and `~/.config/avx/config.toml`.
+pub fn json_schema() -> serde_json::Value {
+    let label = json!({
+        "type": "string",
+        "pattern": "^[a-z0-9]([-a-z0-9]*[a-z0-9])?$",
+        "maxLength": 63
+    });
+    let env_values: Vec<&str> = Environment::ALL.iter().map(|e| e.as_str()).collect();
+    let mut mesh_values: Vec<&str> = Mesh::ALL.iter().map(|m| m.as_str()).collect();
+    mesh_values.push(Mesh::LEGACY_NONE);
+
+    let values = json!({
+        "stack": { "description": "Stack name (AVX__STACK)", "allOf": [label] },
+        "layer": { "description": "Stack layer (AVX__LAYER)", "allOf": [label] },
+        "env": {
+            "description": "Target environment (AVX__ENV)",
+            "type": "string",
+            "enum": env_values
+        },
+        "cluster": { "description": "Target cluster (AVX__CLUSTER)", "allOf": [label] },
+        "mesh": {
+            "description": "Service mesh (AVX__MESH)",
+            "type": "string",
+            "enum": mesh_values,
+            "anyOf": [
+                { "enum": Mesh::ALL.map(|m| m.as_str()) },
+                {
+                    "const": Mesh::LEGACY_NONE,
+                    "description": "Deprecated alias of `none`",
+                    "deprecated": true
+                }
+            ]
+        },
+        "namespace": { "description": "Kubernetes namespace (AVX__NAMESPACE)", "allOf": [label] },
+        "kube_context": {
+            "description": "Kubeconfig context used to reach the cluster (AVX__KUBE_CONTEXT)",
+            "type": "string"
+        }
+    });
+
+    let mut properties = values.as_object().cloned().unwrap_or_default();
+    properties.insert(
+        "profile".into(),
+        json!({ "description": "Active profile (AVX_PROFILE)", "type": "string" }),
+    );
+    properties.insert(
+        "profiles".into(),
+        json!({
+            "description": "Named profiles",
+            "type": "object",
+            "additionalProperties": { "$ref": "#/$defs/profile" }
+        }),
+    );
+    properties.insert(
+        "lint".into(),
+        json!({
+            "description": "Rule overrides for `avx-cli k8s lint`",
+            "type": "object",
+            "properties": {
+                "rules": {
+                    "description": "Severity per rule id",
+                    "type": "object",
+                    "additionalProperties": {
+                        "type": "string",
+                        "enum": ["error", "warning", "note", "off"]
+                    }
+                },
+                "required_labels": {
+                    "description": "Labels every object must carry (default: stack, layer, env)",
+                    "type": "array",
+                    "items": { "type": "string" }
+                }
+            },
+            "additionalProperties": false
+        }),
+    );
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "AVX configuration",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+        "$defs": {
+            "profile": {
+                "type": "object",
+                "properties": values,
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+fn join<const N: usize>(values: [&str; N]) -> String {
+    values.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_accepts_the_legacy_mesh_alias_as_deprecated() {
+        assert_eq!("default".parse::<Mesh>(), Ok(Mesh::None));
+
+        let schema = json_schema();
+        let mesh = &schema["properties"]["mesh"];
+        let allowed: Vec<&str> = mesh["enum"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect();
+        assert_eq!(allowed, ["none", "istio", "linkerd", "default"]);
+        assert_eq!(mesh["anyOf"][1]["const"], "default");
+        assert_eq!(mesh["anyOf"][1]["deprecated"], true);
+        assert_eq!(schema["$defs"]["profile"]["properties"]["mesh"], *mesh);
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported_at_once() {
+        let cfg = AvxConfig {
+            stack: "Checkout".into(),
+            env: "qa".into(),
+            mesh: "consul".into(),
+            namespace: "-avx".into(),
+            kube_context: Some("arn:aws:eks:us-east-1:1:cluster/prod".into()),
+            ..AvxConfig::with_defaults()
+        };
+        let errors = cfg.validate().unwrap_err();
+
+        let keys: Vec<&str> = errors.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["stack", "env", "mesh", "namespace"]);
+        assert_eq!(
+            errors.0[1].to_string(),
+            "env = \"qa\": expected one of dev, staging, prod"
+        );
+        assert!(errors
+            .to_string()
+            .starts_with("4 invalid config value(s)\n  - stack"));
+        assert_eq!(cfg.environment().unwrap_err().key, "env");
+        assert_eq!(cfg.mesh_kind().unwrap_err().value, "consul");
+
+        assert_eq!(AvxConfig::with_defaults().validate(), Ok(()));
+    }
+
+    #[test]
+    fn profile_errors_name_the_profile() {
+        let profile = Profile {
+            env: Some("production".into()),
+            cluster: Some("us-east-1".into()),
+            ..Profile::default()
+        };
+        let errors = profile.validate("prod").unwrap_err();
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].key, "profiles.prod.env");
+        assert_eq!(Profile::default().validate("empty"), Ok(()));
+    }
+
+    #[test]
+    fn single_values_are_checked_by_key() {
+        assert!(AvxConfig::validate_value("mesh", "linkerd").is_ok());
+        assert!(AvxConfig::validate_value("mesh", "default").is_ok());
+        assert!(AvxConfig::validate_value("env", "prod").is_ok());
+        assert!(AvxConfig::validate_value("kube_context", "Anything/Goes").is_ok());
+        let error = AvxConfig::validate_value("cluster", "us_east_1").unwrap_err();
+        assert_eq!(
+            error.message,
+            "must contain only lowercase letters, digits and '-'"
+        );
+    }
+
+    #[test]
+    fn labels_follow_rfc_1123() {
+        for valid in ["a", "avx-core", "us-east-1", &"x".repeat(63)] {
+            assert_eq!(check_rfc1123_label(valid), Ok(()), "{}", valid);
+        }
+        for invalid in [
+            "",
+            "Avx",
+            "avx_core",
+            "-avx",
+            "avx-",
+            "avx.core",
+            &"x".repeat(64),
+        ] {
+            assert!(check_rfc1123_label(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
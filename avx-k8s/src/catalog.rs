@@ -0,0 +1,406 @@
+//! The service catalog: which services `avx-cli k8s` and the `avx_deploy`
+//! tool know about.
+//!
+//! The built-in services (gateway, api-core, events) are always present. A
+//! catalog file adds services or replaces built-ins with the same `id`:
+//!
+//! ```toml
+//! [[services]]
+//! id = "billing"
+//! port = 8095
+//! depends_on = ["api-core"]
+//! probes = { liveness = "/healthz", readiness = "/readyz" }
+//! env = { RUST_LOG = "info" }
+//! ```
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::service::ServiceDef;
+
+/// Environment variable pointing at a catalog file.
+pub const CATALOG_ENV_VAR: &str = "AVX_CATALOG";
+
+/// File names looked up from the working directory upwards, like `avx.toml`.
+pub const CATALOG_FILE_NAMES: [&str; 3] =
+    ["avx-services.toml", "avx-services.yaml", "avx-services.yml"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum CatalogError {
+    #[error("failed to read service catalog {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid service catalog {}: {message}", path.display())]
+    Parse { path: PathBuf, message: String },
+
+    #[error("service `{id}`: {message}")]
+    Invalid { id: String, message: String },
+
+    #[error("dependency cycle between services: {0}")]
+    Cycle(String),
+
+    #[error("unknown service `{id}` (available: {available})")]
+    UnknownService { id: String, available: String },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogFile {
+    #[serde(default)]
+    services: Vec<ServiceDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceCatalog {
+    services: Vec<ServiceDef>,
+    path: Option<PathBuf>,
+}
+
+impl Default for ServiceCatalog {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl ServiceCatalog {
+    /// Only the built-in services.
+    pub fn builtin() -> Self {
+        Self {
+            services: ServiceDef::builtin(),
+            path: None,
+        }
+    }
+
+    /// Built-ins merged with the services in `path` (TOML, or YAML for
+    /// `.yaml`/`.yml`).
+    pub fn load(path: &Path) -> Result<Self, CatalogError> {
+        let text = std::fs::read_to_string(path).map_err(|source| CatalogError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let is_yaml = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("yaml" | "yml")
+        );
+        let file: CatalogFile = if is_yaml {
+            serde_yaml::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        }
+        .map_err(|message| CatalogError::Parse {
+            path: path.to_path_buf(),
+            message,
+        })?;
+
+        let mut catalog = Self::builtin();
+        catalog.path = Some(path.to_path_buf());
+        for service in file.services {
+            catalog.insert(service);
+        }
+        catalog.validate()?;
+        Ok(catalog)
+    }
+
+    /// The catalog named by `AVX_CATALOG`, else the nearest
+    /// `avx-services.{toml,yaml,yml}` from `cwd` upwards, else the built-ins.
+    pub fn discover(cwd: &Path) -> Result<Self, CatalogError> {
+        if let Some(path) = std::env::var_os(CATALOG_ENV_VAR) {
+            return Self::load(Path::new(&path));
+        }
+        let found = cwd.ancestors().find_map(|dir| {
+            CATALOG_FILE_NAMES
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+        });
+        match found {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::builtin()),
+        }
+    }
+
+    /// Adds `service`, replacing any service with the same id.
+    pub fn insert(&mut self, mut service: ServiceDef) {
+        service.fill_defaults();
+        match self.services.iter_mut().find(|s| s.id == service.id) {
+            Some(existing) => *existing = service,
+            None => self.services.push(service),
+        }
+    }
+
+    /// The file the catalog was loaded from, `None` for the built-ins.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn services(&self) -> &[ServiceDef] {
+        &self.services
+    }
+
+    pub fn ids(&self) -> Vec<&str> {
+        self.services.iter().map(|s| s.id.as_str()).collect()
+    }
+
+    /// Looks a service up by id or Kubernetes name.
+    pub fn get(&self, id: &str) -> Result<&ServiceDef, CatalogError> {
+        self.services
+            .iter()
+            .find(|s| s.id == id || s.name == id)
+            .ok_or_else(|| CatalogError::UnknownService {
+                id: id.to_string(),
+                available: self.ids().join(", "),
+            })
+    }
+
+    /// Checks ids and names are unique DNS labels, ports are set and every
+    /// dependency exists.
+    pub fn validate(&self) -> Result<(), CatalogError> {
+        let invalid = |id: &str, message: String| CatalogError::Invalid {
+            id: id.to_string(),
+            message,
+        };
+        let ids: BTreeSet<&str> = self.services.iter().map(|s| s.id.as_str()).collect();
+        let mut names = BTreeSet::new();
+
+        for service in &self.services {
+            let id = service.id.as_str();
+            avx_config::check_rfc1123_label(id).map_err(|e| invalid(id, format!("id {}", e)))?;
+            avx_config::check_rfc1123_label(&service.name)
+                .map_err(|e| invalid(id, format!("name {}", e)))?;
+            if !names.insert(service.name.as_str()) {
+                return Err(invalid(
+                    id,
+                    format!("name `{}` is used by another service", service.name),
+                ));
+            }
+            if service.port == 0 || service.service_port == 0 {
+                return Err(invalid(id, "ports must be greater than 0".into()));
+            }
+            for dep in &service.depends_on {
+                if dep == id {
+                    return Err(invalid(id, "cannot depend on itself".into()));
+                }
+                if !ids.contains(dep.as_str()) {
+                    return Err(invalid(id, format!("depends on unknown service `{}`", dep)));
+                }
+            }
+        }
+        self.ordered().map(|_| ())
+    }
+
+    /// Every service, dependencies before their dependents; otherwise in
+    /// catalog order, so the result is deterministic.
+    pub fn ordered(&self) -> Result<Vec<&ServiceDef>, CatalogError> {
+        let mut ordered: Vec<&ServiceDef> = Vec::with_capacity(self.services.len());
+        let mut pending: Vec<&ServiceDef> = self.services.iter().collect();
+
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|service| {
+                service
+                    .depends_on
+                    .iter()
+                    .all(|dep| ordered.iter().any(|done| &done.id == dep))
+            });
+            match ready {
+                Some(i) => ordered.push(pending.remove(i)),
+                None => {
+                    let ids: Vec<&str> = pending.iter().map(|s| s.id.as_str()).collect();
+                    return Err(CatalogError::Cycle(ids.join(", ")));
+                }
+            }
+        }
+        Ok(ordered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn write(dir: &TempDir, name: &str, text: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn catalog(services: Vec<ServiceDef>) -> ServiceCatalog {
+        let mut catalog = ServiceCatalog::builtin();
+        for service in services {
+            catalog.insert(service);
+        }
+        catalog
+    }
+
+    fn depending(id: &str, port: u16, deps: &[&str]) -> ServiceDef {
+        ServiceDef {
+            depends_on: deps.iter().map(|d| d.to_string()).collect(),
+            ..ServiceDef::new(id, port)
+        }
+    }
+
+    #[test]
+    fn file_services_extend_and_replace_the_builtins() {
+        let dir = TempDir::new().unwrap();
+        let path = write(
+            &dir,
+            "avx-services.toml",
+            "[[services]]\nid = \"billing\"\nport = 8095\ndepends_on = [\"api-core\"]\n\
+             env = { RUST_LOG = \"info\" }\n\n\
+             [[services]]\nid = \"gateway\"\nport = 9090\nimage = \"registry.local/gw:2\"\n",
+        );
+        let catalog = ServiceCatalog::load(&path).unwrap();
+
+        assert_eq!(catalog.path(), Some(path.as_path()));
+        assert_eq!(catalog.ids(), ["gateway", "api-core", "events", "billing"]);
+        let billing = catalog.get("billing").unwrap();
+        assert_eq!(billing.name, "avx-billing");
+        assert_eq!(billing.image, "ghcr.io/avilaops/avx-billing:latest");
+        assert_eq!(billing.service_port, 80);
+        assert_eq!(billing.env["RUST_LOG"], "info");
+        let gateway = catalog.get("avx-gateway").unwrap();
+        assert_eq!(
+            (gateway.port, gateway.image.as_str()),
+            (9090, "registry.local/gw:2")
+        );
+        assert_eq!(gateway.probes, None);
+    }
+
+    #[test]
+    fn yaml_catalogs_are_read_by_extension() {
+        let dir = TempDir::new().unwrap();
+        let path = write(
+            &dir,
+            "avx-services.yaml",
+            "services:\n  - id: search\n    port: 8100\n    probes: { liveness: /live, readiness: /ready }\n",
+        );
+        let catalog = ServiceCatalog::load(&path).unwrap();
+        let search = catalog.get("search").unwrap();
+        assert_eq!(search.probes.as_ref().unwrap().readiness, "/ready");
+    }
+
+    #[test]
+    fn discover_looks_upwards_and_falls_back_to_the_builtins() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(
+            ServiceCatalog::discover(dir.path()).unwrap(),
+            ServiceCatalog::builtin()
+        );
+
+        let path = write(
+            &dir,
+            "repo/avx-services.yml",
+            "services:\n  - id: search\n    port: 8100\n",
+        );
+        let nested = dir.path().join("repo/deploy/k8s");
+        let catalog = ServiceCatalog::discover(&nested).unwrap();
+        assert_eq!(catalog.path(), Some(path.as_path()));
+        assert!(catalog.get("search").is_ok());
+    }
+
+    #[test]
+    fn broken_files_are_reported_with_their_path() {
+        let dir = TempDir::new().unwrap();
+        let path = write(
+            &dir,
+            "avx-services.toml",
+            "[[services]]\nid = \"billing\"\nport = 8095\nreplicas = 3\n",
+        );
+        let error = ServiceCatalog::load(&path).unwrap_err();
+        assert!(
+            matches!(&error, CatalogError::Parse { path: p, .. } if *p == path),
+            "{}",
+            error
+        );
+
+        let missing = dir.path().join("missing.toml");
+        assert!(matches!(
+            ServiceCatalog::load(&missing),
+            Err(CatalogError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_services_are_rejected() {
+        let cases = [
+            (
+                ServiceDef {
+                    name: "avx_billing".into(),
+                    ..ServiceDef::new("billing", 8095)
+                },
+                "name",
+            ),
+            (ServiceDef::new("billing", 0), "ports"),
+            (depending("billing", 8095, &["billing"]), "itself"),
+            (depending("billing", 8095, &["ledger"]), "`ledger`"),
+            (
+                ServiceDef {
+                    name: "avx-gateway".into(),
+                    ..ServiceDef::new("billing", 8095)
+                },
+                "used by another service",
+            ),
+        ];
+        for (service, expected) in cases {
+            let error = catalog(vec![service]).validate().unwrap_err();
+            assert!(
+                matches!(&error, CatalogError::Invalid { id, message }
+                    if id == "billing" && message.contains(expected)),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let catalog = catalog(vec![
+            depending("billing", 8095, &["ledger", "api-core"]),
+            depending("ledger", 8096, &[]),
+            depending("gateway", 8080, &["billing"]),
+        ]);
+        let ordered: Vec<&str> = catalog
+            .ordered()
+            .unwrap()
+            .iter()
+            .map(|s| s.id.as_str())
+            .collect();
+        assert_eq!(
+            ordered,
+            ["api-core", "events", "ledger", "billing", "gateway"]
+        );
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let catalog = catalog(vec![
+            depending("billing", 8095, &["ledger"]),
+            depending("ledger", 8096, &["billing"]),
+        ]);
+        let error = catalog.validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "dependency cycle between services: billing, ledger"
+        );
+    }
+
+    #[test]
+    fn unknown_services_list_the_available_ones() {
+        let error = ServiceCatalog::builtin().get("billing").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown service `billing` (available: gateway, api-core, events)"
+        );
+    }
+}
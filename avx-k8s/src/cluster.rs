@@ -0,0 +1,670 @@
+//! Kubernetes API backend: server-side apply, rollout status and rollback.
+//!
+//! Callers go through [`ClusterBackend`] so the CLI and the MCP server can be
+//! pointed at a fake implementation (or a [`KubeBackend`] built from a
+//! `kube::Client` talking to a local fake API server) instead of a real
+//! cluster. Only available with the `cluster` feature.
+
+use std::fmt;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use avx_config::{AvxConfigError, ConfigLoader};
+use k8s_openapi::api::apps::v1::{Deployment, ReplicaSet};
+use k8s_openapi::api::core::v1::{Event, Namespace, Node};
+use k8s_openapi::chrono::SecondsFormat;
+use kube::api::{Api, DynamicObject, ListParams, Patch, PatchParams, PostParams};
+use kube::config::{InferConfigError, KubeConfigOptions, Kubeconfig, KubeconfigError};
+use kube::core::GroupVersionKind;
+use kube::discovery::{self, Scope};
+use kube::{Client, Config};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::inspect::{DeploymentInfo, EventInfo, NodeInfo};
+
+/// Field manager recorded on objects applied by avx.
+pub const FIELD_MANAGER: &str = "avx-cli";
+
+/// Annotation the Deployment controller uses to number rollouts.
+const REVISION_ANNOTATION: &str = "deployment.kubernetes.io/revision";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClusterError {
+    #[error("failed to load kubeconfig context {context}")]
+    Kubeconfig {
+        context: String,
+        #[source]
+        source: KubeconfigError,
+    },
+
+    #[error(transparent)]
+    Config(#[from] AvxConfigError),
+
+    #[error("failed to read kubeconfig")]
+    ReadKubeconfig(#[source] KubeconfigError),
+
+    #[error("no kubeconfig context or in-cluster configuration found")]
+    Infer(#[source] InferConfigError),
+
+    #[error("failed to connect to the Kubernetes API")]
+    Connect(#[source] Box<kube::Error>),
+
+    #[error("Kubernetes API request failed for {object}")]
+    Api {
+        object: String,
+        #[source]
+        source: Box<kube::Error>,
+    },
+
+    #[error("{kind} {name} not found")]
+    NotFound { kind: &'static str, name: String },
+
+    #[error("object {index} has no {field}")]
+    InvalidObject { index: usize, field: &'static str },
+
+    #[error("deployment {namespace}/{name} has no revision to roll back to{}", revision.map(|r| format!(" (requested {})", r)).unwrap_or_default())]
+    NoRevision {
+        namespace: String,
+        name: String,
+        revision: Option<i64>,
+    },
+
+    #[error("rollout of {namespace}/{name} failed: {message}")]
+    RolloutFailed {
+        namespace: String,
+        name: String,
+        message: String,
+    },
+
+    #[error("timed out after {}s waiting for {namespace}/{name}: {status}", timeout.as_secs())]
+    Timeout {
+        namespace: String,
+        name: String,
+        timeout: Duration,
+        status: Box<RolloutStatus>,
+    },
+}
+
+impl ClusterError {
+    /// Whether the kubeconfig has no context with the requested name.
+    pub fn is_unknown_context(&self) -> bool {
+        matches!(
+            self,
+            ClusterError::Kubeconfig {
+                source: KubeconfigError::LoadContext(_),
+                ..
+            }
+        )
+    }
+}
+
+/// One object accepted by the API server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedObject {
+    pub kind: String,
+    pub name: String,
+    pub namespace: Option<String>,
+}
+
+impl fmt::Display for AppliedObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.namespace {
+            Some(ns) => write!(f, "{}/{} ({})", self.kind, self.name, ns),
+            None => write!(f, "{}/{}", self.kind, self.name),
+        }
+    }
+}
+
+/// Snapshot of a Deployment rollout, read from its spec and status.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RolloutStatus {
+    pub generation: i64,
+    pub observed_generation: i64,
+    /// `spec.replicas`.
+    pub replicas: i32,
+    /// `status.replicas`, old pods included.
+    pub total_replicas: i32,
+    pub updated_replicas: i32,
+    pub ready_replicas: i32,
+    pub available_replicas: i32,
+    /// Value of the `deployment.kubernetes.io/revision` annotation.
+    pub revision: Option<i64>,
+    /// Message of the `Progressing` condition once it reports
+    /// `ProgressDeadlineExceeded`.
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RolloutState {
+    Progressing,
+    Complete,
+    Failed,
+}
+
+impl RolloutStatus {
+    /// Same rules as `kubectl rollout status`.
+    pub fn state(&self) -> RolloutState {
+        if self.failure.is_some() {
+            RolloutState::Failed
+        } else if self.observed_generation >= self.generation
+            && self.updated_replicas >= self.replicas
+            && self.total_replicas <= self.updated_replicas
+            && self.available_replicas >= self.updated_replicas
+        {
+            RolloutState::Complete
+        } else {
+            RolloutState::Progressing
+        }
+    }
+}
+
+impl fmt::Display for RolloutStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(failure) = &self.failure {
+            return f.write_str(failure);
+        }
+        if self.observed_generation < self.generation {
+            return f.write_str("waiting for the deployment spec update to be observed");
+        }
+        write!(
+            f,
+            "{}/{} updated, {} ready, {} available",
+            self.updated_replicas, self.replicas, self.ready_replicas, self.available_replicas
+        )?;
+        if self.total_replicas > self.updated_replicas {
+            write!(
+                f,
+                ", {} old replicas pending termination",
+                self.total_replicas - self.updated_replicas
+            )?;
+        }
+        if let Some(revision) = self.revision {
+            write!(f, " (revision {})", revision)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of [`ClusterBackend::rollback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rollback {
+    pub from_revision: Option<i64>,
+    pub to_revision: i64,
+}
+
+#[async_trait]
+pub trait ClusterBackend: Send + Sync {
+    /// Server-side applies `objects` in order. With `dry_run` the API server
+    /// validates them without persisting anything.
+    async fn apply(
+        &self,
+        objects: &[Value],
+        dry_run: bool,
+    ) -> Result<Vec<AppliedObject>, ClusterError>;
+
+    /// Current rollout status of a Deployment.
+    async fn rollout_status(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<RolloutStatus, ClusterError>;
+
+    /// Rolls a Deployment back to `revision`, or to the revision before the
+    /// current one when `None`.
+    async fn rollback(
+        &self,
+        namespace: &str,
+        name: &str,
+        revision: Option<i64>,
+        dry_run: bool,
+    ) -> Result<Rollback, ClusterError>;
+
+    async fn nodes(&self) -> Result<Vec<NodeInfo>, ClusterError>;
+
+    async fn namespaces(&self) -> Result<Vec<String>, ClusterError>;
+
+    /// Deployments of `namespace`, or of every namespace when `None`.
+    async fn deployments(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<DeploymentInfo>, ClusterError>;
+
+    /// `type: Warning` events of `namespace`, or of every namespace when `None`.
+    async fn warning_events(&self, namespace: Option<&str>)
+        -> Result<Vec<EventInfo>, ClusterError>;
+}
+
+/// Context names of the user's kubeconfig (`KUBECONFIG` or
+/// `~/.kube/config`), in file order.
+pub fn kube_contexts() -> Result<Vec<String>, ClusterError> {
+    let kubeconfig = Kubeconfig::read().map_err(ClusterError::ReadKubeconfig)?;
+    Ok(kubeconfig.contexts.into_iter().map(|c| c.name).collect())
+}
+
+/// Polls `backend` until the Deployment has rolled out, failed, or
+/// `timeout` has elapsed.
+pub async fn wait_for_rollout(
+    backend: &dyn ClusterBackend,
+    namespace: &str,
+    name: &str,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<RolloutStatus, ClusterError> {
+    wait_for_rollout_with(backend, namespace, name, timeout, interval, |_| {}).await
+}
+
+/// [`wait_for_rollout`], calling `on_status` with every status polled,
+/// including the last one.
+pub async fn wait_for_rollout_with(
+    backend: &dyn ClusterBackend,
+    namespace: &str,
+    name: &str,
+    timeout: Duration,
+    interval: Duration,
+    mut on_status: impl FnMut(&RolloutStatus) + Send,
+) -> Result<RolloutStatus, ClusterError> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let status = backend.rollout_status(namespace, name).await?;
+        on_status(&status);
+        match status.state() {
+            RolloutState::Complete => return Ok(status),
+            RolloutState::Failed => {
+                return Err(ClusterError::RolloutFailed {
+                    namespace: namespace.to_string(),
+                    name: name.to_string(),
+                    message: status.to_string(),
+                })
+            }
+            RolloutState::Progressing if tokio::time::Instant::now() + interval > deadline => {
+                return Err(ClusterError::Timeout {
+                    namespace: namespace.to_string(),
+                    name: name.to_string(),
+                    timeout,
+                    status: Box::new(status),
+                })
+            }
+            RolloutState::Progressing => tokio::time::sleep(interval).await,
+        }
+    }
+}
+
+/// [`ClusterBackend`] backed by the Kubernetes API.
+#[derive(Clone)]
+pub struct KubeBackend {
+    client: Client,
+    field_manager: String,
+}
+
+impl KubeBackend {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            field_manager: FIELD_MANAGER.to_string(),
+        }
+    }
+
+    /// Connects through `context` of the user's kubeconfig, or the current
+    /// context (falling back to the in-cluster service account) when `None`.
+    pub async fn connect(context: Option<&str>) -> Result<Self, ClusterError> {
+        let config = match context {
+            Some(context) => {
+                let options = KubeConfigOptions {
+                    context: Some(context.to_string()),
+                    ..Default::default()
+                };
+                Config::from_kubeconfig(&options).await.map_err(|source| {
+                    ClusterError::Kubeconfig {
+                        context: context.to_string(),
+                        source,
+                    }
+                })?
+            }
+            None => Config::infer().await.map_err(ClusterError::Infer)?,
+        };
+        Client::try_from(config)
+            .map(Self::new)
+            .map_err(|e| ClusterError::Connect(Box::new(e)))
+    }
+
+    /// Connects to `cluster` through the context picked by
+    /// [`ConfigLoader::kube_context_for`].
+    pub async fn for_cluster(loader: &ConfigLoader, cluster: &str) -> Result<Self, ClusterError> {
+        let context = loader.kube_context_for(cluster)?;
+        Self::connect(context.as_deref()).await
+    }
+
+    pub fn field_manager(mut self, field_manager: impl Into<String>) -> Self {
+        self.field_manager = field_manager.into();
+        self
+    }
+
+    async fn deployment(&self, namespace: &str, name: &str) -> Result<Deployment, ClusterError> {
+        Api::<Deployment>::namespaced(self.client.clone(), namespace)
+            .get(name)
+            .await
+            .map_err(api_error("Deployment", namespace, name))
+    }
+}
+
+#[async_trait]
+impl ClusterBackend for KubeBackend {
+    async fn apply(
+        &self,
+        objects: &[Value],
+        dry_run: bool,
+    ) -> Result<Vec<AppliedObject>, ClusterError> {
+        let mut params = PatchParams::apply(&self.field_manager).force();
+        params.dry_run = dry_run;
+
+        let mut applied = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            let field = |pointer: &str, field: &'static str| {
+                object
+                    .pointer(pointer)
+                    .and_then(Value::as_str)
+                    .ok_or(ClusterError::InvalidObject { index, field })
+            };
+            let api_version = field("/apiVersion", "apiVersion")?;
+            let kind = field("/kind", "kind")?;
+            let name = field("/metadata/name", "metadata.name")?;
+            let namespace = object
+                .pointer("/metadata/namespace")
+                .and_then(Value::as_str);
+
+            let (group, version) = api_version.rsplit_once('/').unwrap_or(("", api_version));
+            let gvk = GroupVersionKind::gvk(group, version, kind);
+            let (resource, caps) = discovery::pinned_kind(&self.client, &gvk)
+                .await
+                .map_err(api_error(kind, namespace.unwrap_or_default(), name))?;
+
+            let (api, namespace) = match (caps.scope, namespace) {
+                (Scope::Cluster, _) => (
+                    Api::<DynamicObject>::all_with(self.client.clone(), &resource),
+                    None,
+                ),
+                (Scope::Namespaced, Some(ns)) => (
+                    Api::namespaced_with(self.client.clone(), ns, &resource),
+                    Some(ns.to_string()),
+                ),
+                (Scope::Namespaced, None) => (
+                    Api::default_namespaced_with(self.client.clone(), &resource),
+                    Some(self.client.default_namespace().to_string()),
+                ),
+            };
+            api.patch(name, &params, &Patch::Apply(object))
+                .await
+                .map_err(api_error(
+                    kind,
+                    namespace.as_deref().unwrap_or_default(),
+                    name,
+                ))?;
+
+            applied.push(AppliedObject {
+                kind: kind.to_string(),
+                name: name.to_string(),
+                namespace,
+            });
+        }
+        Ok(applied)
+    }
+
+    async fn rollout_status(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<RolloutStatus, ClusterError> {
+        let deployment = self.deployment(namespace, name).await?;
+        let spec = deployment.spec.unwrap_or_default();
+        let status = deployment.status.unwrap_or_default();
+        let failure = status
+            .conditions
+            .unwrap_or_default()
+            .into_iter()
+            .find(|c| {
+                c.type_ == "Progressing" && c.reason.as_deref() == Some("ProgressDeadlineExceeded")
+            })
+            .map(|c| {
+                c.message
+                    .unwrap_or_else(|| "progress deadline exceeded".into())
+            });
+
+        Ok(RolloutStatus {
+            generation: deployment.metadata.generation.unwrap_or_default(),
+            observed_generation: status.observed_generation.unwrap_or_default(),
+            replicas: spec.replicas.unwrap_or(1),
+            total_replicas: status.replicas.unwrap_or_default(),
+            updated_replicas: status.updated_replicas.unwrap_or_default(),
+            ready_replicas: status.ready_replicas.unwrap_or_default(),
+            available_replicas: status.available_replicas.unwrap_or_default(),
+            revision: revision_of(&deployment.metadata),
+            failure,
+        })
+    }
+
+    async fn rollback(
+        &self,
+        namespace: &str,
+        name: &str,
+        revision: Option<i64>,
+        dry_run: bool,
+    ) -> Result<Rollback, ClusterError> {
+        let mut deployment = self.deployment(namespace, name).await?;
+        let current = revision_of(&deployment.metadata);
+        let no_revision = || ClusterError::NoRevision {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            revision,
+        };
+
+        // ReplicaSets owned by this Deployment, as `kubectl rollout undo` does
+        let selector = deployment
+            .spec
+            .as_ref()
+            .and_then(|s| s.selector.match_labels.as_ref())
+            .map(|labels| {
+                labels
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .unwrap_or_default();
+        let uid = deployment.metadata.uid.clone();
+        let replica_sets = Api::<ReplicaSet>::namespaced(self.client.clone(), namespace)
+            .list(&ListParams::default().labels(&selector))
+            .await
+            .map_err(api_error("ReplicaSet", namespace, name))?;
+
+        let target = replica_sets
+            .items
+            .into_iter()
+            .filter(|rs| {
+                rs.metadata
+                    .owner_references
+                    .iter()
+                    .flatten()
+                    .any(|owner| Some(&owner.uid) == uid.as_ref())
+            })
+            .filter_map(|rs| revision_of(&rs.metadata).map(|r| (r, rs)))
+            .filter(|(r, _)| match revision {
+                Some(wanted) => *r == wanted,
+                None => current.is_none_or(|current| *r < current),
+            })
+            .max_by_key(|(r, _)| *r);
+        let Some((to_revision, replica_set)) = target else {
+            return Err(no_revision());
+        };
+        if Some(to_revision) == current {
+            return Err(no_revision());
+        }
+
+        let mut template = replica_set
+            .spec
+            .and_then(|s| s.template)
+            .ok_or_else(no_revision)?;
+        if let Some(labels) = template.metadata.as_mut().and_then(|m| m.labels.as_mut()) {
+            labels.remove("pod-template-hash");
+        }
+        if let Some(spec) = deployment.spec.as_mut() {
+            spec.template = template;
+        }
+
+        let params = PostParams {
+            dry_run,
+            field_manager: Some(self.field_manager.clone()),
+        };
+        Api::<Deployment>::namespaced(self.client.clone(), namespace)
+            .replace(name, &params, &deployment)
+            .await
+            .map_err(api_error("Deployment", namespace, name))?;
+
+        Ok(Rollback {
+            from_revision: current,
+            to_revision,
+        })
+    }
+
+    async fn nodes(&self) -> Result<Vec<NodeInfo>, ClusterError> {
+        let nodes = Api::<Node>::all(self.client.clone())
+            .list(&ListParams::default())
+            .await
+            .map_err(api_error("Node", "", ""))?;
+        Ok(nodes
+            .items
+            .into_iter()
+            .map(|node| {
+                let status = node.status.unwrap_or_default();
+                NodeInfo {
+                    name: node.metadata.name.unwrap_or_default(),
+                    ready: status
+                        .conditions
+                        .iter()
+                        .flatten()
+                        .any(|c| c.type_ == "Ready" && c.status == "True"),
+                    kubelet_version: status.node_info.map(|i| i.kubelet_version),
+                }
+            })
+            .collect())
+    }
+
+    async fn namespaces(&self) -> Result<Vec<String>, ClusterError> {
+        let namespaces = Api::<Namespace>::all(self.client.clone())
+            .list(&ListParams::default())
+            .await
+            .map_err(api_error("Namespace", "", ""))?;
+        Ok(namespaces
+            .items
+            .into_iter()
+            .filter_map(|ns| ns.metadata.name)
+            .collect())
+    }
+
+    async fn deployments(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<DeploymentInfo>, ClusterError> {
+        let api = match namespace {
+            Some(ns) => Api::<Deployment>::namespaced(self.client.clone(), ns),
+            None => Api::<Deployment>::all(self.client.clone()),
+        };
+        let deployments = api.list(&ListParams::default()).await.map_err(api_error(
+            "Deployment",
+            namespace.unwrap_or_default(),
+            "",
+        ))?;
+        Ok(deployments
+            .items
+            .into_iter()
+            .map(|d| {
+                let revision = revision_of(&d.metadata);
+                let spec = d.spec.unwrap_or_default();
+                let status = d.status.unwrap_or_default();
+                DeploymentInfo {
+                    namespace: d.metadata.namespace.unwrap_or_default(),
+                    name: d.metadata.name.unwrap_or_default(),
+                    replicas: spec.replicas.unwrap_or(1),
+                    ready_replicas: status.ready_replicas.unwrap_or_default(),
+                    updated_replicas: status.updated_replicas.unwrap_or_default(),
+                    available_replicas: status.available_replicas.unwrap_or_default(),
+                    images: spec
+                        .template
+                        .spec
+                        .map(|pod| pod.containers.into_iter().filter_map(|c| c.image).collect())
+                        .unwrap_or_default(),
+                    revision,
+                }
+            })
+            .collect())
+    }
+
+    async fn warning_events(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<EventInfo>, ClusterError> {
+        let api = match namespace {
+            Some(ns) => Api::<Event>::namespaced(self.client.clone(), ns),
+            None => Api::<Event>::all(self.client.clone()),
+        };
+        let events = api
+            .list(&ListParams::default().fields("type=Warning"))
+            .await
+            .map_err(api_error("Event", namespace.unwrap_or_default(), ""))?;
+        Ok(events
+            .items
+            .into_iter()
+            .map(|e| {
+                let object = &e.involved_object;
+                let last_seen = e
+                    .last_timestamp
+                    .map(|t| t.0)
+                    .or(e.event_time.map(|t| t.0))
+                    .or(e.metadata.creation_timestamp.map(|t| t.0))
+                    .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true));
+                EventInfo {
+                    namespace: e.metadata.namespace.unwrap_or_default(),
+                    object: format!(
+                        "{}/{}",
+                        object.kind.as_deref().unwrap_or_default(),
+                        object.name.as_deref().unwrap_or_default()
+                    ),
+                    reason: e.reason.unwrap_or_default(),
+                    message: e.message.unwrap_or_default(),
+                    count: e.count.unwrap_or(1),
+                    last_seen,
+                }
+            })
+            .collect())
+    }
+}
+
+fn revision_of(meta: &k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta) -> Option<i64> {
+    meta.annotations
+        .as_ref()
+        .and_then(|a| a.get(REVISION_ANNOTATION))
+        .and_then(|r| r.parse().ok())
+}
+
+fn api_error<'a>(
+    kind: &'a str,
+    namespace: &'a str,
+    name: &'a str,
+) -> impl FnOnce(kube::Error) -> ClusterError + 'a {
+    move |source| {
+        let object = if name.is_empty() {
+            format!("{} list", kind)
+        } else {
+            format!("{}/{}", kind, name)
+        };
+        ClusterError::Api {
+            object: if namespace.is_empty() {
+                object
+            } else {
+                format!("{} in {}", object, namespace)
+            },
+            source: Box::new(source),
+        }
+    }
+}
@@ -0,0 +1,443 @@
+//! Field-level comparison of rendered manifests against YAML on disk, for
+//! `avx-cli k8s diff`.
+//!
+//! Objects are matched by kind, namespace and name. Key order is ignored, and
+//! so are the fields the API server fills in (`status`, `metadata.uid`,
+//! `metadata.resourceVersion`, ...), so a `kubectl get -o yaml` snapshot can be
+//! compared as well as a previous render.
+//!
+//! An object read from a cluster (it carries `metadata.uid`, `status`, ...)
+//! also has every defaulted field (`strategy`, `dnsPolicy`,
+//! `terminationMessagePath`, ...) and whatever controllers and admission
+//! webhooks add, so it is compared like server-side apply would: only the
+//! fields the rendered object sets count. A previous render is compared
+//! field by field both ways.
+
+use std::fmt;
+use std::path::Path;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::input::load_sources;
+use crate::model::Manifest;
+use crate::ManifestError;
+
+/// `metadata` fields owned by the API server.
+const SERVER_METADATA: [&str; 7] = [
+    "uid",
+    "resourceVersion",
+    "generation",
+    "creationTimestamp",
+    "managedFields",
+    "selfLink",
+    "ownerReferences",
+];
+
+/// Annotations written by kubectl and controllers.
+const SERVER_ANNOTATIONS: [&str; 2] = [
+    "kubectl.kubernetes.io/last-applied-configuration",
+    "deployment.kubernetes.io/revision",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectKey {
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+}
+
+impl ObjectKey {
+    pub fn of(value: &Value) -> Option<Self> {
+        let metadata = value.get("metadata")?;
+        Some(Self {
+            kind: value.get("kind")?.as_str()?.to_string(),
+            namespace: metadata
+                .get("namespace")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            name: metadata.get("name")?.as_str()?.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ObjectKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.namespace {
+            Some(ns) => write!(f, "{} {}/{}", self.kind, ns, self.name),
+            None => write!(f, "{} {}", self.kind, self.name),
+        }
+    }
+}
+
+/// One field that differs; `None` means the field is absent on that side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// Dotted path, e.g. `spec.template.spec.containers[0].image`.
+    pub path: String,
+    pub existing: Option<Value>,
+    pub desired: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectDiff {
+    /// Rendered but not present in the existing manifests.
+    Added(ObjectKey),
+    /// Present in the existing manifests but no longer rendered.
+    Removed(ObjectKey),
+    Changed(ObjectKey, Vec<FieldChange>),
+}
+
+impl ObjectDiff {
+    pub fn key(&self) -> &ObjectKey {
+        match self {
+            ObjectDiff::Added(key) | ObjectDiff::Removed(key) | ObjectDiff::Changed(key, _) => key,
+        }
+    }
+}
+
+/// Reads every object from `path`, see [`load_sources`].
+pub fn load_objects(path: &Path) -> Result<Vec<Value>, ManifestError> {
+    Ok(load_sources(path)?
+        .into_iter()
+        .map(|source| source.value)
+        .collect())
+}
+
+/// Compares `desired` against `existing`, object by object. The result is
+/// sorted by object key; an empty result means no drift.
+pub fn diff_manifests(
+    desired: &[Manifest],
+    existing: &[Value],
+) -> Result<Vec<ObjectDiff>, ManifestError> {
+    let mut desired_objects = Vec::new();
+    for manifest in desired {
+        let value = serde_yaml::to_value(manifest)?;
+        if let Some(key) = ObjectKey::of(&value) {
+            desired_objects.push((key, normalize(value)));
+        }
+    }
+    let existing_objects: Vec<(ObjectKey, bool, Value)> = existing
+        .iter()
+        .filter_map(|value| {
+            let key = ObjectKey::of(value)?;
+            Some((key, from_cluster(value), normalize(value.clone())))
+        })
+        .collect();
+
+    let mut diffs = Vec::new();
+    for (key, desired_value) in &desired_objects {
+        match existing_objects.iter().find(|(k, _, _)| k == key) {
+            None => diffs.push(ObjectDiff::Added(key.clone())),
+            Some((_, subset, existing_value)) => {
+                let mut changes = Vec::new();
+                diff_values(
+                    String::new(),
+                    Some(existing_value),
+                    Some(desired_value),
+                    *subset,
+                    &mut changes,
+                );
+                if !changes.is_empty() {
+                    diffs.push(ObjectDiff::Changed(key.clone(), changes));
+                }
+            }
+        }
+    }
+    for (key, _, _) in &existing_objects {
+        if !desired_objects.iter().any(|(k, _)| k == key) {
+            diffs.push(ObjectDiff::Removed(key.clone()));
+        }
+    }
+    diffs.sort_by(|a, b| a.key().cmp(b.key()));
+    Ok(diffs)
+}
+
+/// Whether `value` was read back from the API server rather than rendered.
+fn from_cluster(value: &Value) -> bool {
+    value.get("status").is_some()
+        || value.get("metadata").is_some_and(|metadata| {
+            SERVER_METADATA
+                .iter()
+                .any(|field| metadata.get(field).is_some())
+        })
+}
+
+/// Drops server-populated fields so they never show up as drift.
+fn normalize(mut value: Value) -> Value {
+    if let Some(object) = value.as_mapping_mut() {
+        object.remove("status");
+    }
+    if let Some(metadata) = value.get_mut("metadata").and_then(Value::as_mapping_mut) {
+        for field in SERVER_METADATA {
+            metadata.remove(field);
+        }
+        if let Some(annotations) = metadata
+            .get_mut("annotations")
+            .and_then(Value::as_mapping_mut)
+        {
+            for annotation in SERVER_ANNOTATIONS {
+                annotations.remove(annotation);
+            }
+            if annotations.is_empty() {
+                metadata.remove("annotations");
+            }
+        }
+    }
+    value
+}
+
+/// With `subset`, fields and list items only `existing` has are ignored.
+fn diff_values(
+    path: String,
+    existing: Option<&Value>,
+    desired: Option<&Value>,
+    subset: bool,
+    changes: &mut Vec<FieldChange>,
+) {
+    match (existing, desired) {
+        (Some(Value::Mapping(a)), Some(Value::Mapping(b))) => {
+            diff_mappings(&path, a, b, subset, changes)
+        }
+        (Some(Value::Sequence(a)), Some(Value::Sequence(b))) => {
+            let len = if subset {
+                b.len()
+            } else {
+                a.len().max(b.len())
+            };
+            for i in 0..len {
+                diff_values(
+                    format!("{}[{}]", path, i),
+                    a.get(i),
+                    b.get(i),
+                    subset,
+                    changes,
+                );
+            }
+        }
+        (a, b) if !same_scalar(a, b) => changes.push(FieldChange {
+            path,
+            existing: a.cloned(),
+            desired: b.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_mappings(
+    path: &str,
+    existing: &Mapping,
+    desired: &Mapping,
+    subset: bool,
+    changes: &mut Vec<FieldChange>,
+) {
+    let existing_keys = existing.keys().filter(|_| !subset);
+    let mut keys: Vec<&Value> = existing_keys.chain(desired.keys()).collect();
+    keys.sort_by_key(|key| key_str(key));
+    keys.dedup();
+    for key in keys {
+        let child = if path.is_empty() {
+            key_str(key)
+        } else {
+            format!("{}.{}", path, key_str(key))
+        };
+        diff_values(child, existing.get(key), desired.get(key), subset, changes);
+    }
+}
+
+fn key_str(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
+/// Equal values, treating `1` and `"1"` alike: quantities and ports may come
+/// back from the API server in either form.
+fn same_scalar(a: Option<&Value>, b: Option<&Value>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            a == b || scalar_text(a).is_some() && scalar_text(a) == scalar_text(b)
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use avx_config::AvxConfig;
+
+    use super::*;
+    use crate::{generate_manifests, ManifestOptions, ServiceDef};
+
+    fn desired() -> Vec<Manifest> {
+        let opts = ManifestOptions::new(ServiceDef::builtin().remove(0), "avx");
+        generate_manifests(&AvxConfig::with_defaults(), &opts).unwrap()
+    }
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    /// Sets `value` at the dotted `path` of `object`, creating mappings.
+    fn set(object: &mut Value, path: &str, value: Value) {
+        let mut current = object;
+        let mut keys = path.split('.').peekable();
+        while let Some(key) = keys.next() {
+            let next = match key.split_once('[') {
+                Some((key, index)) => {
+                    let index: usize = index.trim_end_matches(']').parse().unwrap();
+                    &mut current[key][index]
+                }
+                None => {
+                    if current.get(key).is_none() {
+                        current[key] = Value::Mapping(Mapping::new());
+                    }
+                    &mut current[key]
+                }
+            };
+            if keys.peek().is_none() {
+                *next = value;
+                return;
+            }
+            current = next;
+        }
+    }
+
+    /// What `kubectl get -o yaml` returns for the rendered objects.
+    fn cluster_snapshot() -> Vec<Value> {
+        desired()
+            .iter()
+            .map(|manifest| {
+                let mut object = serde_yaml::to_value(manifest).unwrap();
+                set(&mut object, "metadata.uid", yaml("0b6f1a5e"));
+                set(&mut object, "metadata.resourceVersion", yaml("'4711'"));
+                object["metadata"]["annotations"] =
+                    yaml("{deployment.kubernetes.io/revision: '3'}");
+                match manifest.kind() {
+                    "Deployment" => {
+                        for (path, value) in [
+                            ("spec.revisionHistoryLimit", "10"),
+                            ("spec.progressDeadlineSeconds", "600"),
+                            (
+                                "spec.strategy",
+                                "{type: RollingUpdate, rollingUpdate: {maxSurge: 25%, maxUnavailable: 25%}}",
+                            ),
+                            ("spec.template.spec.dnsPolicy", "ClusterFirst"),
+                            ("spec.template.spec.restartPolicy", "Always"),
+                            ("spec.template.spec.schedulerName", "default-scheduler"),
+                            ("spec.template.spec.terminationGracePeriodSeconds", "30"),
+                            (
+                                "spec.template.spec.containers[0].terminationMessagePath",
+                                "/dev/termination-log",
+                            ),
+                            (
+                                "spec.template.spec.containers[0].terminationMessagePolicy",
+                                "File",
+                            ),
+                            ("spec.template.spec.containers[0].ports[0].protocol", "TCP"),
+                            (
+                                "spec.template.spec.containers[0].livenessProbe.httpGet.scheme",
+                                "HTTP",
+                            ),
+                            ("status", "{replicas: 2, readyReplicas: 2}"),
+                        ] {
+                            set(&mut object, path, yaml(value));
+                        }
+                    }
+                    "Service" => {
+                        for (path, value) in [
+                            ("spec.clusterIP", "10.96.12.34"),
+                            ("spec.clusterIPs", "[10.96.12.34]"),
+                            ("spec.sessionAffinity", "None"),
+                            ("spec.ports[0].protocol", "TCP"),
+                            ("status", "{loadBalancer: {}}"),
+                        ] {
+                            set(&mut object, path, yaml(value));
+                        }
+                    }
+                    _ => {}
+                }
+                object
+            })
+            .collect()
+    }
+
+    #[test]
+    fn defaulted_cluster_snapshot_has_no_drift() {
+        assert_eq!(diff_manifests(&desired(), &cluster_snapshot()).unwrap(), []);
+    }
+
+    #[test]
+    fn changed_fields_of_a_cluster_snapshot_are_drift() {
+        let mut snapshot = cluster_snapshot();
+        set(
+            &mut snapshot[0],
+            "spec.template.spec.containers[0].image",
+            yaml("ghcr.io/avilaops/avx-gateway:v0.9.0"),
+        );
+        set(&mut snapshot[0], "spec.replicas", yaml("3"));
+
+        let diffs = diff_manifests(&desired(), &snapshot).unwrap();
+        let [ObjectDiff::Changed(key, changes)] = diffs.as_slice() else {
+            panic!("unexpected diff {:?}", diffs);
+        };
+        assert_eq!(key.to_string(), "Deployment avx/avx-gateway");
+        let paths: Vec<&str> = changes.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["spec.replicas", "spec.template.spec.containers[0].image"]
+        );
+    }
+
+    #[test]
+    fn extra_fields_of_a_previous_render_are_drift() {
+        let mut previous: Vec<Value> = desired()
+            .iter()
+            .map(|m| serde_yaml::to_value(m).unwrap())
+            .collect();
+        set(&mut previous[0], "spec.revisionHistoryLimit", yaml("10"));
+
+        let diffs = diff_manifests(&desired(), &previous).unwrap();
+        let [ObjectDiff::Changed(_, changes)] = diffs.as_slice() else {
+            panic!("unexpected diff {:?}", diffs);
+        };
+        assert_eq!(changes[0].path, "spec.revisionHistoryLimit");
+        assert_eq!(changes[0].desired, None);
+    }
+
+    #[test]
+    fn objects_are_matched_by_kind_namespace_and_name() {
+        let mut existing = cluster_snapshot();
+        existing.remove(1);
+        existing.push(yaml(
+            "{apiVersion: v1, kind: ConfigMap, metadata: {name: old, namespace: avx}}",
+        ));
+
+        let diffs = diff_manifests(&desired(), &existing).unwrap();
+        let summary: Vec<String> = diffs
+            .iter()
+            .map(|diff| match diff {
+                ObjectDiff::Added(key) => format!("+ {}", key),
+                ObjectDiff::Removed(key) => format!("- {}", key),
+                ObjectDiff::Changed(key, _) => format!("~ {}", key),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            ["- ConfigMap avx/old", "+ Service avx/avx-gateway"]
+        );
+    }
+}
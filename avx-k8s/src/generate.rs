@@ -0,0 +1,1138 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use avx_config::AvxConfig;
+
+use crate::mesh::{gateway_namespace, mesh_manifests, pod_annotations};
+use crate::model::*;
+use crate::service::HealthPaths;
+use crate::{ManifestError, ServiceDef};
+
+/// Config keys passed to every container as `AVX__*` variables.
+const POD_ENV_KEYS: [&str; 5] = ["stack", "layer", "env", "cluster", "mesh"];
+
+/// Ingress controller namespace admitted by the NetworkPolicy when
+/// [`ManifestOptions::ingress_namespace`] is not set.
+pub const DEFAULT_INGRESS_NAMESPACE: &str = "ingress-nginx";
+
+/// What to render for a single service.
+#[derive(Debug, Clone)]
+pub struct ManifestOptions {
+    pub service: ServiceDef,
+    pub namespace: String,
+    /// Container image; the service's default image when `None`.
+    pub image: Option<String>,
+    /// Ignored when `hpa` is set: the autoscaler owns the replica count.
+    pub replicas: u32,
+    pub resources: ResourceSettings,
+    /// Whether to emit liveness/readiness/startup probes.
+    pub probes: bool,
+    /// Autoscaling bounds; no HorizontalPodAutoscaler when `None`.
+    pub hpa: Option<HpaSettings>,
+    pub pdb: bool,
+    /// Run the pods under a dedicated ServiceAccount.
+    pub service_account: bool,
+    /// Ship the `AVX__*` variables in a ConfigMap instead of inline `env`.
+    pub config_map: bool,
+    pub network_policy: bool,
+    /// Public host; no Ingress when `None`.
+    pub ingress: Option<String>,
+    pub ingress_class: Option<String>,
+    /// Namespace of the ingress controller the NetworkPolicy lets in when
+    /// `ingress` is set; [`DEFAULT_INGRESS_NAMESPACE`] when `None`.
+    pub ingress_namespace: Option<String>,
+}
+
+impl ManifestOptions {
+    pub fn new(service: ServiceDef, namespace: impl Into<String>) -> Self {
+        Self {
+            service,
+            namespace: namespace.into(),
+            image: None,
+            replicas: 2,
+            resources: ResourceSettings::default(),
+            probes: true,
+            hpa: None,
+            pdb: false,
+            service_account: false,
+            config_map: false,
+            network_policy: false,
+            ingress: None,
+            ingress_class: None,
+            ingress_namespace: None,
+        }
+    }
+}
+
+/// HorizontalPodAutoscaler bounds, written `min:max:cpu%` on the command
+/// line (e.g. `2:10:70%`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HpaSettings {
+    pub min_replicas: u32,
+    pub max_replicas: u32,
+    /// Target average CPU utilization, in percent of the request.
+    pub cpu_utilization: u32,
+}
+
+impl FromStr for HpaSettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "`{}` is not a valid HPA spec, expected min:max:cpu% (e.g. 2:10:70%)",
+                s
+            )
+        };
+        let parts: Vec<&str> = s.split(':').collect();
+        let [min, max, cpu] = parts[..] else {
+            return Err(invalid());
+        };
+        let number = |v: &str| v.trim().parse::<u32>().map_err(|_| invalid());
+        let hpa = HpaSettings {
+            min_replicas: number(min)?,
+            max_replicas: number(max)?,
+            cpu_utilization: number(cpu.trim_end_matches('%'))?,
+        };
+
+        if hpa.min_replicas == 0 || hpa.min_replicas > hpa.max_replicas {
+            return Err(format!(
+                "HPA needs 1 <= min <= max, got {}:{}",
+                hpa.min_replicas, hpa.max_replicas
+            ));
+        }
+        if hpa.cpu_utilization == 0 {
+            return Err("HPA CPU target must be greater than 0%".to_string());
+        }
+        Ok(hpa)
+    }
+}
+
+impl fmt::Display for HpaSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}%",
+            self.min_replicas, self.max_replicas, self.cpu_utilization
+        )
+    }
+}
+
+/// Container requests and limits, as Kubernetes quantities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceSettings {
+    pub cpu_request: String,
+    pub cpu_limit: String,
+    pub memory_request: String,
+    pub memory_limit: String,
+}
+
+impl Default for ResourceSettings {
+    fn default() -> Self {
+        Self {
+            cpu_request: "100m".into(),
+            cpu_limit: "500m".into(),
+            memory_request: "128Mi".into(),
+            memory_limit: "256Mi".into(),
+        }
+    }
+}
+
+impl ResourceSettings {
+    fn requirements(&self) -> ResourceRequirements {
+        ResourceRequirements {
+            requests: BTreeMap::from([
+                ("cpu".to_string(), self.cpu_request.clone()),
+                ("memory".to_string(), self.memory_request.clone()),
+            ]),
+            limits: BTreeMap::from([
+                ("cpu".to_string(), self.cpu_limit.clone()),
+                ("memory".to_string(), self.memory_limit.clone()),
+            ]),
+        }
+    }
+}
+
+/// Checks that `value` is a non-negative Kubernetes quantity (`250m`, `0.5`,
+/// `512Mi`, `1G`, `1e3`).
+pub fn parse_quantity(value: &str) -> Result<String, String> {
+    const SUFFIXES: [&str; 13] = [
+        "Ki", "Mi", "Gi", "Ti", "Pi", "Ei", "m", "k", "M", "G", "T", "P", "E",
+    ];
+    let valid = match SUFFIXES
+        .iter()
+        .find_map(|suffix| value.strip_suffix(suffix))
+    {
+        Some(number) => is_decimal(number.strip_prefix('+').unwrap_or(number)),
+        // no suffix: a decimal exponent may follow instead
+        None => {
+            let number = value.strip_prefix('+').unwrap_or(value);
+            match number.split_once(['e', 'E']) {
+                Some((mantissa, exponent)) => {
+                    let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+                    is_decimal(mantissa) && is_digits(exponent)
+                }
+                None => is_decimal(number),
+            }
+        }
+    };
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(format!("`{}` is not a valid Kubernetes quantity", value))
+    }
+}
+
+/// `digits`, `digits.`, `digits.digits` or `.digits`.
+fn is_decimal(number: &str) -> bool {
+    match number.split_once('.') {
+        Some((whole, fraction)) => {
+            (whole.is_empty() || is_digits(whole))
+                && (fraction.is_empty() || is_digits(fraction))
+                && !(whole.is_empty() && fraction.is_empty())
+        }
+        None => is_digits(number),
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Labels stamped on every object of a service. `mesh` is normalized, so
+/// the deprecated `default` reads `none`.
+pub fn stack_labels(cfg: &AvxConfig, name: &str) -> Result<Labels, ManifestError> {
+    Ok(BTreeMap::from([
+        ("app".to_string(), name.to_string()),
+        ("stack".to_string(), cfg.stack.clone()),
+        ("layer".to_string(), cfg.layer.clone()),
+        ("env".to_string(), cfg.env.clone()),
+        ("cluster".to_string(), cfg.cluster.clone()),
+        ("mesh".to_string(), cfg.mesh_kind()?.as_str().to_string()),
+    ]))
+}
+
+/// Builds the objects for `opts.service`: always a Deployment and a Service,
+/// plus whatever optional kinds `opts` asks for and the objects required by
+/// the configured mesh.
+pub fn generate_manifests(
+    cfg: &AvxConfig,
+    opts: &ManifestOptions,
+) -> Result<Vec<Manifest>, ManifestError> {
+    let mesh = cfg.mesh_kind()?;
+    // labels, the ConfigMap and `AVX__MESH` carry the mesh actually rendered
+    let cfg = &AvxConfig {
+        mesh: mesh.as_str().to_string(),
+        ..cfg.clone()
+    };
+    let service = &opts.service;
+    let name = service.name.as_str();
+    let labels = stack_labels(cfg, name)?;
+    let selector = Labels::from([("app".to_string(), name.to_string())]);
+    let meta = |name: &str| ObjectMeta {
+        name: name.to_string(),
+        namespace: Some(opts.namespace.clone()),
+        labels: labels.clone(),
+        ..Default::default()
+    };
+    let config_map_name = format!("{}-config", name);
+
+    let mut manifests = Vec::new();
+
+    if opts.service_account {
+        manifests.push(Manifest::ServiceAccount(ServiceAccount {
+            automount_service_account_token: Some(false),
+            ..ServiceAccount::new(meta(name))
+        }));
+    }
+
+    if opts.config_map {
+        let data = POD_ENV_KEYS
+            .iter()
+            .map(|key| {
+                (
+                    avx_config::env_var_name(key),
+                    cfg.get(key).unwrap_or_default().to_string(),
+                )
+            })
+            .collect();
+        manifests.push(Manifest::ConfigMap(ConfigMap::new(
+            meta(&config_map_name),
+            data,
+        )));
+    }
+
+    let (mut env, env_from) = if opts.config_map {
+        let source = EnvFromSource {
+            config_map_ref: Some(LocalObjectReference {
+                name: config_map_name,
+            }),
+        };
+        (Vec::new(), vec![source])
+    } else {
+        let env = POD_ENV_KEYS
+            .iter()
+            .map(|key| {
+                EnvVar::new(
+                    avx_config::env_var_name(key),
+                    cfg.get(key).unwrap_or_default(),
+                )
+            })
+            .collect();
+        (env, Vec::new())
+    };
+    env.extend(
+        service
+            .env
+            .iter()
+            .map(|(key, value)| EnvVar::new(key, value)),
+    );
+
+    let container = Container {
+        name: name.to_string(),
+        image: opts.image.clone().unwrap_or_else(|| service.image.clone()),
+        image_pull_policy: Some("IfNotPresent".into()),
+        ports: vec![ContainerPort {
+            name: None,
+            container_port: service.port,
+        }],
+        env,
+        env_from,
+        resources: Some(opts.resources.requirements()),
+        security_context: Some(SecurityContext {
+            run_as_non_root: Some(true),
+            read_only_root_filesystem: Some(true),
+            allow_privilege_escalation: Some(false),
+            privileged: Some(false),
+            capabilities: Some(Capabilities {
+                drop: vec!["ALL".into()],
+                ..Default::default()
+            }),
+        }),
+        volume_mounts: vec![VolumeMount {
+            name: "tmp".into(),
+            mount_path: "/tmp".into(),
+        }],
+        ..Default::default()
+    };
+    let container = match &service.probes {
+        Some(paths) if opts.probes => with_probes(container, service.port, paths),
+        _ => container,
+    };
+
+    manifests.push(Manifest::Deployment(Deployment::new(
+        meta(name),
+        DeploymentSpec {
+            // an applied replica count would undo the autoscaler's choice
+            replicas: opts.hpa.is_none().then_some(opts.replicas),
+            selector: LabelSelector {
+                match_labels: selector.clone(),
+            },
+            template: PodTemplateSpec {
+                metadata: PodTemplateMeta {
+                    labels: labels.clone(),
+                    annotations: pod_annotations(mesh),
+                },
+                spec: PodSpec {
+                    service_account_name: opts.service_account.then(|| name.to_string()),
+                    automount_service_account_token: opts.service_account.then_some(false),
+                    containers: vec![container],
+                    security_context: Some(PodSecurityContext {
+                        run_as_non_root: Some(true),
+                        seccomp_profile: Some(SeccompProfile {
+                            type_: "RuntimeDefault".into(),
+                        }),
+                    }),
+                    topology_spread_constraints: spread_constraints(&selector),
+                    // writable scratch space, since the root filesystem is read-only
+                    volumes: vec![Volume {
+                        name: "tmp".into(),
+                        empty_dir: Some(EmptyDirVolumeSource {}),
+                    }],
+                },
+            },
+        },
+    )));
+
+    manifests.push(Manifest::Service(Service::new(
+        meta(name),
+        ServiceSpec {
+            type_: "ClusterIP".into(),
+            selector: selector.clone(),
+            ports: vec![ServicePort {
+                name: "http".into(),
+                port: service.service_port,
+                target_port: service.port,
+            }],
+        },
+    )));
+
+    if let Some(hpa) = opts.hpa {
+        manifests.push(Manifest::HorizontalPodAutoscaler(
+            HorizontalPodAutoscaler::new(
+                meta(name),
+                HorizontalPodAutoscalerSpec {
+                    scale_target_ref: CrossVersionObjectReference {
+                        api_version: "apps/v1".into(),
+                        kind: "Deployment".into(),
+                        name: name.to_string(),
+                    },
+                    min_replicas: hpa.min_replicas,
+                    max_replicas: hpa.max_replicas,
+                    metrics: vec![MetricSpec {
+                        type_: "Resource".into(),
+                        resource: Some(ResourceMetricSource {
+                            name: "cpu".into(),
+                            target: MetricTarget {
+                                type_: "Utilization".into(),
+                                average_utilization: Some(hpa.cpu_utilization),
+                            },
+                        }),
+                    }],
+                },
+            ),
+        ));
+    }
+
+    if opts.pdb {
+        manifests.push(Manifest::PodDisruptionBudget(PodDisruptionBudget::new(
+            meta(name),
+            PodDisruptionBudgetSpec {
+                max_unavailable: Some(1),
+                selector: LabelSelector {
+                    match_labels: selector.clone(),
+                },
+                ..Default::default()
+            },
+        )));
+    }
+
+    if opts.network_policy {
+        // only pods of the same stack may reach the service, plus the ingress
+        // controller and the mesh gateway when traffic comes through them
+        let mut from = vec![NetworkPolicyPeer {
+            pod_selector: Some(LabelSelector {
+                match_labels: Labels::from([("stack".to_string(), cfg.stack.clone())]),
+            }),
+            ..Default::default()
+        }];
+        if opts.ingress.is_some() {
+            let namespace = opts
+                .ingress_namespace
+                .as_deref()
+                .unwrap_or(DEFAULT_INGRESS_NAMESPACE);
+            from.push(namespace_peer(namespace));
+        }
+        if let Some(namespace) = gateway_namespace(mesh) {
+            from.push(namespace_peer(namespace));
+        }
+        manifests.push(Manifest::NetworkPolicy(NetworkPolicy::new(
+            meta(name),
+            NetworkPolicySpec {
+                pod_selector: LabelSelector {
+                    match_labels: selector.clone(),
+                },
+                policy_types: vec!["Ingress".into()],
+                ingress: vec![NetworkPolicyIngressRule {
+                    from,
+                    ports: vec![NetworkPolicyPort {
+                        protocol: "TCP".into(),
+                        port: service.port,
+                    }],
+                }],
+            },
+        )));
+    }
+
+    if let Some(host) = &opts.ingress {
+        manifests.push(Manifest::Ingress(Ingress::new(
+            meta(name),
+            IngressSpec {
+                ingress_class_name: opts.ingress_class.clone(),
+                rules: vec![IngressRule {
+                    host: host.clone(),
+                    http: HttpIngressRuleValue {
+                        paths: vec![HttpIngressPath {
+                            path: "/".into(),
+                            path_type: "Prefix".into(),
+                            backend: IngressBackend {
+                                service: IngressServiceBackend {
+                                    name: name.to_string(),
+                                    port: ServiceBackendPort {
+                                        number: service.service_port,
+                                    },
+                                },
+                            },
+                        }],
+                    },
+                }],
+            },
+        )));
+    }
+
+    manifests.extend(mesh_manifests(
+        mesh,
+        service,
+        &opts.namespace,
+        &labels,
+        opts.ingress.is_some(),
+    ));
+
+    Ok(manifests)
+}
+
+fn with_probes(container: Container, port: u16, paths: &HealthPaths) -> Container {
+    let http = |path: &str| {
+        Some(HttpGetAction {
+            path: path.to_string(),
+            port,
+        })
+    };
+
+    Container {
+        liveness_probe: Some(Probe {
+            http_get: http(&paths.liveness),
+            period_seconds: Some(10),
+            timeout_seconds: Some(2),
+            failure_threshold: Some(3),
+            ..Default::default()
+        }),
+        readiness_probe: Some(Probe {
+            http_get: http(&paths.readiness),
+            period_seconds: Some(5),
+            timeout_seconds: Some(2),
+            failure_threshold: Some(3),
+            ..Default::default()
+        }),
+        // gives slow starters up to 5 minutes before liveness kicks in
+        startup_probe: Some(Probe {
+            http_get: http(&paths.liveness),
+            period_seconds: Some(10),
+            failure_threshold: Some(30),
+            ..Default::default()
+        }),
+        ..container
+    }
+}
+
+/// Every pod of `namespace`, matched by the label Kubernetes puts on each
+/// namespace.
+fn namespace_peer(namespace: &str) -> NetworkPolicyPeer {
+    NetworkPolicyPeer {
+        namespace_selector: Some(LabelSelector {
+            match_labels: Labels::from([(
+                "kubernetes.io/metadata.name".to_string(),
+                namespace.to_string(),
+            )]),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Spreads replicas across zones and nodes, best effort.
+fn spread_constraints(selector: &Labels) -> Vec<TopologySpreadConstraint> {
+    ["topology.kubernetes.io/zone", "kubernetes.io/hostname"]
+        .into_iter()
+        .map(|key| TopologySpreadConstraint {
+            max_skew: 1,
+            topology_key: key.to_string(),
+            when_unsatisfiable: "ScheduleAnyway".into(),
+            label_selector: LabelSelector {
+                match_labels: selector.clone(),
+            },
+        })
+        .collect()
+}
+
+/// Serializes `manifests` as a multi-document YAML stream preceded by the
+/// avx-cli header.
+pub fn to_yaml(cfg: &AvxConfig, manifests: &[Manifest]) -> Result<String, ManifestError> {
+    let mut out = format!(
+        "# Generated by avx-cli - Avila Experience Fabric\n# Stack: {} | Layer: {} | Cluster: {}\n",
+        cfg.stack, cfg.layer, cfg.cluster
+    );
+    for manifest in manifests {
+        out.push_str("---\n");
+        out.push_str(&serde_yaml::to_string(manifest)?);
+    }
+    Ok(out)
+}
+
+/// Renders the manifests for one service straight to YAML.
+pub fn generate_k8s_manifest(
+    cfg: &AvxConfig,
+    opts: &ManifestOptions,
+) -> Result<String, ManifestError> {
+    to_yaml(cfg, &generate_manifests(cfg, opts)?)
+}
+
+/// A file produced by one of the directory output modes, relative to the
+/// output directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+impl RenderedFile {
+    pub fn new(path: impl Into<PathBuf>, content: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            content: content.into(),
+        }
+    }
+}
+
+/// File name for `manifest` in a directory layout: the kind in kebab case,
+/// e.g. `horizontal-pod-autoscaler.yaml`.
+pub fn manifest_file_name(manifest: &Manifest) -> String {
+    let mut name = String::new();
+    for (i, c) in manifest.kind().chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            name.push('-');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name.push_str(".yaml");
+    name
+}
+
+/// A container image split into repository and tag or digest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageRef {
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    pub fn parse(image: &str) -> Self {
+        if let Some((repository, digest)) = image.split_once('@') {
+            return Self {
+                repository: repository.to_string(),
+                tag: None,
+                digest: Some(digest.to_string()),
+            };
+        }
+        // a ':' before the last '/' belongs to a registry port, not a tag
+        match image.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => Self {
+                repository: repository.to_string(),
+                tag: Some(tag.to_string()),
+                digest: None,
+            },
+            _ => Self {
+                repository: image.to_string(),
+                tag: None,
+                digest: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    /// Compares `actual` with `tests/golden/<name>`; `AVX_UPDATE_GOLDEN=1`
+    /// rewrites the file instead.
+    fn assert_golden(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(name);
+        if std::env::var_os("AVX_UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e));
+        assert_eq!(
+            actual,
+            expected,
+            "{} is out of date, rerun with AVX_UPDATE_GOLDEN=1 to update it",
+            path.display()
+        );
+    }
+
+    fn gateway() -> ServiceDef {
+        ServiceDef::builtin().remove(0)
+    }
+
+    #[test]
+    fn default_deployment_and_service() {
+        let cfg = AvxConfig::with_defaults();
+        let opts = ManifestOptions::new(gateway(), "avx");
+        let manifests = generate_manifests(&cfg, &opts).unwrap();
+
+        let kinds: Vec<&str> = manifests.iter().map(Manifest::kind).collect();
+        assert_eq!(kinds, ["Deployment", "Service"]);
+        assert_golden(
+            "default-gateway.yaml",
+            &generate_k8s_manifest(&cfg, &opts).unwrap(),
+        );
+    }
+
+    #[test]
+    fn other_builtin_services() {
+        let cfg = AvxConfig::with_defaults();
+        for service in ServiceDef::builtin().into_iter().skip(1) {
+            let name = format!("default-{}.yaml", service.id);
+            let opts = ManifestOptions::new(service, "avx");
+            assert_golden(&name, &generate_k8s_manifest(&cfg, &opts).unwrap());
+        }
+    }
+
+    #[test]
+    fn values_with_colons_and_quotes_stay_strings() {
+        let cfg = AvxConfig::with_defaults();
+        let mut service = gateway();
+        service.env = BTreeMap::from([
+            (
+                "DATABASE_URL".to_string(),
+                "postgres://avx:s3cret@db:5432/avx".to_string(),
+            ),
+            (
+                "GREETING".to_string(),
+                r#"say "hi" to 'everyone'"#.to_string(),
+            ),
+            ("RETRIES".to_string(), "3".to_string()),
+            (
+                "MAPPING".to_string(),
+                "key: value # not a comment".to_string(),
+            ),
+        ]);
+        let mut opts = ManifestOptions::new(service.clone(), "avx");
+        opts.image = Some("registry.local:5000/avx/gateway:1.2.3".into());
+
+        let yaml = generate_k8s_manifest(&cfg, &opts).unwrap();
+        assert_golden("quoted-values.yaml", &yaml);
+
+        let deployment: serde_yaml::Value = serde_yaml::Deserializer::from_str(&yaml)
+            .next()
+            .map(serde_yaml::Value::deserialize)
+            .unwrap()
+            .unwrap();
+        let container = &deployment["spec"]["template"]["spec"]["containers"][0];
+        assert_eq!(
+            container["image"].as_str(),
+            Some("registry.local:5000/avx/gateway:1.2.3")
+        );
+        for (name, value) in &service.env {
+            let var = container["env"]
+                .as_sequence()
+                .unwrap()
+                .iter()
+                .find(|var| var["name"].as_str() == Some(name))
+                .unwrap();
+            assert_eq!(var["value"].as_str(), Some(value.as_str()), "{}", name);
+        }
+    }
+
+    /// Namespaces (by `kubernetes.io/metadata.name`) and `stack` pod labels
+    /// the NetworkPolicy admits.
+    fn admitted(cfg: &AvxConfig, opts: &ManifestOptions) -> Vec<String> {
+        let manifests = generate_manifests(cfg, opts).unwrap();
+        let Some(Manifest::NetworkPolicy(policy)) = manifests
+            .into_iter()
+            .find(|m| matches!(m, Manifest::NetworkPolicy(_)))
+        else {
+            panic!("no NetworkPolicy");
+        };
+        policy.spec.ingress[0]
+            .from
+            .iter()
+            .map(
+                |peer| match (&peer.pod_selector, &peer.namespace_selector) {
+                    (Some(pods), None) => format!("stack={}", pods.match_labels["stack"]),
+                    (None, Some(ns)) => {
+                        format!("ns={}", ns.match_labels["kubernetes.io/metadata.name"])
+                    }
+                    _ => panic!("unexpected peer {:?}", peer),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn network_policy_admits_only_the_stack_by_default() {
+        let cfg = AvxConfig::with_defaults();
+        let mut opts = ManifestOptions::new(gateway(), "avx");
+        opts.network_policy = true;
+        assert_eq!(admitted(&cfg, &opts), ["stack=default"]);
+    }
+
+    #[test]
+    fn network_policy_admits_the_ingress_controller() {
+        let cfg = AvxConfig::with_defaults();
+        let mut opts = ManifestOptions::new(gateway(), "avx");
+        opts.network_policy = true;
+        opts.ingress = Some("gateway.avila.inc".into());
+        assert_eq!(admitted(&cfg, &opts), ["stack=default", "ns=ingress-nginx"]);
+
+        opts.ingress_namespace = Some("traefik".into());
+        assert_eq!(admitted(&cfg, &opts), ["stack=default", "ns=traefik"]);
+    }
+
+    #[test]
+    fn network_policy_admits_the_mesh_gateway() {
+        let mut cfg = AvxConfig::with_defaults();
+        let mut opts = ManifestOptions::new(gateway(), "avx");
+        opts.network_policy = true;
+
+        cfg.mesh = "istio".into();
+        assert_eq!(admitted(&cfg, &opts), ["stack=default", "ns=istio-system"]);
+        // Linkerd has no ingress gateway, only the ingress controller
+        cfg.mesh = "linkerd".into();
+        assert_eq!(admitted(&cfg, &opts), ["stack=default"]);
+        opts.ingress = Some("gateway.avila.inc".into());
+        assert_eq!(admitted(&cfg, &opts), ["stack=default", "ns=ingress-nginx"]);
+    }
+
+    /// The Deployment generated for `opts`.
+    fn deployment(cfg: &AvxConfig, opts: &ManifestOptions) -> Deployment {
+        generate_manifests(cfg, opts)
+            .unwrap()
+            .into_iter()
+            .find_map(|m| match m {
+                Manifest::Deployment(deployment) => Some(deployment),
+                _ => None,
+            })
+            .expect("no Deployment")
+    }
+
+    #[test]
+    fn probes_use_the_service_health_paths() {
+        let cfg = AvxConfig::with_defaults();
+        let api_core = ServiceDef::builtin().remove(1);
+        let opts = ManifestOptions::new(api_core, "avx");
+        let container = deployment(&cfg, &opts).spec.template.spec.containers[0].clone();
+
+        let target = |probe: &Option<Probe>| {
+            let action = probe.as_ref().unwrap().http_get.clone().unwrap();
+            (action.path, action.port)
+        };
+        assert_eq!(
+            target(&container.liveness_probe),
+            ("/health/live".to_string(), 8081)
+        );
+        assert_eq!(
+            target(&container.readiness_probe),
+            ("/health/ready".to_string(), 8081)
+        );
+        assert_eq!(
+            target(&container.startup_probe),
+            ("/health/live".to_string(), 8081)
+        );
+        let startup = container.startup_probe.unwrap();
+        assert_eq!(
+            startup.period_seconds.unwrap() * startup.failure_threshold.unwrap(),
+            300
+        );
+    }
+
+    #[test]
+    fn probes_can_be_turned_off_or_missing() {
+        let cfg = AvxConfig::with_defaults();
+        let mut opts = ManifestOptions::new(gateway(), "avx");
+        opts.probes = false;
+        let without_flag = deployment(&cfg, &opts);
+        let opts = ManifestOptions::new(ServiceDef::new("worker", 9000), "avx");
+        let without_paths = deployment(&cfg, &opts);
+
+        for deployment in [without_flag, without_paths] {
+            let container = &deployment.spec.template.spec.containers[0];
+            assert_eq!(container.liveness_probe, None);
+            assert_eq!(container.readiness_probe, None);
+            assert_eq!(container.startup_probe, None);
+        }
+    }
+
+    #[test]
+    fn pods_run_locked_down() {
+        let cfg = AvxConfig::with_defaults();
+        let spec = deployment(&cfg, &ManifestOptions::new(gateway(), "avx"))
+            .spec
+            .template
+            .spec;
+
+        let pod = spec.security_context.unwrap();
+        assert_eq!(pod.run_as_non_root, Some(true));
+        assert_eq!(pod.seccomp_profile.unwrap().type_, "RuntimeDefault");
+
+        let container = &spec.containers[0];
+        let security = container.security_context.clone().unwrap();
+        assert_eq!(security.run_as_non_root, Some(true));
+        assert_eq!(security.read_only_root_filesystem, Some(true));
+        assert_eq!(security.allow_privilege_escalation, Some(false));
+        assert_eq!(security.privileged, Some(false));
+        assert_eq!(security.capabilities.unwrap().drop, ["ALL"]);
+        // the read-only root filesystem still gets a writable /tmp
+        assert_eq!(container.volume_mounts[0].mount_path, "/tmp");
+        assert_eq!(spec.volumes[0].name, container.volume_mounts[0].name);
+        assert!(spec.volumes[0].empty_dir.is_some());
+    }
+
+    #[test]
+    fn resources_come_from_the_options() {
+        let cfg = AvxConfig::with_defaults();
+        let mut opts = ManifestOptions::new(gateway(), "avx");
+        opts.resources = ResourceSettings {
+            cpu_request: "250m".into(),
+            cpu_limit: "1".into(),
+            memory_request: "512Mi".into(),
+            memory_limit: "1Gi".into(),
+        };
+        let resources = deployment(&cfg, &opts).spec.template.spec.containers[0]
+            .resources
+            .clone()
+            .unwrap();
+
+        assert_eq!(resources.requests["cpu"], "250m");
+        assert_eq!(resources.requests["memory"], "512Mi");
+        assert_eq!(resources.limits["cpu"], "1");
+        assert_eq!(resources.limits["memory"], "1Gi");
+    }
+
+    #[test]
+    fn quantities_are_checked() {
+        for valid in [
+            "250m", "0.5", "1", "512Mi", "1G", "2Ki", ".5", "1.", "+1", "1e3", "1E-2", "1.5Gi",
+        ] {
+            assert_eq!(parse_quantity(valid).as_deref(), Ok(valid));
+        }
+        for invalid in [
+            "", "Mi", "lots", "-1", "512MB", "1 Gi", "inf", "infinity", "infMi", "NaN", ".", "1e",
+            "1e3Mi", "0x10",
+        ] {
+            assert!(parse_quantity(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn hpa_owns_the_replica_count() {
+        let cfg = AvxConfig::with_defaults();
+        let mut opts = ManifestOptions::new(gateway(), "avx");
+        opts.replicas = 3;
+        assert_eq!(deployment(&cfg, &opts).spec.replicas, Some(3));
+
+        opts.hpa = Some("2:10:70%".parse().unwrap());
+        assert_eq!(deployment(&cfg, &opts).spec.replicas, None);
+        let yaml = generate_k8s_manifest(&cfg, &opts).unwrap();
+        let deployment: serde_yaml::Value = serde_yaml::Deserializer::from_str(&yaml)
+            .next()
+            .map(serde_yaml::Value::deserialize)
+            .unwrap()
+            .unwrap();
+        assert_eq!(deployment["kind"], "Deployment");
+        assert!(deployment["spec"].get("replicas").is_none(), "{}", yaml);
+    }
+
+    /// The gateway with every optional object switched on.
+    fn everything() -> ManifestOptions {
+        let mut opts = ManifestOptions::new(gateway(), "avx");
+        opts.hpa = Some("2:10:70%".parse().unwrap());
+        opts.pdb = true;
+        opts.service_account = true;
+        opts.config_map = true;
+        opts.network_policy = true;
+        opts.ingress = Some("gateway.avila.inc".into());
+        opts.ingress_class = Some("nginx".into());
+        opts
+    }
+
+    #[test]
+    fn optional_objects_carry_the_stack_labels() {
+        let cfg = AvxConfig {
+            stack: "checkout".into(),
+            env: "prod".into(),
+            ..AvxConfig::with_defaults()
+        };
+        let manifests = generate_manifests(&cfg, &everything()).unwrap();
+
+        let kinds: Vec<&str> = manifests.iter().map(Manifest::kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                "ServiceAccount",
+                "ConfigMap",
+                "Deployment",
+                "Service",
+                "HorizontalPodAutoscaler",
+                "PodDisruptionBudget",
+                "NetworkPolicy",
+                "Ingress",
+            ]
+        );
+        let labels = stack_labels(&cfg, "avx-gateway").unwrap();
+        for manifest in &manifests {
+            let metadata = manifest.metadata();
+            assert_eq!(metadata.labels, labels, "{}", manifest.kind());
+            assert_eq!(metadata.namespace.as_deref(), Some("avx"));
+        }
+    }
+
+    #[test]
+    fn hpa_and_pdb_target_the_deployment() {
+        let cfg = AvxConfig::with_defaults();
+        let manifests = generate_manifests(&cfg, &everything()).unwrap();
+
+        let hpa = manifests
+            .iter()
+            .find_map(|m| match m {
+                Manifest::HorizontalPodAutoscaler(hpa) => Some(&hpa.spec),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(hpa.scale_target_ref.kind, "Deployment");
+        assert_eq!(hpa.scale_target_ref.name, "avx-gateway");
+        assert_eq!((hpa.min_replicas, hpa.max_replicas), (2, 10));
+        let cpu = hpa.metrics[0].resource.as_ref().unwrap();
+        assert_eq!(cpu.name, "cpu");
+        assert_eq!(cpu.target.average_utilization, Some(70));
+
+        let pdb = manifests
+            .iter()
+            .find_map(|m| match m {
+                Manifest::PodDisruptionBudget(pdb) => Some(&pdb.spec),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(pdb.max_unavailable, Some(1));
+        assert_eq!(pdb.selector.match_labels["app"], "avx-gateway");
+    }
+
+    #[test]
+    fn service_account_and_config_map_are_wired_into_the_pods() {
+        let cfg = AvxConfig::with_defaults();
+        let manifests = generate_manifests(&cfg, &everything()).unwrap();
+
+        let Manifest::ServiceAccount(account) = &manifests[0] else {
+            panic!("no ServiceAccount first");
+        };
+        assert_eq!(account.metadata.name, "avx-gateway");
+        assert_eq!(account.automount_service_account_token, Some(false));
+        let Manifest::ConfigMap(config_map) = &manifests[1] else {
+            panic!("no ConfigMap second");
+        };
+        assert_eq!(config_map.metadata.name, "avx-gateway-config");
+        let keys: Vec<&str> = config_map.data.keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            [
+                "AVX__CLUSTER",
+                "AVX__ENV",
+                "AVX__LAYER",
+                "AVX__MESH",
+                "AVX__STACK"
+            ]
+        );
+
+        let pod = deployment(&cfg, &everything()).spec.template.spec;
+        assert_eq!(pod.service_account_name.as_deref(), Some("avx-gateway"));
+        assert_eq!(pod.automount_service_account_token, Some(false));
+        let container = &pod.containers[0];
+        assert_eq!(
+            container.env_from[0].config_map_ref.as_ref().unwrap().name,
+            "avx-gateway-config"
+        );
+        assert!(container
+            .env
+            .iter()
+            .all(|var| !var.name.starts_with("AVX__")));
+    }
+
+    #[test]
+    fn ingress_routes_the_host_to_the_service() {
+        let cfg = AvxConfig::with_defaults();
+        let manifests = generate_manifests(&cfg, &everything()).unwrap();
+
+        let Some(Manifest::Ingress(ingress)) = manifests.last() else {
+            panic!("no Ingress last");
+        };
+        assert_eq!(ingress.spec.ingress_class_name.as_deref(), Some("nginx"));
+        let rule = &ingress.spec.rules[0];
+        assert_eq!(rule.host, "gateway.avila.inc");
+        let path = &rule.http.paths[0];
+        assert_eq!(
+            (path.path.as_str(), path.path_type.as_str()),
+            ("/", "Prefix")
+        );
+        assert_eq!(path.backend.service.name, "avx-gateway");
+        assert_eq!(path.backend.service.port.number, 80);
+    }
+
+    #[test]
+    fn hpa_specs_are_parsed_and_checked() {
+        let hpa: HpaSettings = "2:10:70%".parse().unwrap();
+        assert_eq!(
+            hpa,
+            HpaSettings {
+                min_replicas: 2,
+                max_replicas: 10,
+                cpu_utilization: 70,
+            }
+        );
+        assert_eq!(hpa.to_string(), "2:10:70%");
+        assert_eq!(
+            "3:3:50".parse::<HpaSettings>(),
+            Ok(HpaSettings {
+                min_replicas: 3,
+                max_replicas: 3,
+                cpu_utilization: 50,
+            })
+        );
+
+        for (spec, message) in [
+            ("0:3:50%", "HPA needs 1 <= min <= max, got 0:3"),
+            ("5:2:50%", "HPA needs 1 <= min <= max, got 5:2"),
+            ("2:5:0%", "HPA CPU target must be greater than 0%"),
+            (
+                "2:5",
+                "`2:5` is not a valid HPA spec, expected min:max:cpu% (e.g. 2:10:70%)",
+            ),
+            (
+                "a:b:c%",
+                "`a:b:c%` is not a valid HPA spec, expected min:max:cpu% (e.g. 2:10:70%)",
+            ),
+        ] {
+            assert_eq!(
+                spec.parse::<HpaSettings>(),
+                Err(message.to_string()),
+                "{}",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_mesh_alias_is_rendered_as_none() {
+        let cfg = AvxConfig {
+            mesh: "default".into(),
+            ..AvxConfig::with_defaults()
+        };
+        let mut opts = ManifestOptions::new(gateway(), "avx");
+        let inline = deployment(&cfg, &opts);
+        let var = inline.spec.template.spec.containers[0]
+            .env
+            .iter()
+            .find(|var| var.name == "AVX__MESH")
+            .unwrap();
+        assert_eq!(var.value, "none");
+        assert_eq!(inline.metadata.labels["mesh"], "none");
+        assert_eq!(inline.spec.template.metadata.labels["mesh"], "none");
+
+        opts.config_map = true;
+        let manifests = generate_manifests(&cfg, &opts).unwrap();
+        let Manifest::ConfigMap(config_map) = &manifests[0] else {
+            panic!("no ConfigMap first");
+        };
+        assert_eq!(config_map.data["AVX__MESH"], "none");
+        assert_eq!(stack_labels(&cfg, "avx-gateway").unwrap()["mesh"], "none");
+    }
+}
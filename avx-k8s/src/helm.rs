@@ -0,0 +1,264 @@
+//! `--format helm`: a chart whose templates are the generated manifests with
+//! replicas, image, resources and namespace lifted into `values.yaml`.
+//!
+//! ```text
+//! <service>/
+//! ├── Chart.yaml
+//! ├── values.yaml
+//! └── templates/
+//!     ├── deployment.yaml
+//!     └── service.yaml
+//! ```
+
+use std::path::Path;
+
+use avx_config::AvxConfig;
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::generate::{
+    generate_manifests, manifest_file_name, ImageRef, ManifestOptions, RenderedFile,
+    ResourceSettings,
+};
+use crate::ManifestError;
+
+/// Placeholders rendered into the manifests, then swapped for template
+/// expressions once serialized. They are plain YAML scalars, so serde_yaml
+/// never quotes them.
+const PLACEHOLDERS: [(&str, &str); 7] = [
+    ("__AVX_HELM_NAMESPACE__", "{{ .Release.Namespace }}"),
+    (
+        "__AVX_HELM_IMAGE__",
+        "{{ .Values.image.repository }}{{ with .Values.image.tag }}:{{ . }}{{ end }}",
+    ),
+    ("__AVX_HELM_REPLICAS__", "{{ .Values.replicaCount }}"),
+    (
+        "__AVX_HELM_CPU_REQUEST__",
+        "{{ .Values.resources.requests.cpu | quote }}",
+    ),
+    (
+        "__AVX_HELM_CPU_LIMIT__",
+        "{{ .Values.resources.limits.cpu | quote }}",
+    ),
+    (
+        "__AVX_HELM_MEMORY_REQUEST__",
+        "{{ .Values.resources.requests.memory | quote }}",
+    ),
+    (
+        "__AVX_HELM_MEMORY_LIMIT__",
+        "{{ .Values.resources.limits.memory | quote }}",
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chart {
+    pub api_version: String,
+    pub name: String,
+    pub description: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub version: String,
+    pub app_version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartValues {
+    pub replica_count: u32,
+    pub image: ImageValues,
+    pub resources: ResourceValues,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageValues {
+    pub repository: String,
+    pub tag: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResourceValues {
+    pub requests: ResourceQuantities,
+    pub limits: ResourceQuantities,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResourceQuantities {
+    pub cpu: String,
+    pub memory: String,
+}
+
+/// Renders `Chart.yaml`, `values.yaml` and one template per object for
+/// `opts.service`.
+pub fn render_helm(
+    cfg: &AvxConfig,
+    opts: &ManifestOptions,
+) -> Result<Vec<RenderedFile>, ManifestError> {
+    let service = &opts.service;
+    let image = ImageRef::parse(opts.image.as_deref().unwrap_or(&service.image));
+    let (repository, tag) = match (image.tag, image.digest) {
+        (Some(tag), _) => (image.repository, tag),
+        // digests stay on the repository; `tag` is left empty
+        (None, Some(digest)) => (format!("{}@{}", image.repository, digest), String::new()),
+        (None, None) => (image.repository, String::new()),
+    };
+
+    let chart = Chart {
+        api_version: "v2".into(),
+        name: service.name.clone(),
+        description: format!(
+            "{} - Avila Experience Fabric (stack {})",
+            service.name, cfg.stack
+        ),
+        type_: "application".into(),
+        version: "0.1.0".into(),
+        app_version: if tag.is_empty() {
+            "latest".into()
+        } else {
+            tag.clone()
+        },
+    };
+    let values = ChartValues {
+        replica_count: opts.replicas,
+        image: ImageValues { repository, tag },
+        resources: ResourceValues {
+            requests: ResourceQuantities {
+                cpu: opts.resources.cpu_request.clone(),
+                memory: opts.resources.memory_request.clone(),
+            },
+            limits: ResourceQuantities {
+                cpu: opts.resources.cpu_limit.clone(),
+                memory: opts.resources.memory_limit.clone(),
+            },
+        },
+    };
+
+    let template_opts = ManifestOptions {
+        namespace: PLACEHOLDERS[0].0.into(),
+        image: Some(PLACEHOLDERS[1].0.into()),
+        resources: ResourceSettings {
+            cpu_request: PLACEHOLDERS[3].0.into(),
+            cpu_limit: PLACEHOLDERS[4].0.into(),
+            memory_request: PLACEHOLDERS[5].0.into(),
+            memory_limit: PLACEHOLDERS[6].0.into(),
+        },
+        ..opts.clone()
+    };
+
+    let mut files = vec![
+        RenderedFile::new("Chart.yaml", serde_yaml::to_string(&chart)?),
+        RenderedFile::new("values.yaml", serde_yaml::to_string(&values)?),
+    ];
+    let templates = Path::new("templates");
+    for manifest in generate_manifests(cfg, &template_opts)? {
+        let mut value = serde_yaml::to_value(&manifest)?;
+        if manifest.kind() == "Deployment" && opts.hpa.is_none() {
+            value["spec"]["replicas"] = Value::String(PLACEHOLDERS[2].0.into());
+        }
+        let mut content = serde_yaml::to_string(&value)?;
+        for (placeholder, expr) in PLACEHOLDERS {
+            content = content.replace(placeholder, expr);
+        }
+        files.push(RenderedFile::new(
+            templates.join(manifest_file_name(&manifest)),
+            content,
+        ));
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::ServiceDef;
+
+    fn render(opts: &ManifestOptions) -> BTreeMap<String, String> {
+        render_helm(&AvxConfig::with_defaults(), opts)
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.path.to_string_lossy().replace('\\', "/"), file.content))
+            .collect()
+    }
+
+    fn gateway() -> ManifestOptions {
+        ManifestOptions::new(ServiceDef::builtin().remove(0), "avx")
+    }
+
+    #[test]
+    fn chart_has_one_template_per_object() {
+        let mut opts = gateway();
+        opts.pdb = true;
+        let files = render(&opts);
+        let paths: Vec<&str> = files.keys().map(String::as_str).collect();
+        assert_eq!(
+            paths,
+            [
+                "Chart.yaml",
+                "templates/deployment.yaml",
+                "templates/pod-disruption-budget.yaml",
+                "templates/service.yaml",
+                "values.yaml",
+            ]
+        );
+
+        let chart: serde_yaml::Value = serde_yaml::from_str(&files["Chart.yaml"]).unwrap();
+        assert_eq!(chart["apiVersion"], "v2");
+        assert_eq!(chart["name"], "avx-gateway");
+        assert_eq!(chart["appVersion"], "latest");
+    }
+
+    #[test]
+    fn values_hold_what_the_templates_reference() {
+        let mut opts = gateway();
+        opts.replicas = 4;
+        opts.image = Some("registry.local:5000/avx/gateway:1.2.3".into());
+        opts.resources.memory_limit = "1Gi".into();
+        let files = render(&opts);
+
+        let values: serde_yaml::Value = serde_yaml::from_str(&files["values.yaml"]).unwrap();
+        assert_eq!(values["replicaCount"], 4);
+        assert_eq!(
+            values["image"]["repository"],
+            "registry.local:5000/avx/gateway"
+        );
+        assert_eq!(values["image"]["tag"], "1.2.3");
+        assert_eq!(values["resources"]["requests"]["cpu"], "100m");
+        assert_eq!(values["resources"]["limits"]["memory"], "1Gi");
+        let chart: serde_yaml::Value = serde_yaml::from_str(&files["Chart.yaml"]).unwrap();
+        assert_eq!(chart["appVersion"], "1.2.3");
+
+        let deployment = &files["templates/deployment.yaml"];
+        for (placeholder, expr) in PLACEHOLDERS {
+            assert!(!deployment.contains(placeholder), "{}", deployment);
+            assert!(
+                deployment.contains(expr),
+                "{} missing:\n{}",
+                expr,
+                deployment
+            );
+        }
+        assert!(!deployment.contains("1.2.3"));
+        assert!(!deployment.contains("1Gi"));
+        let service = &files["templates/service.yaml"];
+        assert!(service.contains("namespace: {{ .Release.Namespace }}"));
+        assert!(!service.contains("namespace: avx"));
+    }
+
+    #[test]
+    fn digests_stay_on_the_repository() {
+        let mut opts = gateway();
+        opts.image = Some("ghcr.io/avilaops/avx-gateway@sha256:abc123".into());
+        let files = render(&opts);
+
+        let values: serde_yaml::Value = serde_yaml::from_str(&files["values.yaml"]).unwrap();
+        assert_eq!(
+            values["image"]["repository"],
+            "ghcr.io/avilaops/avx-gateway@sha256:abc123"
+        );
+        assert_eq!(values["image"]["tag"], "");
+    }
+}
@@ -0,0 +1,100 @@
+//! Reading Kubernetes objects from YAML files, for `k8s diff` and `k8s lint`.
+
+use std::path::{Path, PathBuf};
+
+use serde_yaml::Value;
+
+use crate::diff::ObjectKey;
+use crate::ManifestError;
+
+/// An object read from disk, with where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceObject {
+    pub path: PathBuf,
+    /// 1-based line where the object's YAML document starts.
+    pub line: usize,
+    pub value: Value,
+}
+
+/// Reads every object from `path`: a YAML file, or every `.yaml`/`.yml` file
+/// under a directory. `kind: List` documents are flattened; documents that are
+/// not Kubernetes objects (e.g. `kustomization.yaml`) are skipped.
+pub fn load_sources(path: &Path) -> Result<Vec<SourceObject>, ManifestError> {
+    let mut files = Vec::new();
+    collect_yaml_files(path, &mut files)?;
+    files.sort();
+
+    let mut objects = Vec::new();
+    for file in files {
+        let text = std::fs::read_to_string(&file).map_err(|source| ManifestError::Io {
+            path: file.clone(),
+            source,
+        })?;
+        for (line, document) in split_documents(&text) {
+            let value: Value =
+                serde_yaml::from_str(&document).map_err(|source| ManifestError::Parse {
+                    path: file.clone(),
+                    source,
+                })?;
+            push_objects(&file, line, value, &mut objects);
+        }
+    }
+    Ok(objects)
+}
+
+/// Splits a multi-document stream on `---` lines, keeping the start line of
+/// each document.
+fn split_documents(text: &str) -> Vec<(usize, String)> {
+    let mut documents = Vec::new();
+    let mut start = 1;
+    let mut current = String::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim_end() == "---" || line.starts_with("--- ") {
+            documents.push((start, std::mem::take(&mut current)));
+            start = i + 2;
+            continue;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    documents.push((start, current));
+    documents
+}
+
+fn collect_yaml_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), ManifestError> {
+    let io_err = |source| ManifestError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(path).map_err(io_err)? {
+        let entry_path = entry.map_err(io_err)?.path();
+        let is_yaml = matches!(
+            entry_path.extension().and_then(|ext| ext.to_str()),
+            Some("yaml" | "yml")
+        );
+        if entry_path.is_dir() || is_yaml {
+            collect_yaml_files(&entry_path, files)?;
+        }
+    }
+    Ok(())
+}
+
+fn push_objects(path: &Path, line: usize, value: Value, objects: &mut Vec<SourceObject>) {
+    if value.get("kind").and_then(Value::as_str) == Some("List") {
+        if let Some(Value::Sequence(items)) = value.get("items") {
+            for item in items {
+                push_objects(path, line, item.clone(), objects);
+            }
+        }
+    } else if value.get("apiVersion").is_some() && ObjectKey::of(&value).is_some() {
+        objects.push(SourceObject {
+            path: path.to_path_buf(),
+            line,
+            value,
+        });
+    }
+}
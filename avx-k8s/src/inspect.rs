@@ -0,0 +1,158 @@
+//! Read-only cluster summaries built on the [`ClusterBackend`] listing calls,
+//! served by the MCP server as `avx://cluster/*` resources. Only available
+//! with the `cluster` feature.
+
+use serde::Serialize;
+
+use crate::cluster::{ClusterBackend, ClusterError, RolloutState, RolloutStatus};
+
+/// Warning events kept in a summary, most recent first.
+pub const MAX_WARNINGS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeInfo {
+    pub name: String,
+    pub ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kubelet_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeploymentInfo {
+    pub namespace: String,
+    pub name: String,
+    /// `spec.replicas`.
+    pub replicas: i32,
+    pub ready_replicas: i32,
+    pub updated_replicas: i32,
+    pub available_replicas: i32,
+    pub images: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<i64>,
+}
+
+/// A `type: Warning` core/v1 Event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EventInfo {
+    pub namespace: String,
+    /// Involved object as `Kind/name`.
+    pub object: String,
+    pub reason: String,
+    pub message: String,
+    pub count: i32,
+    /// RFC 3339 timestamp of the last occurrence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct NodeCounts {
+    pub total: usize,
+    pub ready: usize,
+}
+
+/// `avx://cluster/<name>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClusterSummary {
+    pub nodes: NodeCounts,
+    pub namespaces: Vec<String>,
+    pub deployments: Vec<DeploymentInfo>,
+    pub warnings: Vec<EventInfo>,
+}
+
+/// `avx://cluster/<name>/namespaces/<namespace>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NamespaceSummary {
+    pub namespace: String,
+    pub deployments: Vec<DeploymentInfo>,
+    pub warnings: Vec<EventInfo>,
+}
+
+/// `avx://cluster/<name>/namespaces/<namespace>/deployments/<deployment>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeploymentDetail {
+    #[serde(flatten)]
+    pub deployment: DeploymentInfo,
+    pub state: RolloutState,
+    pub rollout: String,
+    /// Warnings about the Deployment, its ReplicaSets and its Pods.
+    pub warnings: Vec<EventInfo>,
+}
+
+pub async fn cluster_summary(backend: &dyn ClusterBackend) -> Result<ClusterSummary, ClusterError> {
+    let nodes = backend.nodes().await?;
+    Ok(ClusterSummary {
+        nodes: NodeCounts {
+            total: nodes.len(),
+            ready: nodes.iter().filter(|n| n.ready).count(),
+        },
+        namespaces: backend.namespaces().await?,
+        deployments: backend.deployments(None).await?,
+        warnings: recent(backend.warning_events(None).await?),
+    })
+}
+
+pub async fn namespace_summary(
+    backend: &dyn ClusterBackend,
+    namespace: &str,
+) -> Result<NamespaceSummary, ClusterError> {
+    if !backend.namespaces().await?.iter().any(|ns| ns == namespace) {
+        return Err(ClusterError::NotFound {
+            kind: "Namespace",
+            name: namespace.to_string(),
+        });
+    }
+    Ok(NamespaceSummary {
+        namespace: namespace.to_string(),
+        deployments: backend.deployments(Some(namespace)).await?,
+        warnings: recent(backend.warning_events(Some(namespace)).await?),
+    })
+}
+
+pub async fn deployment_detail(
+    backend: &dyn ClusterBackend,
+    namespace: &str,
+    name: &str,
+) -> Result<DeploymentDetail, ClusterError> {
+    let deployment = backend
+        .deployments(Some(namespace))
+        .await?
+        .into_iter()
+        .find(|d| d.name == name)
+        .ok_or_else(|| ClusterError::NotFound {
+            kind: "Deployment",
+            name: format!("{}/{}", namespace, name),
+        })?;
+    let status: RolloutStatus = backend.rollout_status(namespace, name).await?;
+
+    // ReplicaSets and Pods are named <deployment>-<hash>[-<suffix>]
+    let owned = |object: &str| {
+        object == format!("Deployment/{}", name)
+            || ["ReplicaSet/", "Pod/"].iter().any(|kind| {
+                object
+                    .strip_prefix(kind)
+                    .and_then(|o| o.strip_prefix(name))
+                    .is_some_and(|rest| rest.starts_with('-'))
+            })
+    };
+    let warnings = backend
+        .warning_events(Some(namespace))
+        .await?
+        .into_iter()
+        .filter(|e| owned(&e.object))
+        .collect();
+
+    Ok(DeploymentDetail {
+        deployment,
+        state: status.state(),
+        rollout: status.to_string(),
+        warnings: recent(warnings),
+    })
+}
+
+fn recent(mut events: Vec<EventInfo>) -> Vec<EventInfo> {
+    // RFC 3339 in UTC sorts chronologically as text
+    events.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+    events.truncate(MAX_WARNINGS);
+    events
+}
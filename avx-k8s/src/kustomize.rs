@@ -0,0 +1,347 @@
+//! `--format kustomize`: a base with one file per object plus an overlay for
+//! the configured environment.
+//!
+//! ```text
+//! <service>/
+//! ├── base/
+//! │   ├── kustomization.yaml
+//! │   ├── deployment.yaml
+//! │   └── service.yaml
+//! └── overlays/<env>/
+//!     ├── kustomization.yaml
+//!     └── replicas-patch.yaml
+//! ```
+//!
+//! The base uses the catalog image and default replica count and leaves out
+//! the `env` and `cluster` labels and `AVX__*` variables, so rendering
+//! another environment into the same directory keeps it byte for byte; the
+//! overlay patches in the requested replicas and image and stamps those labels
+//! and variables.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use avx_config::{env_var_name, AvxConfig};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::generate::{
+    generate_manifests, manifest_file_name, ImageRef, ManifestOptions, RenderedFile,
+};
+use crate::model::{Deployment, DeploymentSpec, Manifest, ObjectMeta};
+use crate::ManifestError;
+
+/// Config keys set per overlay rather than in the shared base.
+const OVERLAY_KEYS: [&str; 2] = ["env", "cluster"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Kustomization {
+    pub api_version: String,
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub resources: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<KustomizeLabels>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<KustomizeImage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<KustomizePatch>,
+}
+
+impl Kustomization {
+    pub fn new(resources: Vec<String>) -> Self {
+        Self {
+            api_version: "kustomize.config.k8s.io/v1beta1".into(),
+            kind: "Kustomization".into(),
+            resources,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KustomizeLabels {
+    pub pairs: BTreeMap<String, String>,
+    pub include_selectors: bool,
+    /// Also label pod templates.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub include_templates: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KustomizeImage {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KustomizePatch {
+    pub path: String,
+}
+
+/// Renders the base and the `overlays/<env>` directory for `opts.service`.
+pub fn render_kustomize(
+    cfg: &AvxConfig,
+    opts: &ManifestOptions,
+) -> Result<Vec<RenderedFile>, ManifestError> {
+    let defaults = ManifestOptions::new(opts.service.clone(), opts.namespace.clone());
+    let base_opts = ManifestOptions {
+        image: None,
+        replicas: defaults.replicas,
+        ..opts.clone()
+    };
+
+    let base = Path::new("base");
+    let mut files = Vec::new();
+    let mut resources = Vec::new();
+    for mut manifest in generate_manifests(cfg, &base_opts)? {
+        strip_overlay_keys(&mut manifest);
+        let name = manifest_file_name(&manifest);
+        files.push(RenderedFile::new(
+            base.join(&name),
+            serde_yaml::to_string(&manifest)?,
+        ));
+        resources.push(name);
+    }
+    files.push(RenderedFile::new(
+        base.join("kustomization.yaml"),
+        serde_yaml::to_string(&Kustomization::new(resources))?,
+    ));
+
+    let overlay = Path::new("overlays").join(&cfg.env);
+    let name = opts.service.name.clone();
+    let mut patches = Vec::new();
+    // with an HPA the replica count is left to the autoscaler
+    if opts.hpa.is_none() {
+        let replicas_patch = Deployment::new(
+            ObjectMeta {
+                name: name.clone(),
+                namespace: Some(opts.namespace.clone()),
+                ..Default::default()
+            },
+            DeploymentSpec {
+                replicas: Some(opts.replicas),
+                ..Default::default()
+            },
+        );
+        files.push(RenderedFile::new(
+            overlay.join("replicas-patch.yaml"),
+            replicas_patch_yaml(&replicas_patch)?,
+        ));
+        patches.push(KustomizePatch {
+            path: "replicas-patch.yaml".into(),
+        });
+    }
+
+    // the AVX__* variables the base leaves out, in the ConfigMap or the
+    // container env
+    let vars: BTreeMap<String, String> = OVERLAY_KEYS
+        .iter()
+        .map(|key| {
+            (
+                env_var_name(key),
+                cfg.get(key).unwrap_or_default().to_string(),
+            )
+        })
+        .collect();
+    let metadata = json!({ "name": name, "namespace": opts.namespace });
+    let (vars_patch, vars_path) = if opts.config_map {
+        let patch = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": format!("{}-config", name), "namespace": opts.namespace },
+            "data": vars,
+        });
+        (patch, "config-map-patch.yaml")
+    } else {
+        let env: Vec<_> = vars
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+        let patch = json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": metadata,
+            "spec": { "template": { "spec": { "containers": [{ "name": name, "env": env }] } } },
+        });
+        (patch, "env-patch.yaml")
+    };
+    files.push(RenderedFile::new(
+        overlay.join(vars_path),
+        serde_yaml::to_string(&vars_patch)?,
+    ));
+    patches.push(KustomizePatch {
+        path: vars_path.into(),
+    });
+
+    let mut kustomization = Kustomization {
+        namespace: Some(opts.namespace.clone()),
+        labels: vec![KustomizeLabels {
+            pairs: OVERLAY_KEYS
+                .iter()
+                .map(|key| {
+                    (
+                        key.to_string(),
+                        cfg.get(key).unwrap_or_default().to_string(),
+                    )
+                })
+                .collect(),
+            include_selectors: false,
+            include_templates: true,
+        }],
+        patches,
+        ..Kustomization::new(vec!["../../base".into()])
+    };
+    if let Some(image) = &opts.image {
+        let from = ImageRef::parse(&opts.service.image);
+        let to = ImageRef::parse(image);
+        kustomization.images.push(KustomizeImage {
+            name: from.repository,
+            new_name: Some(to.repository),
+            new_tag: to.tag,
+            digest: to.digest,
+        });
+    }
+    files.push(RenderedFile::new(
+        overlay.join("kustomization.yaml"),
+        serde_yaml::to_string(&kustomization)?,
+    ));
+
+    Ok(files)
+}
+
+/// Removes the [`OVERLAY_KEYS`] labels and `AVX__*` variables from a base
+/// object.
+fn strip_overlay_keys(manifest: &mut Manifest) {
+    let vars: Vec<String> = OVERLAY_KEYS.iter().map(|key| env_var_name(key)).collect();
+    for key in OVERLAY_KEYS {
+        manifest.metadata_mut().labels.remove(key);
+    }
+    match manifest {
+        Manifest::Deployment(deployment) => {
+            let template = &mut deployment.spec.template;
+            for key in OVERLAY_KEYS {
+                template.metadata.labels.remove(key);
+            }
+            for container in &mut template.spec.containers {
+                container.env.retain(|var| !vars.contains(&var.name));
+            }
+        }
+        Manifest::ConfigMap(config_map) => config_map.data.retain(|key, _| !vars.contains(key)),
+        _ => {}
+    }
+}
+
+/// The strategic-merge patch only carries `metadata.name` and
+/// `spec.replicas`; the typed Deployment would also emit an empty selector
+/// and template.
+fn replicas_patch_yaml(deployment: &Deployment) -> Result<String, ManifestError> {
+    let mut value = serde_yaml::to_value(deployment)?;
+    if let Some(spec) = value.get_mut("spec").and_then(|s| s.as_mapping_mut()) {
+        spec.retain(|key, _| key.as_str() == Some("replicas"));
+    }
+    Ok(serde_yaml::to_string(&value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ServiceDef;
+
+    fn render(env: &str, cluster: &str, config_map: bool) -> BTreeMap<String, String> {
+        let cfg = AvxConfig {
+            env: env.into(),
+            cluster: cluster.into(),
+            ..AvxConfig::with_defaults()
+        };
+        let mut opts = ManifestOptions::new(ServiceDef::builtin().remove(0), "avx");
+        opts.config_map = config_map;
+        render_kustomize(&cfg, &opts)
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.path.to_string_lossy().replace('\\', "/"), file.content))
+            .collect()
+    }
+
+    fn base(files: &BTreeMap<String, String>) -> BTreeMap<&String, &String> {
+        files
+            .iter()
+            .filter(|(path, _)| path.starts_with("base/"))
+            .collect()
+    }
+
+    #[test]
+    fn base_is_the_same_for_every_environment() {
+        for config_map in [false, true] {
+            let prod = render("prod", "us-east-1", config_map);
+            let staging = render("staging", "staging-1", config_map);
+            assert_eq!(base(&prod), base(&staging));
+
+            for content in base(&prod).values() {
+                for value in ["prod", "us-east-1", "AVX__ENV", "AVX__CLUSTER"] {
+                    assert!(!content.contains(value), "{} in base:\n{}", value, content);
+                }
+            }
+            assert!(prod.contains_key("overlays/prod/kustomization.yaml"));
+            assert!(staging.contains_key("overlays/staging/kustomization.yaml"));
+        }
+    }
+
+    #[test]
+    fn overlay_sets_the_environment_labels_and_variables() {
+        let files = render("prod", "us-east-1", false);
+        let kustomization = &files["overlays/prod/kustomization.yaml"];
+        assert!(kustomization.contains("env: prod"), "{}", kustomization);
+        assert!(kustomization.contains("cluster: us-east-1"));
+        assert!(kustomization.contains("includeTemplates: true"));
+        assert!(kustomization.contains("path: env-patch.yaml"));
+
+        let patch: serde_yaml::Value =
+            serde_yaml::from_str(&files["overlays/prod/env-patch.yaml"]).unwrap();
+        let container = &patch["spec"]["template"]["spec"]["containers"][0];
+        assert_eq!(container["name"], "avx-gateway");
+        assert_eq!(container["env"][0]["name"], "AVX__CLUSTER");
+        assert_eq!(container["env"][0]["value"], "us-east-1");
+        assert_eq!(container["env"][1]["name"], "AVX__ENV");
+        assert_eq!(container["env"][1]["value"], "prod");
+
+        let files = render("prod", "us-east-1", true);
+        let patch: serde_yaml::Value =
+            serde_yaml::from_str(&files["overlays/prod/config-map-patch.yaml"]).unwrap();
+        assert_eq!(patch["metadata"]["name"], "avx-gateway-config");
+        assert_eq!(patch["data"]["AVX__ENV"], "prod");
+        assert!(!files.contains_key("overlays/prod/env-patch.yaml"));
+    }
+
+    #[test]
+    fn hpa_leaves_the_replicas_to_the_autoscaler() {
+        let cfg = AvxConfig::with_defaults();
+        let mut opts = ManifestOptions::new(ServiceDef::builtin().remove(0), "avx");
+        opts.hpa = Some("2:10:70%".parse().unwrap());
+        let files = render_kustomize(&cfg, &opts).unwrap();
+
+        let deployment = files
+            .iter()
+            .find(|file| file.path.ends_with("base/deployment.yaml"))
+            .unwrap();
+        assert!(!deployment.content.contains("replicas:"));
+        assert!(!files
+            .iter()
+            .any(|file| file.path.ends_with("replicas-patch.yaml")));
+        let kustomization = files
+            .iter()
+            .find(|file| file.path.ends_with("overlays/dev/kustomization.yaml"))
+            .unwrap();
+        assert!(!kustomization.content.contains("replicas-patch.yaml"));
+    }
+}
@@ -0,0 +1,65 @@
+pub mod catalog;
+#[cfg(feature = "cluster")]
+pub mod cluster;
+pub mod diff;
+pub mod generate;
+pub mod helm;
+pub mod input;
+#[cfg(feature = "cluster")]
+pub mod inspect;
+pub mod kustomize;
+pub mod lint;
+pub mod mesh;
+pub mod model;
+pub mod service;
+pub mod stack;
+
+pub use catalog::{CatalogError, ServiceCatalog};
+#[cfg(feature = "cluster")]
+pub use cluster::{
+    kube_contexts, wait_for_rollout, wait_for_rollout_with, AppliedObject, ClusterBackend,
+    ClusterError, KubeBackend, Rollback, RolloutState, RolloutStatus,
+};
+pub use diff::{diff_manifests, load_objects, FieldChange, ObjectDiff, ObjectKey};
+pub use generate::{
+    generate_k8s_manifest, generate_manifests, manifest_file_name, parse_quantity, stack_labels,
+    to_yaml, HpaSettings, ImageRef, ManifestOptions, RenderedFile, ResourceSettings,
+};
+pub use helm::render_helm;
+pub use input::{load_sources, SourceObject};
+#[cfg(feature = "cluster")]
+pub use inspect::{
+    cluster_summary, deployment_detail, namespace_summary, ClusterSummary, DeploymentDetail,
+    DeploymentInfo, EventInfo, NamespaceSummary, NodeInfo,
+};
+pub use kustomize::render_kustomize;
+pub use lint::{lint, Finding, LintConfig, LintError, Severity};
+pub use model::Manifest;
+pub use service::{HealthPaths, ServiceDef};
+pub use stack::{render_stack, stack_manifests, stack_namespace, StackLayout};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("failed to read {}", path.display())]
+    Io {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid YAML in {}", path.display())]
+    Parse {
+        path: std::path::PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+
+    #[error("failed to serialize manifest")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error(transparent)]
+    Config(#[from] avx_config::ValidationError),
+
+    #[error(transparent)]
+    Catalog(#[from] CatalogError),
+}
@@ -0,0 +1,583 @@
+//! Offline policy checks for `avx-cli k8s lint`.
+//!
+//! Works on plain YAML values, so hand-written manifests can be checked as
+//! well as generated ones. Severities and the required labels come from the
+//! `[lint]` table of the avx config files.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use avx_config::{Environment, LintSettings};
+use serde::Serialize;
+use serde_json::json;
+use serde_yaml::Value;
+
+use crate::diff::ObjectKey;
+use crate::input::SourceObject;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub id: &'static str,
+    pub description: &'static str,
+    pub default_severity: Severity,
+}
+
+pub const RULES: [Rule; 5] = [
+    Rule {
+        id: "latest-tag",
+        description: "Container images must be pinned to a tag other than `latest` or a digest",
+        default_severity: Severity::Warning,
+    },
+    Rule {
+        id: "missing-probes",
+        description: "Containers should declare liveness and readiness probes",
+        default_severity: Severity::Warning,
+    },
+    Rule {
+        id: "missing-limits",
+        description: "Containers must set CPU and memory limits",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "privileged-container",
+        description: "Containers must not run privileged or allow privilege escalation",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "required-labels",
+        description:
+            "Objects must carry the stack labels (stack, layer, env by default) with valid values",
+        default_severity: Severity::Warning,
+    },
+];
+
+const DEFAULT_REQUIRED_LABELS: [&str; 3] = ["stack", "layer", "env"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum LintError {
+    #[error("unknown lint rule `{0}` in [lint.rules]")]
+    UnknownRule(String),
+
+    #[error(
+        "invalid severity `{value}` for lint rule `{rule}` (expected error, warning, note or off)"
+    )]
+    InvalidSeverity { rule: String, value: String },
+}
+
+/// Effective rule set: which rules run and at what severity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    severities: BTreeMap<&'static str, Option<Severity>>,
+    required_labels: Vec<String>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            severities: RULES
+                .iter()
+                .map(|r| (r.id, Some(r.default_severity)))
+                .collect(),
+            required_labels: DEFAULT_REQUIRED_LABELS
+                .iter()
+                .map(|l| l.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn from_settings(settings: &LintSettings) -> Result<Self, LintError> {
+        let mut config = Self::default();
+        for (id, value) in &settings.rules {
+            let rule = RULES
+                .iter()
+                .find(|r| r.id == id)
+                .ok_or_else(|| LintError::UnknownRule(id.clone()))?;
+            let severity = match value.as_str() {
+                "off" => None,
+                other => Some(other.parse().map_err(|_| LintError::InvalidSeverity {
+                    rule: id.clone(),
+                    value: value.clone(),
+                })?),
+            };
+            config.severities.insert(rule.id, severity);
+        }
+        if let Some(labels) = &settings.required_labels {
+            config.required_labels.clone_from(labels);
+        }
+        Ok(config)
+    }
+
+    /// `None` when the rule is switched off.
+    pub fn severity(&self, rule: &str) -> Option<Severity> {
+        self.severities.get(rule).copied().flatten()
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Severity::Error),
+            "warning" => Ok(Severity::Warning),
+            "note" => Ok(Severity::Note),
+            other => Err(format!("unknown severity `{}`", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// `Kind namespace/name` of the offending object.
+    pub object: String,
+    /// File and line, when the object was read from disk.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+/// Runs every enabled rule over `objects`. Findings keep the input order.
+pub fn lint(objects: &[SourceObject], config: &LintConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for source in objects {
+        let Some(key) = ObjectKey::of(&source.value) else {
+            continue;
+        };
+        let mut report = |rule: &'static str, message: String| {
+            if let Some(severity) = config.severity(rule) {
+                findings.push(Finding {
+                    rule,
+                    severity,
+                    message,
+                    object: key.to_string(),
+                    path: (!source.path.as_os_str().is_empty()).then(|| source.path.clone()),
+                    line: (source.line > 0).then_some(source.line),
+                });
+            }
+        };
+
+        check_labels(&source.value, &config.required_labels, &mut report);
+        for (container, is_init) in containers(&source.value) {
+            check_container(container, is_init, &mut report);
+        }
+    }
+    findings
+}
+
+fn check_labels(
+    object: &Value,
+    required: &[String],
+    report: &mut impl FnMut(&'static str, String),
+) {
+    let labels = object.get("metadata").and_then(|m| m.get("labels"));
+    for label in required {
+        match labels
+            .and_then(|l| l.get(label.as_str()))
+            .and_then(Value::as_str)
+        {
+            None => report("required-labels", format!("missing label `{}`", label)),
+            Some(value) if label == "env" && value.parse::<Environment>().is_err() => report(
+                "required-labels",
+                format!("label `env={}` is not a known environment", value),
+            ),
+            Some(value) => {
+                if let Err(e) = avx_config::check_rfc1123_label(value) {
+                    report(
+                        "required-labels",
+                        format!("label `{}={}` {}", label, value, e),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The pod spec of workloads (Pod, Deployment, StatefulSet, DaemonSet,
+/// ReplicaSet, Job, CronJob).
+fn pod_spec(object: &Value) -> Option<&Value> {
+    let spec = object.get("spec")?;
+    match object.get("kind")?.as_str()? {
+        "Pod" => Some(spec),
+        "CronJob" => spec
+            .get("jobTemplate")?
+            .get("spec")?
+            .get("template")?
+            .get("spec"),
+        "Deployment" | "StatefulSet" | "DaemonSet" | "ReplicaSet" | "Job" => {
+            spec.get("template")?.get("spec")
+        }
+        _ => None,
+    }
+}
+
+/// Every container of a workload, flagged `true` for init containers.
+fn containers(object: &Value) -> Vec<(&Value, bool)> {
+    let Some(spec) = pod_spec(object) else {
+        return Vec::new();
+    };
+    [("containers", false), ("initContainers", true)]
+        .into_iter()
+        .filter_map(|(field, is_init)| {
+            let list = spec.get(field).and_then(Value::as_sequence)?;
+            Some(list.iter().map(move |c| (c, is_init)))
+        })
+        .flatten()
+        .collect()
+}
+
+fn check_container(
+    container: &Value,
+    is_init: bool,
+    report: &mut impl FnMut(&'static str, String),
+) {
+    let name = container.get("name").and_then(Value::as_str).unwrap_or("?");
+
+    let image = container
+        .get("image")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    if is_unpinned(image) {
+        report(
+            "latest-tag",
+            format!("container `{}` uses unpinned image `{}`", name, image),
+        );
+    }
+
+    // init containers run to completion and take no probes
+    for probe in ["livenessProbe", "readinessProbe"] {
+        if container.get(probe).is_none() && !is_init {
+            report(
+                "missing-probes",
+                format!("container `{}` has no {}", name, probe),
+            );
+        }
+    }
+
+    let limits = container.get("resources").and_then(|r| r.get("limits"));
+    for resource in ["cpu", "memory"] {
+        if limits.and_then(|l| l.get(resource)).is_none() {
+            report(
+                "missing-limits",
+                format!("container `{}` has no {} limit", name, resource),
+            );
+        }
+    }
+
+    let security = container.get("securityContext");
+    let flag = |field: &str| security.and_then(|s| s.get(field)).and_then(Value::as_bool);
+    if flag("privileged") == Some(true) {
+        report(
+            "privileged-container",
+            format!("container `{}` runs privileged", name),
+        );
+    }
+    if flag("allowPrivilegeEscalation") == Some(true) {
+        report(
+            "privileged-container",
+            format!("container `{}` allows privilege escalation", name),
+        );
+    }
+}
+
+/// `:latest`, or no tag and no digest at all.
+fn is_unpinned(image: &str) -> bool {
+    if image.contains('@') {
+        return false;
+    }
+    match image.rsplit_once(':') {
+        Some((_, tag)) if !tag.contains('/') => tag == "latest",
+        _ => true,
+    }
+}
+
+/// Findings as a JSON array.
+pub fn to_json(findings: &[Finding]) -> serde_json::Value {
+    json!(findings)
+}
+
+/// Findings as a SARIF 2.1.0 log, for CI code-scanning annotations.
+pub fn to_sarif(findings: &[Finding], config: &LintConfig) -> serde_json::Value {
+    let rules: Vec<serde_json::Value> = RULES
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id,
+                "shortDescription": { "text": rule.description },
+                "defaultConfiguration": {
+                    "level": config.severity(rule.id).map_or("none", |s| s.as_str()),
+                    "enabled": config.severity(rule.id).is_some()
+                }
+            })
+        })
+        .collect();
+
+    let results: Vec<serde_json::Value> = findings
+        .iter()
+        .map(|finding| {
+            let mut location = json!({
+                "logicalLocations": [{ "fullyQualifiedName": finding.object, "kind": "object" }]
+            });
+            if let Some(path) = &finding.path {
+                location["physicalLocation"] = json!({
+                    "artifactLocation": { "uri": path.to_string_lossy().replace('\\', "/") },
+                    "region": { "startLine": finding.line.unwrap_or(1) }
+                });
+            }
+            json!({
+                "ruleId": finding.rule,
+                "level": finding.severity.as_str(),
+                "message": { "text": format!("{}: {}", finding.object, finding.message) },
+                "locations": [location]
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "avx-cli",
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules
+                }
+            },
+            "results": results
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use avx_config::AvxConfig;
+
+    use super::*;
+    use crate::{generate_manifests, ManifestOptions, ServiceDef};
+
+    fn object(yaml: &str) -> SourceObject {
+        SourceObject {
+            path: PathBuf::new(),
+            line: 0,
+            value: serde_yaml::from_str(yaml).unwrap(),
+        }
+    }
+
+    /// A Deployment breaking every rule once, the `env` label included.
+    const SLOPPY: &str = r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: sloppy
+  namespace: avx
+  labels: { stack: avx, env: qa }
+spec:
+  template:
+    spec:
+      initContainers:
+        - name: migrate
+          image: ghcr.io/avilaops/migrate:1.0
+          resources: { limits: { cpu: 100m, memory: 64Mi } }
+      containers:
+        - name: app
+          image: ghcr.io/avilaops/app
+          resources: { limits: { cpu: 500m } }
+          securityContext: { privileged: true, allowPrivilegeEscalation: true }
+"#;
+
+    fn rules(findings: &[Finding]) -> Vec<(&str, &str)> {
+        findings
+            .iter()
+            .map(|f| (f.rule, f.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn generated_manifests_pass() {
+        let cfg = AvxConfig::with_defaults();
+        let mut opts = ManifestOptions::new(ServiceDef::builtin().remove(0), "avx");
+        opts.image = Some("ghcr.io/avilaops/avx-gateway:1.2.3".into());
+        opts.pdb = true;
+        opts.network_policy = true;
+        let objects: Vec<SourceObject> = generate_manifests(&cfg, &opts)
+            .unwrap()
+            .iter()
+            .map(|m| object(&serde_yaml::to_string(m).unwrap()))
+            .collect();
+
+        assert_eq!(lint(&objects, &LintConfig::default()), []);
+    }
+
+    #[test]
+    fn every_rule_fires_on_a_sloppy_deployment() {
+        let findings = lint(&[object(SLOPPY)], &LintConfig::default());
+        assert_eq!(
+            rules(&findings),
+            [
+                ("required-labels", "missing label `layer`"),
+                (
+                    "required-labels",
+                    "label `env=qa` is not a known environment"
+                ),
+                (
+                    "latest-tag",
+                    "container `app` uses unpinned image `ghcr.io/avilaops/app`"
+                ),
+                ("missing-probes", "container `app` has no livenessProbe"),
+                ("missing-probes", "container `app` has no readinessProbe"),
+                ("missing-limits", "container `app` has no memory limit"),
+                ("privileged-container", "container `app` runs privileged"),
+                (
+                    "privileged-container",
+                    "container `app` allows privilege escalation"
+                ),
+            ]
+        );
+        assert!(findings.iter().all(|f| f.object == "Deployment avx/sloppy"));
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[5].severity, Severity::Error);
+        assert_eq!((findings[0].path.as_ref(), findings[0].line), (None, None));
+    }
+
+    #[test]
+    fn settings_change_severities_and_labels() {
+        let settings = LintSettings {
+            rules: BTreeMap::from([
+                ("latest-tag".to_string(), "error".to_string()),
+                ("missing-probes".to_string(), "off".to_string()),
+                ("privileged-container".to_string(), "note".to_string()),
+            ]),
+            required_labels: Some(vec!["team".to_string()]),
+        };
+        let config = LintConfig::from_settings(&settings).unwrap();
+        let findings = lint(&[object(SLOPPY)], &config);
+
+        let summary: Vec<(&str, Severity)> =
+            findings.iter().map(|f| (f.rule, f.severity)).collect();
+        assert_eq!(
+            summary,
+            [
+                ("required-labels", Severity::Warning),
+                ("latest-tag", Severity::Error),
+                ("missing-limits", Severity::Error),
+                ("privileged-container", Severity::Note),
+                ("privileged-container", Severity::Note),
+            ]
+        );
+        assert_eq!(findings[0].message, "missing label `team`");
+    }
+
+    #[test]
+    fn unknown_rules_and_severities_are_errors() {
+        let settings = |rule: &str, value: &str| LintSettings {
+            rules: BTreeMap::from([(rule.to_string(), value.to_string())]),
+            required_labels: None,
+        };
+        assert!(matches!(
+            LintConfig::from_settings(&settings("no-root", "error")),
+            Err(LintError::UnknownRule(rule)) if rule == "no-root"
+        ));
+        assert!(matches!(
+            LintConfig::from_settings(&settings("latest-tag", "fatal")),
+            Err(LintError::InvalidSeverity { value, .. }) if value == "fatal"
+        ));
+    }
+
+    #[test]
+    fn cron_job_containers_are_checked() {
+        let cron_job = object(
+            r#"
+apiVersion: batch/v1
+kind: CronJob
+metadata: { name: report, labels: { stack: avx, layer: core, env: prod } }
+spec:
+  jobTemplate:
+    spec:
+      template:
+        spec:
+          containers:
+            - name: report
+              image: ghcr.io/avilaops/report:latest
+              resources: { limits: { cpu: 100m, memory: 64Mi } }
+              livenessProbe: {}
+              readinessProbe: {}
+"#,
+        );
+        let findings = lint(&[cron_job], &LintConfig::default());
+        assert_eq!(
+            rules(&findings),
+            [(
+                "latest-tag",
+                "container `report` uses unpinned image `ghcr.io/avilaops/report:latest`"
+            )]
+        );
+        assert_eq!(findings[0].object, "CronJob report");
+    }
+
+    #[test]
+    fn images_are_pinned_by_tag_or_digest() {
+        for image in ["app:1.0", "registry.local:5000/app:1.0", "app@sha256:abc"] {
+            assert!(!is_unpinned(image), "{}", image);
+        }
+        for image in ["app", "app:latest", "registry.local:5000/app", ""] {
+            assert!(is_unpinned(image), "{}", image);
+        }
+    }
+
+    #[test]
+    fn sarif_points_at_the_file_and_line() {
+        let mut source = object(SLOPPY);
+        source.path = PathBuf::from("deploy/app.yaml");
+        source.line = 12;
+        let config = LintConfig::default();
+        let findings = lint(&[source], &config);
+
+        let sarif = to_sarif(&findings, &config);
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"].as_array().unwrap().len(),
+            RULES.len()
+        );
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "required-labels");
+        assert_eq!(result["level"], "warning");
+        let physical = &result["locations"][0]["physicalLocation"];
+        assert_eq!(physical["artifactLocation"]["uri"], "deploy/app.yaml");
+        assert_eq!(physical["region"]["startLine"], 12);
+        assert_eq!(to_json(&findings)[0]["line"], 12);
+    }
+}
@@ -0,0 +1,522 @@
+//! Service-mesh objects, emitted according to `AvxConfig.mesh`.
+//!
+//! Istio gets sidecar injection, a VirtualService, a DestinationRule with
+//! mTLS and outlier detection, and a STRICT PeerAuthentication; Linkerd gets
+//! the inject annotation and a ServiceProfile.
+
+use std::collections::BTreeMap;
+
+use avx_config::Mesh;
+use serde::{Deserialize, Serialize};
+
+use crate::model::{LabelSelector, Labels, Manifest, ObjectMeta};
+use crate::ServiceDef;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualService {
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub spec: VirtualServiceSpec,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualServiceSpec {
+    pub hosts: Vec<String>,
+    pub http: Vec<HttpRoute>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRoute {
+    pub route: Vec<HttpRouteDestination>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<HttpRetry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRouteDestination {
+    pub destination: Destination,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Destination {
+    pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<PortSelector>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortSelector {
+    pub number: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRetry {
+    pub attempts: u32,
+    pub per_try_timeout: String,
+    pub retry_on: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationRule {
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub spec: DestinationRuleSpec,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationRuleSpec {
+    pub host: String,
+    pub traffic_policy: TrafficPolicy,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficPolicy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<ClientTlsSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outlier_detection: Option<OutlierDetection>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientTlsSettings {
+    pub mode: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlierDetection {
+    #[serde(rename = "consecutive5xxErrors")]
+    pub consecutive_5xx_errors: u32,
+    pub interval: String,
+    pub base_ejection_time: String,
+    pub max_ejection_percent: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerAuthentication {
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub spec: PeerAuthenticationSpec,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerAuthenticationSpec {
+    pub selector: LabelSelector,
+    pub mtls: PeerAuthenticationMtls,
+    /// Exceptions to `mtls`, keyed by container port.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub port_level_mtls: BTreeMap<u16, PeerAuthenticationMtls>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerAuthenticationMtls {
+    pub mode: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceProfile {
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub spec: ServiceProfileSpec,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceProfileSpec {
+    pub routes: Vec<RouteSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_budget: Option<RetryBudget>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteSpec {
+    pub name: String,
+    pub condition: RequestMatch,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_retryable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestMatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    pub path_regex: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryBudget {
+    pub retry_ratio: f64,
+    pub min_retries_per_second: u32,
+    pub ttl: String,
+}
+
+/// Namespace of the ingress gateway through which `mesh` sends traffic from
+/// outside the stack. Linkerd has none: its external traffic comes through
+/// the ingress controller (`--ingress-namespace`).
+pub fn gateway_namespace(mesh: Mesh) -> Option<&'static str> {
+    match mesh {
+        Mesh::None | Mesh::Linkerd => None,
+        Mesh::Istio => Some("istio-system"),
+    }
+}
+
+/// Pod template annotations that enrol the pods in `mesh`.
+pub fn pod_annotations(mesh: Mesh) -> BTreeMap<String, String> {
+    match mesh {
+        Mesh::None => BTreeMap::new(),
+        Mesh::Istio => {
+            BTreeMap::from([("sidecar.istio.io/inject".to_string(), "true".to_string())])
+        }
+        Mesh::Linkerd => BTreeMap::from([("linkerd.io/inject".to_string(), "enabled".to_string())]),
+    }
+}
+
+/// Mesh routing and security objects for `service`; empty for [`Mesh::None`].
+/// With `ingress`, the service port also accepts the plaintext traffic of an
+/// ingress controller outside the mesh.
+pub fn mesh_manifests(
+    mesh: Mesh,
+    service: &ServiceDef,
+    namespace: &str,
+    labels: &Labels,
+    ingress: bool,
+) -> Vec<Manifest> {
+    let name = service.name.as_str();
+    let meta = |name: &str| ObjectMeta {
+        name: name.to_string(),
+        namespace: Some(namespace.to_string()),
+        labels: labels.clone(),
+        ..Default::default()
+    };
+    let host = format!("{}.{}.svc.cluster.local", name, namespace);
+
+    match mesh {
+        Mesh::None => Vec::new(),
+        Mesh::Istio => vec![
+            Manifest::VirtualService(VirtualService {
+                api_version: "networking.istio.io/v1beta1".into(),
+                kind: "VirtualService".into(),
+                metadata: meta(name),
+                spec: VirtualServiceSpec {
+                    hosts: vec![host.clone()],
+                    http: vec![HttpRoute {
+                        route: vec![HttpRouteDestination {
+                            destination: Destination {
+                                host: host.clone(),
+                                port: Some(PortSelector {
+                                    number: service.service_port,
+                                }),
+                            },
+                        }],
+                        timeout: Some("30s".into()),
+                        retries: Some(HttpRetry {
+                            attempts: 3,
+                            per_try_timeout: "10s".into(),
+                            retry_on: "5xx,reset,connect-failure".into(),
+                        }),
+                    }],
+                },
+            }),
+            Manifest::DestinationRule(DestinationRule {
+                api_version: "networking.istio.io/v1beta1".into(),
+                kind: "DestinationRule".into(),
+                metadata: meta(name),
+                spec: DestinationRuleSpec {
+                    host,
+                    traffic_policy: TrafficPolicy {
+                        tls: Some(ClientTlsSettings {
+                            mode: "ISTIO_MUTUAL".into(),
+                        }),
+                        outlier_detection: Some(OutlierDetection {
+                            consecutive_5xx_errors: 5,
+                            interval: "30s".into(),
+                            base_ejection_time: "30s".into(),
+                            max_ejection_percent: 50,
+                        }),
+                    },
+                },
+            }),
+            Manifest::PeerAuthentication(PeerAuthentication {
+                api_version: "security.istio.io/v1beta1".into(),
+                kind: "PeerAuthentication".into(),
+                metadata: meta(name),
+                spec: PeerAuthenticationSpec {
+                    selector: LabelSelector {
+                        match_labels: Labels::from([("app".to_string(), name.to_string())]),
+                    },
+                    mtls: PeerAuthenticationMtls {
+                        mode: "STRICT".into(),
+                    },
+                    // an unmeshed ingress-nginx cannot speak mTLS
+                    port_level_mtls: ingress
+                        .then(|| {
+                            (
+                                service.port,
+                                PeerAuthenticationMtls {
+                                    mode: "PERMISSIVE".into(),
+                                },
+                            )
+                        })
+                        .into_iter()
+                        .collect(),
+                },
+            }),
+        ],
+        Mesh::Linkerd => {
+            let mut paths: Vec<&str> = service
+                .probes
+                .iter()
+                .flat_map(|p| [p.liveness.as_str(), p.readiness.as_str()])
+                .collect();
+            paths.dedup();
+            let mut routes: Vec<RouteSpec> = paths
+                .into_iter()
+                .map(|path| RouteSpec {
+                    name: format!("GET {}", path),
+                    condition: RequestMatch {
+                        method: Some("GET".into()),
+                        path_regex: regex_escape(path),
+                    },
+                    is_retryable: Some(true),
+                    timeout: Some("1s".into()),
+                })
+                .collect();
+            routes.push(RouteSpec {
+                name: "default".into(),
+                condition: RequestMatch {
+                    method: None,
+                    path_regex: "/.*".into(),
+                },
+                is_retryable: None,
+                timeout: Some("30s".into()),
+            });
+
+            vec![Manifest::ServiceProfile(ServiceProfile {
+                api_version: "linkerd.io/v1alpha2".into(),
+                kind: "ServiceProfile".into(),
+                // Linkerd matches profiles by the service FQDN
+                metadata: meta(&host),
+                spec: ServiceProfileSpec {
+                    routes,
+                    retry_budget: Some(RetryBudget {
+                        retry_ratio: 0.2,
+                        min_retries_per_second: 10,
+                        ttl: "10s".into(),
+                    }),
+                },
+            })]
+        }
+    }
+}
+
+fn regex_escape(path: &str) -> String {
+    path.chars()
+        .flat_map(|c| {
+            let escape = matches!(
+                c,
+                '.' | '+' | '*' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '^' | '$' | '\\'
+            );
+            escape.then_some('\\').into_iter().chain(std::iter::once(c))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gateway() -> ServiceDef {
+        ServiceDef::builtin().remove(0)
+    }
+
+    fn kinds(manifests: &[Manifest]) -> Vec<&str> {
+        manifests.iter().map(Manifest::kind).collect()
+    }
+
+    #[test]
+    fn no_mesh_adds_nothing() {
+        assert!(mesh_manifests(Mesh::None, &gateway(), "avx", &Labels::new(), false).is_empty());
+        assert!(pod_annotations(Mesh::None).is_empty());
+        assert_eq!(gateway_namespace(Mesh::None), None);
+        assert_eq!(gateway_namespace(Mesh::Linkerd), None);
+    }
+
+    #[test]
+    fn istio_routes_and_enforces_mtls() {
+        let labels = Labels::from([("stack".to_string(), "avx".to_string())]);
+        let manifests = mesh_manifests(Mesh::Istio, &gateway(), "avx", &labels, false);
+        assert_eq!(
+            kinds(&manifests),
+            ["VirtualService", "DestinationRule", "PeerAuthentication"]
+        );
+        assert_eq!(
+            pod_annotations(Mesh::Istio),
+            BTreeMap::from([("sidecar.istio.io/inject".to_string(), "true".to_string())])
+        );
+
+        let host = "avx-gateway.avx.svc.cluster.local";
+        let Manifest::VirtualService(routes) = &manifests[0] else {
+            unreachable!()
+        };
+        assert_eq!(routes.metadata.namespace.as_deref(), Some("avx"));
+        assert_eq!(routes.metadata.labels, labels);
+        assert_eq!(routes.spec.hosts, [host]);
+        let destination = &routes.spec.http[0].route[0].destination;
+        assert_eq!(destination.host, host);
+        assert_eq!(destination.port.as_ref().unwrap().number, 80);
+
+        let Manifest::DestinationRule(rule) = &manifests[1] else {
+            unreachable!()
+        };
+        assert_eq!(rule.spec.host, host);
+        assert_eq!(
+            rule.spec.traffic_policy.tls.as_ref().unwrap().mode,
+            "ISTIO_MUTUAL"
+        );
+
+        let Manifest::PeerAuthentication(auth) = &manifests[2] else {
+            unreachable!()
+        };
+        assert_eq!(auth.spec.mtls.mode, "STRICT");
+        assert!(auth.spec.port_level_mtls.is_empty());
+        assert_eq!(auth.spec.selector.match_labels["app"], "avx-gateway");
+    }
+
+    #[test]
+    fn linkerd_profile_is_named_after_the_service_fqdn() {
+        let manifests = mesh_manifests(Mesh::Linkerd, &gateway(), "avx", &Labels::new(), false);
+        assert_eq!(kinds(&manifests), ["ServiceProfile"]);
+        assert_eq!(
+            pod_annotations(Mesh::Linkerd),
+            BTreeMap::from([("linkerd.io/inject".to_string(), "enabled".to_string())])
+        );
+
+        let Manifest::ServiceProfile(profile) = &manifests[0] else {
+            unreachable!()
+        };
+        assert_eq!(profile.metadata.name, "avx-gateway.avx.svc.cluster.local");
+        let routes: Vec<(&str, &str)> = profile
+            .spec
+            .routes
+            .iter()
+            .map(|r| (r.name.as_str(), r.condition.path_regex.as_str()))
+            .collect();
+        assert_eq!(
+            routes,
+            [
+                ("GET /healthz", "/healthz"),
+                ("GET /readyz", "/readyz"),
+                ("default", "/.*"),
+            ]
+        );
+    }
+
+    #[test]
+    fn linkerd_routes_shared_health_paths_once() {
+        let events = ServiceDef::builtin().remove(2);
+        let manifests = mesh_manifests(Mesh::Linkerd, &events, "avx", &Labels::new(), false);
+        let Manifest::ServiceProfile(profile) = &manifests[0] else {
+            unreachable!()
+        };
+        let names: Vec<&str> = profile
+            .spec
+            .routes
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect();
+        assert_eq!(names, ["GET /healthz", "default"]);
+
+        let worker = ServiceDef::new("worker", 9000);
+        let manifests = mesh_manifests(Mesh::Linkerd, &worker, "avx", &Labels::new(), false);
+        let Manifest::ServiceProfile(profile) = &manifests[0] else {
+            unreachable!()
+        };
+        assert_eq!(profile.spec.routes.len(), 1);
+    }
+
+    #[test]
+    fn route_paths_are_regex_escaped() {
+        assert_eq!(regex_escape("/health/ready"), "/health/ready");
+        assert_eq!(regex_escape("/v1.0/status?full"), r"/v1\.0/status\?full");
+        assert_eq!(regex_escape("/a+b(c)"), r"/a\+b\(c\)");
+    }
+
+    #[test]
+    fn generated_pods_join_the_configured_mesh() {
+        let mut cfg = avx_config::AvxConfig::with_defaults();
+        cfg.mesh = "linkerd".into();
+        let opts = crate::ManifestOptions::new(gateway(), "avx");
+        let manifests = crate::generate_manifests(&cfg, &opts).unwrap();
+
+        assert_eq!(
+            kinds(&manifests),
+            ["Deployment", "Service", "ServiceProfile"]
+        );
+        let Manifest::Deployment(deployment) = &manifests[0] else {
+            unreachable!()
+        };
+        assert_eq!(
+            deployment.spec.template.metadata.annotations,
+            pod_annotations(Mesh::Linkerd)
+        );
+    }
+
+    #[test]
+    fn istio_lets_the_ingress_controller_in_without_mtls() {
+        let mut cfg = avx_config::AvxConfig::with_defaults();
+        cfg.mesh = "istio".into();
+        let mut opts = crate::ManifestOptions::new(gateway(), "avx");
+        opts.ingress = Some("gateway.avila.inc".into());
+        let manifests = crate::generate_manifests(&cfg, &opts).unwrap();
+
+        let auth = manifests
+            .iter()
+            .find_map(|m| match m {
+                Manifest::PeerAuthentication(auth) => Some(auth),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(auth.spec.mtls.mode, "STRICT");
+        assert_eq!(
+            auth.spec.port_level_mtls,
+            BTreeMap::from([(
+                8080,
+                PeerAuthenticationMtls {
+                    mode: "PERMISSIVE".into(),
+                },
+            )])
+        );
+        let yaml = serde_yaml::to_string(&manifests.last().unwrap()).unwrap();
+        assert!(
+            yaml.contains("portLevelMtls:\n    8080:\n      mode: PERMISSIVE"),
+            "{}",
+            yaml
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// One deployable service, as described by a `[[services]]` entry of the
+/// service catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceDef {
+    /// Short name used on the command line and in the `avx_deploy` tool.
+    pub id: String,
+    /// Kubernetes object name; the catalog fills in `avx-<id>` when empty.
+    #[serde(default)]
+    pub name: String,
+    /// Default container image; the catalog fills in
+    /// `ghcr.io/avilaops/<name>:latest` when empty.
+    #[serde(default)]
+    pub image: String,
+    /// Port the container listens on.
+    pub port: u16,
+    /// Port exposed by the ClusterIP Service.
+    #[serde(default = "default_service_port")]
+    pub service_port: u16,
+    /// HTTP health endpoints; no probes are generated when `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probes: Option<HealthPaths>,
+    /// Extra container environment, added after the `AVX__*` variables.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Ids of the services this one calls.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+}
+
+fn default_service_port() -> u16 {
+    // porta externa do Service (ClusterIP)
+    80
+}
+
+impl ServiceDef {
+    /// A service with the catalog defaults for everything but `id` and `port`.
+    pub fn new(id: impl Into<String>, port: u16) -> Self {
+        let mut service = Self {
+            id: id.into(),
+            name: String::new(),
+            image: String::new(),
+            port,
+            service_port: default_service_port(),
+            probes: None,
+            env: BTreeMap::new(),
+            depends_on: Vec::new(),
+        };
+        service.fill_defaults();
+        service
+    }
+
+    pub(crate) fn fill_defaults(&mut self) {
+        if self.name.is_empty() {
+            self.name = format!("avx-{}", self.id);
+        }
+        if self.image.is_empty() {
+            self.image = format!("ghcr.io/avilaops/{}:latest", self.name);
+        }
+    }
+
+    fn with_probes(mut self, liveness: &str, readiness: &str) -> Self {
+        self.probes = Some(HealthPaths {
+            liveness: liveness.into(),
+            readiness: readiness.into(),
+        });
+        self
+    }
+
+    /// The services shipped with avx-cli, used when no catalog file overrides
+    /// them.
+    pub fn builtin() -> Vec<ServiceDef> {
+        vec![
+            ServiceDef::new("gateway", 8080).with_probes("/healthz", "/readyz"),
+            ServiceDef::new("api-core", 8081).with_probes("/health/live", "/health/ready"),
+            ServiceDef::new("events", 8090).with_probes("/healthz", "/healthz"),
+        ]
+    }
+}
+
+/// HTTP paths for the liveness and readiness probes, served on the
+/// container port.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthPaths {
+    pub liveness: String,
+    pub readiness: String,
+}
+
+impl fmt::Display for ServiceDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id)
+    }
+}
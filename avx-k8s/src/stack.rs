@@ -0,0 +1,233 @@
+//! Rendering every service of the catalog at once (`avx-cli k8s --all`).
+
+use avx_config::AvxConfig;
+
+use crate::generate::{generate_manifests, stack_labels, to_yaml, ManifestOptions, RenderedFile};
+use crate::kustomize::Kustomization;
+use crate::model::{Manifest, Namespace, ObjectMeta};
+use crate::{ManifestError, ServiceCatalog};
+
+/// How `render_stack` lays out its files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackLayout {
+    /// `namespace.yaml` plus one `<service>.yaml` per service.
+    PerService,
+    /// A single `stack.yaml` with every object.
+    Bundle,
+}
+
+/// File name of the single-file layout.
+pub const BUNDLE_FILE_NAME: &str = "stack.yaml";
+
+/// The Namespace every service of the stack is rendered into.
+pub fn stack_namespace(cfg: &AvxConfig, namespace: &str) -> Result<Manifest, ManifestError> {
+    let mut labels = stack_labels(cfg, namespace)?;
+    labels.remove("app");
+    Ok(Manifest::Namespace(Namespace::new(ObjectMeta {
+        name: namespace.to_string(),
+        labels,
+        ..Default::default()
+    })))
+}
+
+/// Renders the Namespace and every service in `catalog` (dependencies
+/// first), each with `template` but its own service, plus a
+/// `kustomization.yaml` listing the files in apply order.
+pub fn render_stack(
+    cfg: &AvxConfig,
+    catalog: &ServiceCatalog,
+    template: &ManifestOptions,
+    layout: StackLayout,
+) -> Result<Vec<RenderedFile>, ManifestError> {
+    let namespace = stack_namespace(cfg, &template.namespace)?;
+    let services = service_manifests(cfg, catalog, template)?;
+
+    let mut files = match layout {
+        StackLayout::PerService => {
+            let mut files = vec![RenderedFile::new(
+                "namespace.yaml",
+                to_yaml(cfg, &[namespace])?,
+            )];
+            for (name, manifests) in &services {
+                files.push(RenderedFile::new(
+                    format!("{}.yaml", name),
+                    to_yaml(cfg, manifests)?,
+                ));
+            }
+            files
+        }
+        StackLayout::Bundle => {
+            let mut all = vec![namespace];
+            all.extend(services.into_iter().flat_map(|(_, manifests)| manifests));
+            vec![RenderedFile::new(BUNDLE_FILE_NAME, to_yaml(cfg, &all)?)]
+        }
+    };
+
+    let resources = files
+        .iter()
+        .map(|file| file.path.to_string_lossy().into_owned())
+        .collect();
+    files.push(RenderedFile::new(
+        "kustomization.yaml",
+        serde_yaml::to_string(&Kustomization::new(resources))?,
+    ));
+    Ok(files)
+}
+
+/// The Namespace followed by every service's objects, in the order
+/// [`render_stack`] writes them.
+pub fn stack_manifests(
+    cfg: &AvxConfig,
+    catalog: &ServiceCatalog,
+    template: &ManifestOptions,
+) -> Result<Vec<Manifest>, ManifestError> {
+    let mut all = vec![stack_namespace(cfg, &template.namespace)?];
+    for (_, manifests) in service_manifests(cfg, catalog, template)? {
+        all.extend(manifests);
+    }
+    Ok(all)
+}
+
+fn service_manifests<'a>(
+    cfg: &AvxConfig,
+    catalog: &'a ServiceCatalog,
+    template: &ManifestOptions,
+) -> Result<Vec<(&'a str, Vec<Manifest>)>, ManifestError> {
+    let mut services = Vec::new();
+    for service in catalog.ordered()? {
+        let opts = ManifestOptions {
+            service: service.clone(),
+            ..template.clone()
+        };
+        services.push((service.name.as_str(), generate_manifests(cfg, &opts)?));
+    }
+    Ok(services)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CatalogError, ServiceDef};
+
+    /// The built-ins plus `billing`, which the gateway now depends on.
+    fn catalog() -> ServiceCatalog {
+        let mut catalog = ServiceCatalog::builtin();
+        catalog.insert(ServiceDef {
+            depends_on: vec!["api-core".into()],
+            ..ServiceDef::new("billing", 8095)
+        });
+        let mut gateway = ServiceDef::builtin().remove(0);
+        gateway.depends_on = vec!["billing".into()];
+        catalog.insert(gateway);
+        catalog
+    }
+
+    fn template() -> ManifestOptions {
+        let mut template = ManifestOptions::new(ServiceDef::builtin().remove(0), "avx-prod");
+        template.replicas = 3;
+        template
+    }
+
+    fn paths(files: &[RenderedFile]) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| file.path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn per_service_files_follow_the_dependency_order() {
+        let cfg = AvxConfig::with_defaults();
+        let files = render_stack(&cfg, &catalog(), &template(), StackLayout::PerService).unwrap();
+
+        let order = [
+            "namespace.yaml",
+            "avx-api-core.yaml",
+            "avx-events.yaml",
+            "avx-billing.yaml",
+            "avx-gateway.yaml",
+        ];
+        assert_eq!(
+            paths(&files),
+            [&order[..], &["kustomization.yaml"]].concat()
+        );
+        let kustomization: serde_yaml::Value =
+            serde_yaml::from_str(&files.last().unwrap().content).unwrap();
+        let resources: Vec<&str> = kustomization["resources"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|r| r.as_str().unwrap())
+            .collect();
+        assert_eq!(resources, order);
+
+        let billing = &files[3].content;
+        assert!(billing.contains("name: avx-billing"), "{}", billing);
+        assert!(billing.contains("namespace: avx-prod"));
+        assert!(billing.contains("replicas: 3"));
+        assert!(!billing.contains("avx-gateway"));
+    }
+
+    #[test]
+    fn bundle_holds_every_object_in_one_file() {
+        let cfg = AvxConfig::with_defaults();
+        let files = render_stack(&cfg, &catalog(), &template(), StackLayout::Bundle).unwrap();
+        assert_eq!(paths(&files), [BUNDLE_FILE_NAME, "kustomization.yaml"]);
+
+        let objects = stack_manifests(&cfg, &catalog(), &template()).unwrap();
+        assert_eq!(files[0].content, to_yaml(&cfg, &objects).unwrap());
+        let names: Vec<(&str, &str)> = objects
+            .iter()
+            .map(|m| (m.kind(), m.metadata().name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("Namespace", "avx-prod"),
+                ("Deployment", "avx-api-core"),
+                ("Service", "avx-api-core"),
+                ("Deployment", "avx-events"),
+                ("Service", "avx-events"),
+                ("Deployment", "avx-billing"),
+                ("Service", "avx-billing"),
+                ("Deployment", "avx-gateway"),
+                ("Service", "avx-gateway"),
+            ]
+        );
+    }
+
+    #[test]
+    fn namespace_carries_the_stack_labels_but_no_app() {
+        let cfg = AvxConfig {
+            stack: "checkout".into(),
+            ..AvxConfig::with_defaults()
+        };
+        let namespace = stack_namespace(&cfg, "avx-prod").unwrap();
+        let labels = &namespace.metadata().labels;
+        assert_eq!(labels["stack"], "checkout");
+        assert_eq!(labels["env"], "dev");
+        assert!(!labels.contains_key("app"));
+        assert_eq!(namespace.metadata().namespace, None);
+    }
+
+    #[test]
+    fn dependency_cycles_fail_the_render() {
+        let mut catalog = catalog();
+        catalog.insert(ServiceDef {
+            depends_on: vec!["gateway".into()],
+            ..ServiceDef::new("api-core", 8081)
+        });
+        let error = render_stack(
+            &AvxConfig::with_defaults(),
+            &catalog,
+            &template(),
+            StackLayout::Bundle,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(error, ManifestError::Catalog(CatalogError::Cycle(_))),
+            "{}",
+            error
+        );
+    }
+}
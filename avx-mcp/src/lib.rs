@@ -3,7 +3,23 @@ pub mod resources;
 pub mod server;
 pub mod tools;
 
+#[cfg(test)]
+mod testing;
+
 pub use protocol::*;
 pub use resources::*;
-pub use server::McpServer;
+pub use server::{LifecycleState, McpServer};
 pub use tools::*;
+
+/// `e` followed by its sources, so the cause (e.g. a failed kubeconfig
+/// lookup) reaches the client.
+pub(crate) fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
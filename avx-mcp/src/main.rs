@@ -1,5 +1,5 @@
 use anyhow::Result;
-use avx_mcp::{McpServer, get_all_resources, get_all_tools};
+use avx_mcp::{get_all_resource_providers, get_all_tools, McpServer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -13,12 +13,12 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
-    // Create and configure server
-    let mut server = McpServer::new();
+    // Create the server, configured by the AVX_MCP_* environment variables
+    let mut server = McpServer::from_env();
 
-    // Register all resources
-    for resource in get_all_resources() {
-        server.register_resource(resource);
+    // Register all resource providers
+    for provider in get_all_resource_providers() {
+        server.register_resource_provider(provider);
     }
 
     // Register all tools
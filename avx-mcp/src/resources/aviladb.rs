@@ -1,18 +1,210 @@
-use crate::protocol::Resource;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
 
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{encode_segment, json_contents, ResourceError, ResourceProvider};
+use crate::protocol::{Resource, ResourceContents};
+
+/// Environment variable naming the AvilaDB catalog file.
+pub const AVILADB_CATALOG_ENV_VAR: &str = "AVX_AVILADB_CATALOG";
+
+/// File name searched for upward from the working directory.
+pub const AVILADB_CATALOG_FILE_NAME: &str = "avx-aviladb.toml";
+
+/// The AvilaDB collections exposed as `aviladb://<database>/<collection>`
+/// resources, declared as `[[collections]]` entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AvilaDbCatalog {
+    #[serde(default)]
+    pub collections: Vec<CollectionDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CollectionDef {
+    pub database: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl CollectionDef {
+    /// `aviladb://<database>/<collection>`, each segment percent-encoded.
+    pub fn uri(&self) -> String {
+        format!(
+            "aviladb://{}/{}",
+            encode_segment(&self.database),
+            encode_segment(&self.name)
+        )
+    }
+}
+
+impl AvilaDbCatalog {
+    pub fn load(path: &Path) -> Result<Self, ResourceError> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let catalog = toml::from_str(&text)
+            .with_context(|| format!("invalid AvilaDB catalog {}", path.display()))?;
+        Ok(catalog)
+    }
+
+    /// The catalog named by `AVX_AVILADB_CATALOG`, else the nearest
+    /// `avx-aviladb.toml` from `cwd` upwards, else an empty catalog (logged
+    /// once, as the catalog is re-read on every resource refresh).
+    pub fn discover(cwd: &Path) -> Result<Self, ResourceError> {
+        if let Some(path) = std::env::var_os(AVILADB_CATALOG_ENV_VAR) {
+            return Self::load(Path::new(&path));
+        }
+        let found: Option<PathBuf> = cwd
+            .ancestors()
+            .map(|dir| dir.join(AVILADB_CATALOG_FILE_NAME))
+            .find(|path| path.is_file());
+        match found {
+            Some(path) => Self::load(&path),
+            None => {
+                static REPORTED: Once = Once::new();
+                REPORTED.call_once(|| {
+                    tracing::info!(
+                        "no AvilaDB catalog ({} unset, no {} in {} or above): no aviladb:// resources",
+                        AVILADB_CATALOG_ENV_VAR,
+                        AVILADB_CATALOG_FILE_NAME,
+                        cwd.display()
+                    )
+                });
+                Ok(Self::default())
+            }
+        }
+    }
+}
+
+fn discover_catalog() -> Result<AvilaDbCatalog, ResourceError> {
+    AvilaDbCatalog::discover(&std::env::current_dir().unwrap_or_default())
+}
+
+/// One resource per collection of the AvilaDB catalog, re-read on every call.
 pub fn get_aviladb_resources() -> Vec<Resource> {
-    vec![
-        Resource {
-            uri: "aviladb://production/users".to_string(),
-            name: "Users Database".to_string(),
-            description: Some("Production user database collection".to_string()),
+    let catalog = discover_catalog().unwrap_or_else(|e| {
+        tracing::warn!("ignoring AvilaDB catalog: {}", crate::error_chain(&e));
+        AvilaDbCatalog::default()
+    });
+    catalog
+        .collections
+        .iter()
+        .map(|c| Resource {
+            uri: c.uri(),
+            name: format!("{}/{}", c.database, c.name),
+            description: c.description.clone(),
             mime_type: Some("application/json".to_string()),
-        },
-        Resource {
-            uri: "aviladb://production/events".to_string(),
-            name: "Events Database".to_string(),
-            description: Some("Production events collection".to_string()),
-            mime_type: Some("application/json".to_string()),
-        },
-    ]
+        })
+        .collect()
+}
+
+/// Serves `aviladb://<database>/<collection>` descriptors.
+pub struct AvilaDbResources;
+
+#[async_trait]
+impl ResourceProvider for AvilaDbResources {
+    fn prefix(&self) -> &str {
+        "aviladb://"
+    }
+
+    async fn list(&self) -> Vec<Resource> {
+        get_aviladb_resources()
+    }
+
+    async fn read(&self, uri: &str) -> Result<ResourceContents, ResourceError> {
+        let collection = discover_catalog()?
+            .collections
+            .into_iter()
+            .find(|c| c.uri() == uri)
+            .ok_or_else(|| ResourceError::NotFound(uri.to_string()))?;
+
+        Ok(json_contents(
+            uri,
+            &json!({
+                "database": collection.database,
+                "collection": collection.name,
+                "description": collection.description,
+            }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const CATALOG: &str = "[[collections]]\ndatabase = \"production\"\nname = \"events\"\n\
+                           description = \"Gateway events\"\n\n\
+                           [[collections]]\ndatabase = \"production\"\nname = \"users\"\n";
+
+    fn write(dir: &Path, text: &str) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join(AVILADB_CATALOG_FILE_NAME);
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn collections_become_aviladb_uris() {
+        let dir = TempDir::new().unwrap();
+        let catalog = AvilaDbCatalog::load(&write(dir.path(), CATALOG)).unwrap();
+
+        let uris: Vec<String> = catalog.collections.iter().map(CollectionDef::uri).collect();
+        assert_eq!(
+            uris,
+            ["aviladb://production/events", "aviladb://production/users"]
+        );
+        assert_eq!(
+            catalog.collections[0].description.as_deref(),
+            Some("Gateway events")
+        );
+        assert_eq!(catalog.collections[1].description, None);
+
+        let odd = CollectionDef {
+            database: "tenant:acme".into(),
+            name: "events/2026 q1".into(),
+            description: None,
+        };
+        assert_eq!(odd.uri(), "aviladb://tenant%3Aacme/events%2F2026%20q1");
+    }
+
+    #[test]
+    fn discover_looks_upwards_from_the_working_directory() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(
+            AvilaDbCatalog::discover(dir.path()).unwrap(),
+            AvilaDbCatalog::default()
+        );
+
+        write(&dir.path().join("repo"), CATALOG);
+        let nested = dir.path().join("repo/services/api");
+        let catalog = AvilaDbCatalog::discover(&nested).unwrap();
+        assert_eq!(catalog.collections.len(), 2);
+    }
+
+    #[test]
+    fn broken_catalogs_are_errors() {
+        let dir = TempDir::new().unwrap();
+        let path = write(
+            dir.path(),
+            "[[collections]]\ndatabase = \"production\"\nname = \"events\"\nshards = 4\n",
+        );
+        let error = AvilaDbCatalog::load(&path).unwrap_err();
+        assert!(
+            crate::error_chain(&error).contains("invalid AvilaDB catalog"),
+            "{}",
+            crate::error_chain(&error)
+        );
+
+        let missing = dir.path().join("missing.toml");
+        assert!(AvilaDbCatalog::load(&missing).is_err());
+    }
 }
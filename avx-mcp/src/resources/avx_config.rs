@@ -1,18 +1,235 @@
-use crate::protocol::Resource;
+use async_trait::async_trait;
+use avx_config::{AvxConfig, ConfigLoader, ResolvedConfig};
+use serde_json::json;
+
+use super::{json_contents, ResourceError, ResourceProvider};
+use crate::protocol::{Resource, ResourceContents};
 
 pub fn get_avx_config_resources() -> Vec<Resource> {
     vec![
+        Resource {
+            uri: "avx://config".to_string(),
+            name: "AVX Config".to_string(),
+            description: Some(
+                "Full effective configuration with the source of each value".to_string(),
+            ),
+            mime_type: Some("application/json".to_string()),
+        },
         Resource {
             uri: "avx://config/stack".to_string(),
             name: "AVX Stack Config".to_string(),
             description: Some("Current stack configuration".to_string()),
             mime_type: Some("application/json".to_string()),
         },
+        Resource {
+            uri: "avx://config/env".to_string(),
+            name: "AVX Environment".to_string(),
+            description: Some("Target environment (dev, staging, prod)".to_string()),
+            mime_type: Some("application/json".to_string()),
+        },
+        Resource {
+            uri: "avx://config/cluster".to_string(),
+            name: "AVX Cluster Config".to_string(),
+            description: Some("Target Kubernetes cluster".to_string()),
+            mime_type: Some("application/json".to_string()),
+        },
         Resource {
             uri: "avx://config/mesh".to_string(),
             name: "AVX Mesh Config".to_string(),
             description: Some("Service mesh configuration".to_string()),
             mime_type: Some("application/json".to_string()),
         },
+        Resource {
+            uri: "avx://config/profile".to_string(),
+            name: "AVX Profile".to_string(),
+            description: Some("Active profile and all defined profiles".to_string()),
+            mime_type: Some("application/json".to_string()),
+        },
     ]
 }
+
+/// Serves `avx://config` and `avx://config/*` from the live [`AvxConfig`].
+#[derive(Default)]
+pub struct AvxConfigResources {
+    loader: ConfigLoader,
+}
+
+impl AvxConfigResources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the configuration through `loader` instead of the default
+    /// system, user and project files.
+    pub fn with_loader(mut self, loader: ConfigLoader) -> Self {
+        self.loader = loader;
+        self
+    }
+}
+
+#[async_trait]
+impl ResourceProvider for AvxConfigResources {
+    fn prefix(&self) -> &str {
+        "avx://config"
+    }
+
+    async fn list(&self) -> Vec<Resource> {
+        get_avx_config_resources()
+    }
+
+    async fn read(&self, uri: &str) -> Result<ResourceContents, ResourceError> {
+        let loader = &self.loader;
+        let keys: &[&str] = match uri.trim_start_matches(self.prefix()) {
+            "" => &AvxConfig::KEYS,
+            "/stack" => &["stack", "layer"],
+            "/env" => &["env"],
+            "/cluster" => &["cluster", "namespace", "kube_context"],
+            "/mesh" => &["mesh"],
+            "/profile" => {
+                let value = json!({
+                    "active": loader.selected_profile()?,
+                    "profiles": loader.profiles()?,
+                });
+                return Ok(json_contents(uri, &value));
+            }
+            _ => return Err(ResourceError::NotFound(uri.to_string())),
+        };
+
+        let resolved = loader.resolve()?;
+        let mut value = describe(&resolved, keys);
+        value["profile"] = json!(resolved.profile);
+        Ok(json_contents(uri, &value))
+    }
+}
+
+/// `{"<key>": {"value": ..., "source": ...}}` for each of `keys`.
+fn describe(resolved: &ResolvedConfig, keys: &[&str]) -> serde_json::Value {
+    let entries = keys
+        .iter()
+        .map(|key| {
+            let entry = json!({
+                "value": resolved.config.get(key),
+                "source": resolved.source(key),
+            });
+            (key.to_string(), entry)
+        })
+        .collect();
+    serde_json::Value::Object(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tempfile::TempDir;
+
+    /// Reads `<dir>/user.toml` only, with `env` overridden on the command
+    /// line.
+    fn resources(dir: &TempDir) -> AvxConfigResources {
+        let path = dir.path().join("user.toml");
+        std::fs::write(
+            &path,
+            "profile = \"production\"\nstack = \"avx\"\n\
+             [profiles.production]\ncluster = \"us-east-1\"\nmesh = \"istio\"\n",
+        )
+        .unwrap();
+        AvxConfigResources::new().with_loader(
+            ConfigLoader::new()
+                .system_path(None)
+                .user_path(Some(path))
+                .cwd(None)
+                .use_env(false)
+                .overrides(avx_config::ConfigFile {
+                    env: Some("staging".to_string()),
+                    ..Default::default()
+                }),
+        )
+    }
+
+    async fn read(resources: &AvxConfigResources, uri: &str) -> Value {
+        let contents = resources.read(uri).await.unwrap();
+        assert_eq!(contents.uri, uri);
+        assert_eq!(contents.mime_type.as_deref(), Some("application/json"));
+        serde_json::from_str(&contents.text.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn values_come_with_their_source() {
+        let dir = TempDir::new().unwrap();
+        let resources = resources(&dir);
+        let user_file = dir.path().join("user.toml");
+
+        let config = read(&resources, "avx://config").await;
+        for key in AvxConfig::KEYS {
+            assert!(config.get(key).is_some(), "{} missing from {}", key, config);
+        }
+        assert_eq!(
+            config["stack"],
+            json!({"value": "avx", "source": {"kind": "file", "scope": "user", "path": user_file}})
+        );
+        assert_eq!(
+            config["env"],
+            json!({"value": "staging", "source": {"kind": "cli"}})
+        );
+        assert_eq!(config["layer"]["source"], json!({"kind": "default"}));
+        assert_eq!(config["profile"]["name"], "production");
+
+        let mesh = read(&resources, "avx://config/mesh").await;
+        assert_eq!(mesh["mesh"]["value"], "istio");
+        assert_eq!(mesh["mesh"]["source"]["kind"], "profile");
+        assert_eq!(mesh["mesh"]["source"]["name"], "production");
+    }
+
+    #[tokio::test]
+    async fn sub_resources_only_carry_their_keys() {
+        let dir = TempDir::new().unwrap();
+        let resources = resources(&dir);
+
+        for (uri, keys) in [
+            ("avx://config/stack", &["stack", "layer"][..]),
+            ("avx://config/env", &["env"]),
+            (
+                "avx://config/cluster",
+                &["cluster", "namespace", "kube_context"],
+            ),
+            ("avx://config/mesh", &["mesh"]),
+        ] {
+            let value = read(&resources, uri).await;
+            let mut found: Vec<&str> = value
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(String::as_str)
+                .filter(|key| *key != "profile")
+                .collect();
+            found.sort();
+            let mut keys = keys.to_vec();
+            keys.sort();
+            assert_eq!(found, keys, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn profile_lists_the_active_and_defined_profiles() {
+        let dir = TempDir::new().unwrap();
+        let value = read(&resources(&dir), "avx://config/profile").await;
+
+        assert_eq!(value["active"]["name"], "production");
+        assert_eq!(value["active"]["selected_by"]["kind"], "file");
+        assert_eq!(
+            value["profiles"],
+            json!({"production": {"cluster": "us-east-1", "mesh": "istio"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_config_uris_are_not_found() {
+        let dir = TempDir::new().unwrap();
+        let resources = resources(&dir);
+
+        for uri in ["avx://config/replicas", "avx://configuration"] {
+            let error = resources.read(uri).await.unwrap_err();
+            assert!(matches!(error, ResourceError::NotFound(_)), "{}", uri);
+        }
+    }
+}
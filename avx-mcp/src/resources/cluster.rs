@@ -1,18 +1,368 @@
-use crate::protocol::Resource;
+use std::sync::Arc;
 
+use async_trait::async_trait;
+use avx_config::ConfigLoader;
+use avx_k8s::{
+    cluster_summary, deployment_detail, kube_contexts, namespace_summary, ClusterBackend,
+    KubeBackend,
+};
+use serde_json::json;
+
+use super::{decode_segment, encode_segment, json_contents, ResourceError, ResourceProvider};
+use crate::protocol::{Resource, ResourceContents};
+
+/// One resource per kubeconfig context, named after the cluster it reaches
+/// (see [`ConfigLoader::cluster_for_context`]). Empty without a kubeconfig.
 pub fn get_cluster_resources() -> Vec<Resource> {
-    vec![
-        Resource {
-            uri: "avx://cluster/production".to_string(),
-            name: "Production Cluster".to_string(),
-            description: Some("Production Kubernetes cluster".to_string()),
-            mime_type: Some("application/json".to_string()),
-        },
-        Resource {
-            uri: "avx://cluster/staging".to_string(),
-            name: "Staging Cluster".to_string(),
-            description: Some("Staging Kubernetes cluster".to_string()),
+    match kube_contexts() {
+        Ok(contexts) => context_resources(&ConfigLoader::new(), contexts),
+        Err(e) => {
+            tracing::debug!("no cluster resources: {}", crate::error_chain(&e));
+            Vec::new()
+        }
+    }
+}
+
+/// One resource per cluster reached through `contexts`, in order.
+fn context_resources(loader: &ConfigLoader, contexts: Vec<String>) -> Vec<Resource> {
+    let mut resources: Vec<Resource> = Vec::new();
+    for context in contexts {
+        let cluster = loader
+            .cluster_for_context(&context)
+            .unwrap_or_else(|_| context.clone());
+        let uri = format!("avx://cluster/{}", encode_segment(&cluster));
+        if resources.iter().any(|r| r.uri == uri) {
+            continue;
+        }
+        resources.push(Resource {
+            uri,
+            name: cluster,
+            description: Some(format!(
+                "Kubernetes cluster (kubeconfig context {})",
+                context
+            )),
             mime_type: Some("application/json".to_string()),
-        },
-    ]
+        });
+    }
+    resources
+}
+
+/// Serves live cluster state read through a [`ClusterBackend`]:
+///
+/// - `avx://cluster/<name>`: node count/readiness, namespaces, Deployments
+///   and recent warning events
+/// - `avx://cluster/<name>/namespaces/<namespace>`: Deployments and warnings
+///   of one namespace
+/// - `avx://cluster/<name>/namespaces/<namespace>/deployments/<deployment>`:
+///   replicas, images, rollout state and related warnings
+#[derive(Default)]
+pub struct ClusterResources {
+    loader: ConfigLoader,
+    contexts: Option<Vec<String>>,
+    backend: Option<Arc<dyn ClusterBackend>>,
+}
+
+impl ClusterResources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps clusters and contexts through `loader` instead of the default
+    /// system, user and project files.
+    pub fn with_loader(mut self, loader: ConfigLoader) -> Self {
+        self.loader = loader;
+        self
+    }
+
+    /// Lists the clusters reached through `contexts` instead of the
+    /// kubeconfig contexts.
+    pub fn with_contexts(mut self, contexts: Vec<String>) -> Self {
+        self.contexts = Some(contexts);
+        self
+    }
+
+    /// Reads every cluster through `backend` instead of connecting through
+    /// the kubeconfig context of each cluster. The kubeconfig is not read
+    /// either: only the clusters of [`with_contexts`](Self::with_contexts)
+    /// are listed.
+    pub fn with_backend(mut self, backend: Arc<dyn ClusterBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    fn contexts(&self) -> Vec<String> {
+        if let Some(contexts) = &self.contexts {
+            return contexts.clone();
+        }
+        if self.backend.is_some() {
+            return Vec::new();
+        }
+        kube_contexts().unwrap_or_else(|e| {
+            tracing::debug!("no cluster resources: {}", crate::error_chain(&e));
+            Vec::new()
+        })
+    }
+
+    async fn connect(&self, cluster: &str) -> Result<Arc<dyn ClusterBackend>, ResourceError> {
+        if let Some(backend) = &self.backend {
+            return Ok(backend.clone());
+        }
+        Ok(Arc::new(
+            KubeBackend::for_cluster(&self.loader, cluster).await?,
+        ))
+    }
+}
+
+#[async_trait]
+impl ResourceProvider for ClusterResources {
+    fn prefix(&self) -> &str {
+        "avx://cluster/"
+    }
+
+    async fn list(&self) -> Vec<Resource> {
+        context_resources(&self.loader, self.contexts())
+    }
+
+    async fn read(&self, uri: &str) -> Result<ResourceContents, ResourceError> {
+        let not_found = || ResourceError::NotFound(uri.to_string());
+        let path: Vec<&str> = uri.trim_start_matches(self.prefix()).split('/').collect();
+        let cluster = decode_segment(path[0]).ok_or_else(not_found)?;
+        if cluster.is_empty() {
+            return Err(not_found());
+        }
+        let backend = self.connect(&cluster).await?;
+
+        let value = match path[1..] {
+            [] => {
+                let summary = cluster_summary(backend.as_ref()).await?;
+                json!({
+                    "cluster": cluster,
+                    "nodes": summary.nodes,
+                    "namespaces": summary.namespaces,
+                    "deployments": summary.deployments,
+                    "warnings": summary.warnings,
+                })
+            }
+            ["namespaces", namespace] => {
+                json!(namespace_summary(backend.as_ref(), namespace).await?)
+            }
+            ["namespaces", namespace, "deployments", name] => {
+                json!(deployment_detail(backend.as_ref(), namespace, name).await?)
+            }
+            _ => return Err(not_found()),
+        };
+        Ok(json_contents(uri, &value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{rollout, FakeCluster};
+    use avx_k8s::{DeploymentInfo, EventInfo, NodeInfo};
+    use serde_json::Value;
+    use tempfile::TempDir;
+
+    /// Reads only `<dir>/config.toml`, where the production profile reaches
+    /// `us-east-1` through the `prod-admin@us-east-1` context.
+    fn loader(dir: &TempDir) -> ConfigLoader {
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[profiles.production]\ncluster = \"us-east-1\"\nkube_context = \"prod-admin@us-east-1\"\n",
+        )
+        .unwrap();
+        ConfigLoader::new()
+            .system_path(None)
+            .user_path(Some(path))
+            .cwd(None)
+            .use_env(false)
+    }
+
+    fn event(namespace: &str, object: &str, last_seen: &str) -> EventInfo {
+        EventInfo {
+            namespace: namespace.to_string(),
+            object: object.to_string(),
+            reason: "BackOff".to_string(),
+            message: "Back-off restarting failed container".to_string(),
+            count: 3,
+            last_seen: Some(last_seen.to_string()),
+        }
+    }
+
+    fn cluster() -> FakeCluster {
+        let deployment = |namespace: &str, name: &str| DeploymentInfo {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            replicas: 2,
+            ready_replicas: 2,
+            updated_replicas: 2,
+            available_replicas: 2,
+            images: vec![format!("avilaops/{}:1.0", name)],
+            revision: Some(3),
+        };
+        FakeCluster {
+            nodes: vec![
+                NodeInfo {
+                    name: "node-a".to_string(),
+                    ready: true,
+                    kubelet_version: Some("v1.30.2".to_string()),
+                },
+                NodeInfo {
+                    name: "node-b".to_string(),
+                    ready: false,
+                    kubelet_version: None,
+                },
+            ],
+            namespaces: vec!["avx-core".to_string(), "kube-system".to_string()],
+            deployments: vec![
+                deployment("avx-core", "avx-gateway"),
+                deployment("kube-system", "coredns"),
+            ],
+            events: vec![
+                event(
+                    "avx-core",
+                    "Pod/avx-gateway-5d9c7-x2x9p",
+                    "2026-10-18T10:00:00Z",
+                ),
+                event(
+                    "avx-core",
+                    "Pod/avx-events-7c4f9-q8zt2",
+                    "2026-10-18T11:00:00Z",
+                ),
+                event(
+                    "kube-system",
+                    "Pod/coredns-7db6d-abcde",
+                    "2026-10-18T09:00:00Z",
+                ),
+            ],
+            ..FakeCluster::with_statuses([rollout(2, 2)])
+        }
+    }
+
+    fn resources(dir: &TempDir) -> ClusterResources {
+        ClusterResources::new()
+            .with_loader(loader(dir))
+            .with_backend(Arc::new(cluster()))
+    }
+
+    async fn read(resources: &ClusterResources, uri: &str) -> Value {
+        let contents = resources.read(uri).await.unwrap();
+        assert_eq!(contents.uri, uri);
+        serde_json::from_str(&contents.text.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_one_resource_per_cluster_of_the_contexts() {
+        let dir = TempDir::new().unwrap();
+        let resources = resources(&dir).with_contexts(vec![
+            "prod-admin@us-east-1".to_string(),
+            "kind-local".to_string(),
+            "arn:aws:eks:eu/dev".to_string(),
+        ]);
+
+        let listed = resources.list().await;
+        let uris: Vec<&str> = listed.iter().map(|r| r.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            [
+                "avx://cluster/us-east-1",
+                "avx://cluster/kind-local",
+                "avx://cluster/arn%3Aaws%3Aeks%3Aeu%2Fdev",
+            ]
+        );
+        assert_eq!(listed[0].name, "us-east-1");
+        assert_eq!(
+            listed[0].description.as_deref(),
+            Some("Kubernetes cluster (kubeconfig context prod-admin@us-east-1)")
+        );
+    }
+
+    #[tokio::test]
+    async fn injected_backend_does_not_list_the_kubeconfig() {
+        let dir = TempDir::new().unwrap();
+        assert!(resources(&dir).list().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cluster_summary_counts_ready_nodes() {
+        let dir = TempDir::new().unwrap();
+        let value = read(&resources(&dir), "avx://cluster/us-east-1").await;
+
+        assert_eq!(value["cluster"], "us-east-1");
+        assert_eq!(value["nodes"], json!({"total": 2, "ready": 1}));
+        assert_eq!(value["namespaces"], json!(["avx-core", "kube-system"]));
+        assert_eq!(value["deployments"].as_array().unwrap().len(), 2);
+        let seen: Vec<&str> = value["warnings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| w["last_seen"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                "2026-10-18T11:00:00Z",
+                "2026-10-18T10:00:00Z",
+                "2026-10-18T09:00:00Z"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn namespace_summary_keeps_its_deployments_and_warnings() {
+        let dir = TempDir::new().unwrap();
+        let value = read(
+            &resources(&dir),
+            "avx://cluster/us-east-1/namespaces/avx-core",
+        )
+        .await;
+
+        assert_eq!(value["namespace"], "avx-core");
+        assert_eq!(value["deployments"][0]["name"], "avx-gateway");
+        assert_eq!(value["deployments"].as_array().unwrap().len(), 1);
+        assert_eq!(value["warnings"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn deployment_detail_keeps_the_warnings_about_its_pods() {
+        let dir = TempDir::new().unwrap();
+        let value = read(
+            &resources(&dir),
+            "avx://cluster/us-east-1/namespaces/avx-core/deployments/avx-gateway",
+        )
+        .await;
+
+        assert_eq!(value["name"], "avx-gateway");
+        assert_eq!(value["images"], json!(["avilaops/avx-gateway:1.0"]));
+        assert_eq!(value["state"], "complete");
+        assert_eq!(
+            value["rollout"],
+            "2/2 updated, 2 ready, 2 available (revision 3)"
+        );
+        // Pod/avx-events-7c4f9-q8zt2 belongs to another Deployment
+        let objects: Vec<&str> = value["warnings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| w["object"].as_str().unwrap())
+            .collect();
+        assert_eq!(objects, ["Pod/avx-gateway-5d9c7-x2x9p"]);
+    }
+
+    #[tokio::test]
+    async fn unknown_objects_are_not_found() {
+        let dir = TempDir::new().unwrap();
+        let resources = resources(&dir);
+
+        for uri in [
+            "avx://cluster/",
+            "avx://cluster/us-east-1/namespaces/missing",
+            "avx://cluster/us-east-1/namespaces/avx-core/deployments/missing",
+            "avx://cluster/us-east-1/nodes",
+        ] {
+            let error = resources.read(uri).await.unwrap_err();
+            assert!(matches!(error, ResourceError::NotFound(_)), "{}", uri);
+        }
+    }
 }
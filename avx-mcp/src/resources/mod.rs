@@ -2,12 +2,139 @@ pub mod aviladb;
 pub mod avx_config;
 pub mod cluster;
 
-use crate::protocol::Resource;
+use std::sync::Arc;
 
-pub fn get_all_resources() -> Vec<Resource> {
+use async_trait::async_trait;
+
+use crate::protocol::{Resource, ResourceContents};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResourceError {
+    #[error("Resource not found: {0}")]
+    NotFound(String),
+    #[error(transparent)]
+    Config(#[from] ::avx_config::AvxConfigError),
+    #[error(transparent)]
+    Cluster(avx_k8s::ClusterError),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// A source of resources that owns every URI starting with [`prefix`].
+///
+/// The server routes `resources/read` to the provider with the longest
+/// matching prefix, so `avx://config/` and `avx://cluster/` can live side by
+/// side.
+///
+/// [`prefix`]: ResourceProvider::prefix
+#[async_trait]
+pub trait ResourceProvider: Send + Sync {
+    /// URI prefix owned by this provider, e.g. `aviladb://`.
+    fn prefix(&self) -> &str;
+
+    /// Resources advertised through `resources/list`.
+    async fn list(&self) -> Vec<Resource>;
+
+    /// Reads a single resource. `uri` always starts with [`Self::prefix`].
+    async fn read(&self, uri: &str) -> Result<ResourceContents, ResourceError>;
+}
+
+impl From<avx_k8s::ClusterError> for ResourceError {
+    fn from(e: avx_k8s::ClusterError) -> Self {
+        match e {
+            avx_k8s::ClusterError::NotFound { kind, name } => {
+                Self::NotFound(format!("{} {}", kind, name))
+            }
+            // no kubeconfig context for the cluster named in the URI
+            e if e.is_unknown_context() => Self::NotFound(e.to_string()),
+            e => Self::Cluster(e),
+        }
+    }
+}
+
+pub fn get_all_resource_providers() -> Vec<Arc<dyn ResourceProvider>> {
+    vec![
+        Arc::new(aviladb::AvilaDbResources),
+        Arc::new(avx_config::AvxConfigResources::new()),
+        Arc::new(cluster::ClusterResources::new()),
+    ]
+}
+
+pub async fn get_all_resources() -> Vec<Resource> {
     let mut resources = Vec::new();
-    resources.extend(aviladb::get_aviladb_resources());
-    resources.extend(avx_config::get_avx_config_resources());
-    resources.extend(cluster::get_cluster_resources());
+    for provider in get_all_resource_providers() {
+        resources.extend(provider.list().await);
+    }
     resources
 }
+
+/// Builds JSON contents for `uri`.
+pub(crate) fn json_contents(uri: &str, value: &serde_json::Value) -> ResourceContents {
+    ResourceContents {
+        uri: uri.to_string(),
+        mime_type: Some("application/json".to_string()),
+        text: Some(serde_json::to_string_pretty(value).unwrap_or_default()),
+        blob: None,
+    }
+}
+
+/// Percent-encodes `segment` so it can be used as one URI path segment
+/// (kubeconfig context names often contain `/` and `:`).
+pub(crate) fn encode_segment(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// Reverses [`encode_segment`]; `None` for malformed escapes.
+pub(crate) fn decode_segment(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_round_trip() {
+        for segment in [
+            "staging-1",
+            "arn:aws:eks:us-east-1:123456789012:cluster/prod",
+            "gke_avila_us-central1_prod",
+            "café cluster",
+            "%41",
+        ] {
+            let encoded = encode_segment(segment);
+            assert!(!encoded.contains(['/', ':', ' ']), "{}", encoded);
+            assert_eq!(decode_segment(&encoded).as_deref(), Some(segment));
+        }
+        assert_eq!(encode_segment("a/b:c"), "a%2Fb%3Ac");
+        assert_eq!(encode_segment("café"), "caf%C3%A9");
+    }
+
+    #[test]
+    fn malformed_escapes_do_not_decode() {
+        for segment in ["%", "%4", "%zz", "%FF"] {
+            assert_eq!(decode_segment(segment), None, "{}", segment);
+        }
+        assert_eq!(decode_segment("plain").as_deref(), Some("plain"));
+    }
+}
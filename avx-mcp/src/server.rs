@@ -1,100 +1,607 @@
 use crate::protocol::*;
+use crate::resources::{ResourceError, ResourceProvider};
+use crate::tools::{ProgressReporter, ToolHandler};
 use anyhow::Result;
-use serde_json::json;
-use std::collections::HashMap;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tokio::time::MissedTickBehavior;
+
+/// How often resource lists are re-read to detect added or removed resources.
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a `tools/call` may run before it is answered with an error
+/// result, unless [`McpServer::set_tool_timeout`] says otherwise.
+pub const DEFAULT_TOOL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Messages whose calls may run at the same time; as many more are queued
+/// before calls are answered with a "server busy" error.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 32;
+
+/// Seconds between resource refreshes, read by [`McpServer::from_env`].
+pub const REFRESH_SECS_ENV_VAR: &str = "AVX_MCP_REFRESH_SECS";
+
+/// Seconds every tool may run, read by [`McpServer::from_env`].
+pub const TOOL_TIMEOUT_SECS_ENV_VAR: &str = "AVX_MCP_TOOL_TIMEOUT_SECS";
+
+/// Per-tool limits as `<tool>=<seconds>,...`, read by [`McpServer::from_env`].
+pub const TOOL_TIMEOUTS_ENV_VAR: &str = "AVX_MCP_TOOL_TIMEOUTS";
+
+/// In-flight bound, read by [`McpServer::from_env`].
+pub const MAX_IN_FLIGHT_ENV_VAR: &str = "AVX_MCP_MAX_IN_FLIGHT";
+
+/// Where the server is in the MCP lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleState {
+    /// Waiting for `initialize`; only `ping` is answered.
+    Uninitialized,
+    /// `initialize` was answered, waiting for `notifications/initialized`.
+    Initializing,
+    /// Serving every request.
+    Ready,
+    /// `shutdown` or `exit` was received, or the input was closed; the
+    /// server stops reading and returns once the requests in flight are
+    /// answered.
+    ShuttingDown,
+}
 
 pub struct McpServer {
-    initialized: bool,
-    resources: Vec<Resource>,
-    tools: Vec<Tool>,
+    state: LifecycleState,
+    /// What the client sent in `initialize`.
+    client: Option<InitializeParams>,
+    /// Version agreed on in `initialize`.
+    protocol_version: Option<&'static str>,
+    handlers: Arc<Handlers>,
+    refresh_interval: Duration,
+    max_in_flight: usize,
+    /// Sorted URIs from the last resource refresh.
+    resource_uris: Vec<String>,
+    /// Cancels the requests being handled, keyed by their JSON id.
+    in_flight: HashMap<String, oneshot::Sender<()>>,
+    /// Messages read while every slot was taken, waiting for one in order.
+    queued: VecDeque<Pending>,
 }
 
 impl McpServer {
     pub fn new() -> Self {
         Self {
-            initialized: false,
-            resources: Vec::new(),
-            tools: Vec::new(),
+            state: LifecycleState::Uninitialized,
+            client: None,
+            protocol_version: None,
+            handlers: Arc::new(Handlers {
+                resources: Vec::new(),
+                tools: Vec::new(),
+                default_tool_timeout: DEFAULT_TOOL_TIMEOUT,
+                tool_timeouts: HashMap::new(),
+            }),
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            resource_uris: Vec::new(),
+            in_flight: HashMap::new(),
+            queued: VecDeque::new(),
         }
     }
 
-    pub fn register_resource(&mut self, resource: Resource) {
-        self.resources.push(resource);
+    /// A server configured from the `AVX_MCP_*` environment variables, see
+    /// [`configure_from_env`](Self::configure_from_env).
+    pub fn from_env() -> Self {
+        let mut server = Self::new();
+        server.configure_from_env();
+        server
     }
 
-    pub fn register_tool(&mut self, tool: Tool) {
-        self.tools.push(tool);
+    /// Applies `AVX_MCP_REFRESH_SECS`, `AVX_MCP_TOOL_TIMEOUT_SECS`,
+    /// `AVX_MCP_TOOL_TIMEOUTS` (e.g. `avx_deploy=1800,avx_query=60`) and
+    /// `AVX_MCP_MAX_IN_FLIGHT`. Unset variables keep the current settings;
+    /// values that are not positive integers are ignored with a warning.
+    pub fn configure_from_env(&mut self) {
+        self.configure(|name| std::env::var(name).ok());
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        tracing::info!("🚀 AVX MCP Server starting...");
+    fn configure(&mut self, var: impl Fn(&str) -> Option<String>) {
+        let secs = |name: &str| {
+            let value = var(name)?;
+            match value.trim().parse::<u64>() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    tracing::warn!("ignoring {}={:?}: expected a positive integer", name, value);
+                    None
+                }
+            }
+        };
 
-        let stdin = tokio::io::stdin();
-        let mut stdout = tokio::io::stdout();
-        let mut reader = BufReader::new(stdin);
-        let mut line = String::new();
+        if let Some(secs) = secs(REFRESH_SECS_ENV_VAR) {
+            self.set_refresh_interval(Duration::from_secs(secs));
+        }
+        if let Some(secs) = secs(TOOL_TIMEOUT_SECS_ENV_VAR) {
+            self.set_default_tool_timeout(Duration::from_secs(secs));
+        }
+        let tool_timeouts = var(TOOL_TIMEOUTS_ENV_VAR).unwrap_or_default();
+        for entry in tool_timeouts.split(',').filter(|e| !e.trim().is_empty()) {
+            let timeout = entry.split_once('=').and_then(|(tool, secs)| {
+                let tool = Some(tool.trim()).filter(|t| !t.is_empty())?;
+                let secs = secs.trim().parse::<u64>().ok().filter(|n| *n > 0)?;
+                Some((tool, secs))
+            });
+            match timeout {
+                Some((tool, secs)) => self.set_tool_timeout(tool, Duration::from_secs(secs)),
+                None => tracing::warn!(
+                    "ignoring {} entry {:?}: expected <tool>=<seconds>",
+                    TOOL_TIMEOUTS_ENV_VAR,
+                    entry
+                ),
+            }
+        }
+        if let Some(max) = secs(MAX_IN_FLIGHT_ENV_VAR) {
+            self.set_max_in_flight(usize::try_from(max).unwrap_or(usize::MAX));
+        }
+    }
 
-        loop {
-            line.clear();
-            let n = reader.read_line(&mut line).await?;
+    /// Sets how often providers are re-listed; a change in the set of URIs
+    /// is announced with `notifications/resources/list_changed`.
+    pub fn set_refresh_interval(&mut self, interval: Duration) {
+        self.refresh_interval = interval;
+    }
 
-            if n == 0 {
-                break; // EOF
-            }
+    /// Sets the time limit of tools without their own
+    /// [`set_tool_timeout`](Self::set_tool_timeout).
+    pub fn set_default_tool_timeout(&mut self, timeout: Duration) {
+        Arc::make_mut(&mut self.handlers).default_tool_timeout = timeout;
+    }
 
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
+    /// Sets the time limit of the tool named `tool`. A call still running
+    /// after it is answered with an `isError` result.
+    pub fn set_tool_timeout(&mut self, tool: impl Into<String>, timeout: Duration) {
+        Arc::make_mut(&mut self.handlers)
+            .tool_timeouts
+            .insert(tool.into(), timeout);
+    }
 
-            tracing::debug!("Received: {}", line);
+    /// Sets how many messages (a request or a batch) may have calls running
+    /// at the same time, at least 1. As many more wait in a queue for one of
+    /// them to be answered; the calls of a message read while the queue is
+    /// full get a `-32000` "server busy" error. Input is read all along, so
+    /// notifications, `ping` and cancellations are never held up.
+    pub fn set_max_in_flight(&mut self, max: usize) {
+        self.max_in_flight = max.max(1);
+    }
 
-            let request: JsonRpcRequest = match serde_json::from_str(line) {
-                Ok(req) => req,
-                Err(e) => {
-                    tracing::error!("Failed to parse request: {}", e);
-                    continue;
+    pub fn state(&self) -> LifecycleState {
+        self.state
+    }
+
+    /// The client's name and version, once `initialize` was received.
+    pub fn client_info(&self) -> Option<&ClientInfo> {
+        self.client.as_ref().map(|c| &c.client_info)
+    }
+
+    /// The client's capabilities, once `initialize` was received.
+    pub fn client_capabilities(&self) -> Option<&ClientCapabilities> {
+        self.client.as_ref().map(|c| &c.capabilities)
+    }
+
+    /// The protocol version negotiated in `initialize`.
+    pub fn protocol_version(&self) -> Option<&'static str> {
+        self.protocol_version
+    }
+
+    /// Registers a resource provider for the URIs under its prefix.
+    pub fn register_resource_provider(&mut self, provider: Arc<dyn ResourceProvider>) {
+        Arc::make_mut(&mut self.handlers).resources.push(provider);
+    }
+
+    /// Registers a tool handler. A handler registered under a name that is
+    /// already taken replaces the previous one.
+    pub fn register_tool(&mut self, handler: Arc<dyn ToolHandler>) {
+        let name = handler.definition().name;
+        let tools = &mut Arc::make_mut(&mut self.handlers).tools;
+        tools.retain(|t| t.definition().name != name);
+        tools.push(handler);
+    }
+
+    /// Serves MCP over stdin/stdout until stdin is closed.
+    pub async fn run(&mut self) -> Result<()> {
+        self.serve(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// Serves newline-delimited JSON-RPC messages read from `reader` until
+    /// EOF, writing responses and notifications to `writer`.
+    ///
+    /// Lifecycle messages, notifications and `ping` are handled as they are
+    /// read; every other request runs on its own task, so a slow tool does
+    /// not hold up the rest, or waits for one to finish past
+    /// [`set_max_in_flight`](Self::set_max_in_flight). This loop is the only
+    /// writer: responses are written whole, in the order they complete.
+    pub async fn serve<R, W>(&mut self, reader: R, mut writer: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        tracing::info!("🚀 AVX MCP Server starting...");
+
+        let mut lines = BufReader::new(reader).lines();
+        let (replies_tx, mut replies) = mpsc::unbounded_channel();
+        // A permit per message with calls running
+        let slots = Arc::new(Semaphore::new(self.max_in_flight));
+
+        self.resource_uris = self.handlers.resource_uris().await;
+        let mut refresh = tokio::time::interval_at(
+            tokio::time::Instant::now() + self.refresh_interval,
+            self.refresh_interval,
+        );
+        refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // Providers are re-listed on their own task, one refresh at a time
+        let (refreshed_tx, mut refreshed) = mpsc::unbounded_channel();
+        let mut refreshing = false;
+
+        while self.state != LifecycleState::ShuttingDown {
+            // `next_line` and `acquire_owned` are cancellation safe, so
+            // nothing is lost when another branch wins. Replies go first:
+            // a task queues its reply before giving its permit back, so
+            // responses are written before a queued message is started.
+            tokio::select! {
+                biased;
+
+                Some(outgoing) = replies.recv() => {
+                    if let Outgoing::Reply(_) = outgoing {
+                        self.in_flight.retain(|_, cancel| !cancel.is_closed());
+                    }
+                    write_message(&mut writer, &outgoing).await?;
                 }
-            };
+                permit = slots.clone().acquire_owned(), if !self.queued.is_empty() => {
+                    if let Some(pending) = self.queued.pop_front() {
+                        self.spawn(pending, permit?, replies_tx.clone());
+                    }
+                }
+                Some(uris) = refreshed.recv() => {
+                    refreshing = false;
+                    if self.update_resource_uris(uris) && self.state == LifecycleState::Ready {
+                        let notification =
+                            JsonRpcNotification::new("notifications/resources/list_changed", None);
+                        write_message(&mut writer, &notification).await?;
+                    }
+                }
+                _ = refresh.tick(), if !refreshing => {
+                    refreshing = true;
+                    let handlers = self.handlers.clone();
+                    let refreshed = refreshed_tx.clone();
+                    tokio::spawn(async move {
+                        // Only fails once `serve` has returned
+                        let _ = refreshed.send(handlers.resource_uris().await);
+                    });
+                }
+                line = lines.next_line() => {
+                    let Some(line) = line? else {
+                        self.state = LifecycleState::ShuttingDown;
+                        break; // EOF
+                    };
 
-            let response = self.handle_request(request).await;
-            let response_json = serde_json::to_string(&response)?;
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
 
-            tracing::debug!("Sending: {}", response_json);
+                    tracing::debug!("Received: {}", line);
 
-            stdout.write_all(response_json.as_bytes()).await?;
-            stdout.write_all(b"\n").await?;
-            stdout.flush().await?;
+                    let mut pending = self.handle_message(line);
+                    // Queued messages whose calls were all cancelled
+                    for cancelled in self.take_cancelled() {
+                        if let Some(reply) = cancelled.into_reply() {
+                            write_message(&mut writer, &reply).await?;
+                        }
+                    }
+                    if !pending.calls.is_empty() {
+                        // Queued messages keep their turn over a free slot
+                        let permit = if self.queued.is_empty() {
+                            slots.clone().try_acquire_owned().ok()
+                        } else {
+                            None
+                        };
+                        match permit {
+                            Some(permit) => self.spawn(pending, permit, replies_tx.clone()),
+                            None if self.queued.len() < self.max_in_flight => {
+                                self.queued.push_back(pending);
+                            }
+                            None => {
+                                tracing::warn!(
+                                    "Server busy: rejecting {} call(s)",
+                                    pending.calls.len()
+                                );
+                                pending.reject_calls();
+                                if let Some(reply) = pending.into_reply() {
+                                    write_message(&mut writer, &reply).await?;
+                                }
+                            }
+                        }
+                    } else if let Some(reply) = pending.into_reply() {
+                        write_message(&mut writer, &reply).await?;
+                    }
+                }
+            }
+        }
+
+        // Answer the requests already read, e.g. the calls batched with `shutdown`
+        while let Some(pending) = self.queued.pop_front() {
+            let permit = slots.clone().acquire_owned().await?;
+            self.spawn(pending, permit, replies_tx.clone());
+        }
+        drop(replies_tx);
+        while let Some(outgoing) = replies.recv().await {
+            write_message(&mut writer, &outgoing).await?;
         }
 
         tracing::info!("👋 AVX MCP Server shutting down");
         Ok(())
     }
 
-    async fn handle_request(&mut self, request: JsonRpcRequest) -> JsonRpcResponse {
-        match request.method.as_str() {
+    /// Handles one line of input: a message or a batch of them. Lifecycle
+    /// messages and notifications are applied right away, in order; the
+    /// other requests are left in [`Pending::calls`].
+    fn handle_message(&mut self, line: &str) -> Pending {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::warn!("Failed to parse message: {}", e);
+                return Pending::error(error_codes::PARSE_ERROR, format!("Parse error: {}", e));
+            }
+        };
+
+        match message {
+            Value::Array(batch) if batch.is_empty() => Pending::error(
+                error_codes::INVALID_REQUEST,
+                "Invalid request: empty batch".to_string(),
+            ),
+            Value::Array(batch) => {
+                let mut pending = Pending::new(true);
+                for message in batch {
+                    self.accept(Message::from_value(message), &mut pending);
+                }
+                pending
+            }
+            message => {
+                let mut pending = Pending::new(false);
+                self.accept(Message::from_value(message), &mut pending);
+                pending
+            }
+        }
+    }
+
+    fn accept(&mut self, message: Message, pending: &mut Pending) {
+        let request = match message {
+            Message::Request(request) => request,
+            Message::Notification(notification) => return self.handle_notification(notification),
+            Message::Invalid(response) => return pending.responses.push(Some(response)),
+        };
+        let response = match request.method.as_str() {
             "initialize" => self.handle_initialize(request),
             "initialized" => self.handle_initialized(request),
-            "resources/list" => self.handle_resources_list(request),
-            "resources/read" => self.handle_resources_read(request),
-            "tools/list" => self.handle_tools_list(request),
-            "tools/call" => self.handle_tools_call(request).await,
-            _ => JsonRpcResponse::error(
-                request.id,
-                -32601,
-                format!("Method not found: {}", request.method),
-            ),
+            "shutdown" => self.handle_shutdown(request),
+            "ping" => JsonRpcResponse::success(request.id, json!({})),
+            _ if self.state != LifecycleState::Ready => self.state_error(request.id),
+            _ => {
+                pending.calls.push((pending.responses.len(), request));
+                pending.responses.push(None);
+                return;
+            }
+        };
+        pending.responses.push(Some(response));
+    }
+
+    /// Runs the calls of `pending` concurrently on a task holding `permit`,
+    /// each one cancellable through `notifications/cancelled`; the reply is
+    /// sent to `replies` once they are all done, after any progress
+    /// notification the calls sent there.
+    fn spawn(
+        &mut self,
+        pending: Pending,
+        permit: OwnedSemaphorePermit,
+        replies: mpsc::UnboundedSender<Outgoing>,
+    ) {
+        let Pending {
+            batch,
+            mut responses,
+            calls,
+        } = pending;
+        let calls: Vec<_> = calls
+            .into_iter()
+            .map(|(index, request)| {
+                let (cancel, cancelled) = oneshot::channel();
+                let id = request_key(&request.id);
+                if self.in_flight.insert(id.clone(), cancel).is_some() {
+                    tracing::warn!(
+                        "Request id {} reused while in flight; only the last one can be cancelled",
+                        id
+                    );
+                }
+                (index, request, cancelled)
+            })
+            .collect();
+
+        let handlers = self.handlers.clone();
+        tokio::spawn(async move {
+            let (handlers, replies) = (&handlers, &replies);
+            let runs = calls
+                .into_iter()
+                .map(|(index, request, cancelled)| async move {
+                    let id = request_key(&request.id);
+                    tokio::select! {
+                        response = handlers.call(request, replies) => Some((index, response)),
+                        // A dropped sender (id reused) is not a cancellation
+                        Ok(()) = cancelled => {
+                            tracing::info!("🛑 Request {} cancelled", id);
+                            None
+                        }
+                    }
+                });
+            // Cancelled requests keep an empty slot and get no response
+            for (index, response) in futures::future::join_all(runs).await.into_iter().flatten() {
+                responses[index] = Some(response);
+            }
+
+            let pending = Pending {
+                batch,
+                responses,
+                calls: Vec::new(),
+            };
+            if let Some(reply) = pending.into_reply() {
+                // Only fails once `serve` has returned
+                let _ = replies.send(Outgoing::Reply(reply));
+            }
+            // Given back only once the reply is queued, see `serve`
+            drop(permit);
+        });
+    }
+
+    fn handle_notification(&mut self, notification: JsonRpcRequest) {
+        match notification.method.as_str() {
+            "notifications/initialized" | "initialized" => {
+                self.mark_ready();
+            }
+            "exit" => {
+                tracing::info!("Exit requested by the client");
+                self.state = LifecycleState::ShuttingDown;
+            }
+            "notifications/cancelled" => {
+                let params = notification.params.unwrap_or_default();
+                let request_id = params.get("requestId").cloned().unwrap_or_default();
+                let reason = params
+                    .get("reason")
+                    .and_then(Value::as_str)
+                    .unwrap_or("no reason given");
+                let key = request_key(&Some(request_id.clone()));
+                match self.in_flight.remove(&key) {
+                    Some(cancel) => {
+                        tracing::info!("Cancelling request {} ({})", request_id, reason);
+                        let _ = cancel.send(());
+                    }
+                    None if self.dequeue(&key) => {
+                        tracing::info!("Cancelling queued request {} ({})", request_id, reason);
+                    }
+                    None => tracing::debug!(
+                        "Ignoring cancellation of request {} ({}): not in flight",
+                        request_id,
+                        reason
+                    ),
+                }
+            }
+            method => tracing::debug!("Ignoring notification {}", method),
         }
     }
 
+    /// Drops the queued call with request key `key`; returns whether there
+    /// was one. Like a cancelled running call, it gets no response.
+    fn dequeue(&mut self, key: &str) -> bool {
+        for pending in &mut self.queued {
+            if let Some(at) = pending
+                .calls
+                .iter()
+                .position(|(_, request)| request_key(&request.id) == key)
+            {
+                pending.calls.remove(at);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Removes the queued messages left without calls by [`dequeue`], whose
+    /// other responses can be written right away.
+    ///
+    /// [`dequeue`]: Self::dequeue
+    fn take_cancelled(&mut self) -> Vec<Pending> {
+        let (cancelled, queued): (Vec<_>, Vec<_>) = std::mem::take(&mut self.queued)
+            .into_iter()
+            .partition(|pending| pending.calls.is_empty());
+        self.queued = queued.into();
+        cancelled
+    }
+
+    /// Keeps the URIs of a refresh; returns whether the set of URIs changed.
+    fn update_resource_uris(&mut self, uris: Vec<String>) -> bool {
+        if uris == self.resource_uris {
+            return false;
+        }
+        tracing::info!("🔄 Resource list changed ({} resources)", uris.len());
+        self.resource_uris = uris;
+        true
+    }
+
+    /// Moves from `Initializing` to `Ready`; returns whether the server is
+    /// ready afterwards.
+    fn mark_ready(&mut self) -> bool {
+        match self.state {
+            LifecycleState::Initializing => {
+                self.state = LifecycleState::Ready;
+                tracing::info!("✅ MCP Server initialized");
+                true
+            }
+            LifecycleState::Ready => true,
+            state => {
+                tracing::warn!("Ignoring initialized notification in state {:?}", state);
+                false
+            }
+        }
+    }
+
+    /// The error for a request the current state does not accept: anything
+    /// but `ping` before `Ready`, `initialize` after `Uninitialized`.
+    fn state_error(&self, id: Option<Value>) -> JsonRpcResponse {
+        let message = match self.state {
+            LifecycleState::Uninitialized => "Server not initialized: send initialize first",
+            LifecycleState::Initializing => {
+                "Server not initialized: waiting for notifications/initialized"
+            }
+            LifecycleState::Ready => "Server already initialized",
+            LifecycleState::ShuttingDown => "Server is shutting down",
+        };
+        JsonRpcResponse::error(id, error_codes::INVALID_REQUEST, message.to_string())
+    }
+
     fn handle_initialize(&mut self, request: JsonRpcRequest) -> JsonRpcResponse {
+        if self.state != LifecycleState::Uninitialized {
+            return self.state_error(request.id);
+        }
+
+        let params: InitializeParams = match request.params.map(serde_json::from_value) {
+            Some(Ok(params)) => params,
+            Some(Err(e)) => {
+                return JsonRpcResponse::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid params: {}", e),
+                )
+            }
+            None => {
+                return JsonRpcResponse::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    "Missing params".to_string(),
+                )
+            }
+        };
+
+        let protocol_version = negotiate_protocol_version(&params.protocol_version);
+        tracing::info!(
+            "🤝 Client {} {} (protocol {}, requested {})",
+            params.client_info.name,
+            params.client_info.version,
+            protocol_version,
+            params.protocol_version
+        );
+        self.protocol_version = Some(protocol_version);
+        self.client = Some(params);
+        self.state = LifecycleState::Initializing;
+
         let result = InitializeResult {
-            protocol_version: PROTOCOL_VERSION.to_string(),
+            protocol_version: protocol_version.to_string(),
             capabilities: ServerCapabilities {
                 resources: Some(ResourceCapabilities {
                     subscribe: Some(false),
-                    list_changed: Some(false),
+                    list_changed: Some(true),
                 }),
                 tools: Some(ToolCapabilities {
                     list_changed: Some(false),
@@ -110,108 +617,335 @@ impl McpServer {
         JsonRpcResponse::success(request.id, json!(result))
     }
 
+    /// `initialized` sent as a request by older clients.
     fn handle_initialized(&mut self, request: JsonRpcRequest) -> JsonRpcResponse {
-        self.initialized = true;
-        tracing::info!("✅ MCP Server initialized");
+        if self.mark_ready() {
+            JsonRpcResponse::success(request.id, json!({}))
+        } else {
+            self.state_error(request.id)
+        }
+    }
+
+    /// Answers `shutdown`, after which the server stops reading input.
+    fn handle_shutdown(&mut self, request: JsonRpcRequest) -> JsonRpcResponse {
+        tracing::info!("Shutdown requested by the client");
+        self.state = LifecycleState::ShuttingDown;
         JsonRpcResponse::success(request.id, json!({}))
     }
+}
+
+/// What request tasks share: the registered providers and tools.
+#[derive(Clone)]
+struct Handlers {
+    resources: Vec<Arc<dyn ResourceProvider>>,
+    tools: Vec<Arc<dyn ToolHandler>>,
+    default_tool_timeout: Duration,
+    tool_timeouts: HashMap<String, Duration>,
+}
+
+impl Handlers {
+    /// Sorted URIs of every provider.
+    async fn resource_uris(&self) -> Vec<String> {
+        let mut uris = Vec::new();
+        for provider in &self.resources {
+            uris.extend(provider.list().await.into_iter().map(|r| r.uri));
+        }
+        uris.sort();
+        uris.dedup();
+        uris
+    }
+
+    /// Handles a request that needs a `Ready` server; progress
+    /// notifications go to `outgoing`.
+    async fn call(
+        &self,
+        request: JsonRpcRequest,
+        outgoing: &mpsc::UnboundedSender<Outgoing>,
+    ) -> JsonRpcResponse {
+        match request.method.as_str() {
+            "resources/list" => self.handle_resources_list(request).await,
+            "resources/read" => self.handle_resources_read(request).await,
+            "tools/list" => self.handle_tools_list(request),
+            "tools/call" => self.handle_tools_call(request, outgoing).await,
+            _ => JsonRpcResponse::error(
+                request.id,
+                error_codes::METHOD_NOT_FOUND,
+                format!("Method not found: {}", request.method),
+            ),
+        }
+    }
+
+    async fn handle_resources_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let mut resources = Vec::new();
+        for provider in &self.resources {
+            resources.extend(provider.list().await);
+        }
 
-    fn handle_resources_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
         let result = json!({
-            "resources": self.resources
+            "resources": resources
         });
         JsonRpcResponse::success(request.id, result)
     }
 
-    fn handle_resources_read(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+    async fn handle_resources_read(&self, request: JsonRpcRequest) -> JsonRpcResponse {
         let params: HashMap<String, serde_json::Value> = match request.params {
             Some(p) => serde_json::from_value(p).unwrap_or_default(),
             None => HashMap::new(),
         };
 
-        let uri = params
-            .get("uri")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-
-        // Mock resource content
-        let contents = ResourceContents {
-            uri: uri.to_string(),
-            mime_type: Some("application/json".to_string()),
-            text: Some(json!({"status": "ok", "uri": uri}).to_string()),
-            blob: None,
+        let uri = match params.get("uri").and_then(|v| v.as_str()) {
+            Some(uri) => uri,
+            None => {
+                return JsonRpcResponse::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    "Missing uri".to_string(),
+                )
+            }
         };
 
-        let result = json!({
-            "contents": [contents]
-        });
+        let provider = self
+            .resources
+            .iter()
+            .filter(|p| uri.starts_with(p.prefix()))
+            .max_by_key(|p| p.prefix().len());
 
-        JsonRpcResponse::success(request.id, result)
+        let read = match provider {
+            Some(provider) => provider.read(uri).await,
+            None => Err(ResourceError::NotFound(uri.to_string())),
+        };
+
+        match read {
+            Ok(contents) => JsonRpcResponse::success(
+                request.id,
+                json!({
+                    "contents": [contents]
+                }),
+            ),
+            Err(e @ ResourceError::NotFound(_)) => {
+                JsonRpcResponse::error(request.id, error_codes::RESOURCE_NOT_FOUND, e.to_string())
+            }
+            Err(e) => JsonRpcResponse::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                crate::error_chain(&e),
+            ),
+        }
     }
 
     fn handle_tools_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let tools: Vec<Tool> = self.tools.iter().map(|t| t.definition()).collect();
         let result = json!({
-            "tools": self.tools
+            "tools": tools
         });
         JsonRpcResponse::success(request.id, result)
     }
 
-    async fn handle_tools_call(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+    async fn handle_tools_call(
+        &self,
+        request: JsonRpcRequest,
+        outgoing: &mpsc::UnboundedSender<Outgoing>,
+    ) -> JsonRpcResponse {
         let params: CallToolParams = match request.params {
             Some(p) => match serde_json::from_value(p) {
                 Ok(params) => params,
                 Err(e) => {
                     return JsonRpcResponse::error(
                         request.id,
-                        -32602,
+                        error_codes::INVALID_PARAMS,
                         format!("Invalid params: {}", e),
                     )
                 }
             },
             None => {
-                return JsonRpcResponse::error(request.id, -32602, "Missing params".to_string())
+                return JsonRpcResponse::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    "Missing params".to_string(),
+                )
             }
         };
 
-        // Execute tool based on name
-        let result_text = match params.name.as_str() {
-            "avx_query" => self.execute_query(&params.arguments).await,
-            "avx_deploy" => self.execute_deploy(&params.arguments).await,
-            "avx_telemetry" => self.execute_telemetry(&params.arguments).await,
-            _ => format!("Unknown tool: {}", params.name),
+        let handler = match self
+            .tools
+            .iter()
+            .find(|t| t.definition().name == params.name)
+        {
+            Some(handler) => handler,
+            None => {
+                return JsonRpcResponse::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Unknown tool: {}", params.name),
+                )
+            }
         };
 
-        let result = CallToolResult {
-            content: vec![ToolContent::Text { text: result_text }],
-            is_error: Some(false),
+        let timeout = self
+            .tool_timeouts
+            .get(&params.name)
+            .copied()
+            .unwrap_or(self.default_tool_timeout);
+        let progress = match params.meta.and_then(|meta| meta.progress_token) {
+            Some(token) => ProgressReporter::new(token, outgoing.clone()),
+            None => ProgressReporter::disabled(),
         };
+        let call = handler.call_with_progress(params.arguments, progress.clone());
+        let result = match tokio::time::timeout(timeout, call).await {
+            Ok(result) => result,
+            Err(_) => {
+                tracing::warn!("⏱️ Tool {} timed out after {:?}", params.name, timeout);
+                CallToolResult::error(format!("{} timed out after {:?}", params.name, timeout))
+            }
+        };
+        progress.finish();
 
         JsonRpcResponse::success(request.id, json!(result))
     }
+}
+
+/// What request tasks hand to the loop in [`McpServer::serve`] to write.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(crate) enum Outgoing {
+    Reply(Reply),
+    /// E.g. `notifications/progress` from a [`ProgressReporter`].
+    Notification(JsonRpcNotification),
+}
+
+/// What is written back for one line of input.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(crate) enum Reply {
+    Single(JsonRpcResponse),
+    Batch(Vec<JsonRpcResponse>),
+}
+
+/// What one line of input turned into: the responses known right away, and
+/// the calls that still have to run, each with an empty slot in `responses`.
+struct Pending {
+    batch: bool,
+    responses: Vec<Option<JsonRpcResponse>>,
+    calls: Vec<(usize, JsonRpcRequest)>,
+}
+
+impl Pending {
+    fn new(batch: bool) -> Self {
+        Self {
+            batch,
+            responses: Vec::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    /// A single error response with a `null` id.
+    fn error(code: i32, message: String) -> Self {
+        Self {
+            batch: false,
+            responses: vec![Some(JsonRpcResponse::error(
+                Some(Value::Null),
+                code,
+                message,
+            ))],
+            calls: Vec::new(),
+        }
+    }
 
-    async fn execute_query(&self, args: &HashMap<String, serde_json::Value>) -> String {
-        let query = args
-            .get("query")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-        format!("🔍 Executed query: {}", query)
+    /// Answers every call with a "server busy" error instead of running it.
+    fn reject_calls(&mut self) {
+        for (index, request) in self.calls.drain(..) {
+            self.responses[index] = Some(JsonRpcResponse::error(
+                request.id,
+                error_codes::SERVER_BUSY,
+                "Server busy: too many requests in flight, retry later".to_string(),
+            ));
+        }
     }
 
-    async fn execute_deploy(&self, args: &HashMap<String, serde_json::Value>) -> String {
-        let service = args
-            .get("service")
-            .and_then(|v| v.as_str())
-            .unwrap_or("unknown");
-        format!("🚀 Deployed service: {}", service)
+    /// The filled slots; `None` when there is nothing to answer, as for a
+    /// batch of notifications.
+    fn into_reply(self) -> Option<Reply> {
+        let mut responses: Vec<JsonRpcResponse> = self.responses.into_iter().flatten().collect();
+        if self.batch {
+            (!responses.is_empty()).then_some(Reply::Batch(responses))
+        } else {
+            responses.pop().map(Reply::Single)
+        }
     }
+}
+
+/// One element of the input, checked with [`parse_request`].
+enum Message {
+    Request(JsonRpcRequest),
+    Notification(JsonRpcRequest),
+    /// The `-32600` response for a message that is not a valid request.
+    Invalid(JsonRpcResponse),
+}
 
-    async fn execute_telemetry(&self, args: &HashMap<String, serde_json::Value>) -> String {
-        let metric = args
-            .get("metric")
-            .and_then(|v| v.as_str())
-            .unwrap_or("cpu");
-        format!("📊 Telemetry for {}: 42%", metric)
+impl Message {
+    fn from_value(message: Value) -> Self {
+        // Echoed in the error response when it is valid, `null` otherwise
+        let id = message
+            .get("id")
+            .filter(|id| id.is_string() || id.is_number())
+            .cloned()
+            .unwrap_or(Value::Null);
+        match parse_request(message) {
+            Ok(request) if request.id.is_some() => Message::Request(request),
+            Ok(notification) => Message::Notification(notification),
+            Err(reason) => Message::Invalid(JsonRpcResponse::error(
+                Some(id),
+                error_codes::INVALID_REQUEST,
+                format!("Invalid request: {}", reason),
+            )),
+        }
+    }
+}
+
+/// Key of a request in [`McpServer`]'s in-flight map: its id as JSON text,
+/// so `1` and `"1"` stay apart.
+fn request_key(id: &Option<Value>) -> String {
+    id.as_ref().unwrap_or(&Value::Null).to_string()
+}
+
+/// Checks the shape of a JSON-RPC 2.0 request or notification; the error is
+/// the reason it is invalid.
+fn parse_request(message: Value) -> Result<JsonRpcRequest, String> {
+    let Some(object) = message.as_object() else {
+        return Err("expected a JSON object".to_string());
+    };
+    if object.get("jsonrpc").and_then(Value::as_str) != Some(JSONRPC_VERSION) {
+        return Err("jsonrpc must be \"2.0\"".to_string());
+    }
+    if !object.get("method").is_some_and(Value::is_string) {
+        return Err("method must be a string".to_string());
     }
+    match object.get("id") {
+        None | Some(Value::String(_) | Value::Number(_)) => {}
+        // Plain JSON-RPC allows a null id, MCP does not
+        Some(_) => return Err("id must be a string or a number".to_string()),
+    }
+    if object
+        .get("params")
+        .is_some_and(|p| !p.is_object() && !p.is_array())
+    {
+        return Err("params must be an object or an array".to_string());
+    }
+
+    serde_json::from_value(message).map_err(|e| e.to_string())
+}
+
+/// Writes `message` as one line of JSON.
+async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &impl Serialize,
+) -> Result<()> {
+    let json = serde_json::to_string(message)?;
+    tracing::debug!("Sending: {}", json);
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+    Ok(())
 }
 
 impl Default for McpServer {
@@ -219,3 +953,242 @@ impl Default for McpServer {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// A tool named `name` answering `text`.
+    struct Named(&'static str, &'static str);
+
+    #[async_trait]
+    impl ToolHandler for Named {
+        fn definition(&self) -> Tool {
+            Tool {
+                name: self.0.to_string(),
+                description: format!("Answers {}", self.1),
+                input_schema: json!({"type": "object"}),
+            }
+        }
+
+        async fn call(&self, _args: HashMap<String, Value>) -> CallToolResult {
+            CallToolResult::text(self.1)
+        }
+    }
+
+    /// Owns `prefix`; reads answer with the prefix, except `<prefix>missing`
+    /// (not found) and `<prefix>broken` (a failure with a cause).
+    struct Prefixed(&'static str);
+
+    #[async_trait]
+    impl ResourceProvider for Prefixed {
+        fn prefix(&self) -> &str {
+            self.0
+        }
+
+        async fn list(&self) -> Vec<Resource> {
+            vec![Resource {
+                uri: format!("{}item", self.0),
+                name: self.0.to_string(),
+                description: None,
+                mime_type: None,
+            }]
+        }
+
+        async fn read(&self, uri: &str) -> Result<ResourceContents, ResourceError> {
+            match uri.strip_prefix(self.0) {
+                Some("missing") => Err(ResourceError::NotFound(uri.to_string())),
+                Some("broken") => Err(ResourceError::Other(
+                    anyhow::anyhow!("connection refused").context("backend unavailable"),
+                )),
+                _ => Ok(ResourceContents {
+                    uri: uri.to_string(),
+                    mime_type: None,
+                    text: Some(self.0.to_string()),
+                    blob: None,
+                }),
+            }
+        }
+    }
+
+    async fn request(server: &McpServer, method: &str, params: Value) -> JsonRpcResponse {
+        let (outgoing, _) = mpsc::unbounded_channel();
+        let request = JsonRpcRequest {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: Some(json!(1)),
+            method: method.to_string(),
+            params: Some(params),
+        };
+        server.handlers.call(request, &outgoing).await
+    }
+
+    fn result_text(response: &JsonRpcResponse) -> &str {
+        response.result.as_ref().unwrap()["content"][0]["text"]
+            .as_str()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn tools_call_is_dispatched_by_name() {
+        let mut server = McpServer::new();
+        server.register_tool(Arc::new(Named("first", "one")));
+        server.register_tool(Arc::new(Named("second", "two")));
+
+        let response = request(&server, "tools/call", json!({"name": "second"})).await;
+        assert_eq!(result_text(&response), "two");
+        let response = request(&server, "tools/call", json!({"name": "first"})).await;
+        assert_eq!(result_text(&response), "one");
+    }
+
+    #[tokio::test]
+    async fn registering_a_name_again_replaces_the_tool() {
+        let mut server = McpServer::new();
+        server.register_tool(Arc::new(Named("tool", "old")));
+        server.register_tool(Arc::new(Named("tool", "new")));
+
+        let response = request(&server, "tools/list", json!({})).await;
+        let tools = response.result.unwrap()["tools"].clone();
+        assert_eq!(tools.as_array().unwrap().len(), 1);
+        assert_eq!(tools[0]["description"], "Answers new");
+        let response = request(&server, "tools/call", json!({"name": "tool"})).await;
+        assert_eq!(result_text(&response), "new");
+    }
+
+    #[tokio::test]
+    async fn unknown_tools_and_bad_params_are_invalid_params() {
+        let mut server = McpServer::new();
+        server.register_tool(Arc::new(Named("tool", "ok")));
+
+        for params in [
+            json!({"name": "missing"}),
+            json!({"arguments": {}}),
+            json!(7),
+        ] {
+            let response = request(&server, "tools/call", params.clone()).await;
+            let error = response.error.expect("an error response");
+            assert_eq!(error.code, error_codes::INVALID_PARAMS, "{}", params);
+        }
+    }
+
+    fn configured(vars: &[(&str, &str)]) -> McpServer {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let mut server = McpServer::new();
+        server.configure(|name| vars.get(name).cloned());
+        server
+    }
+
+    fn routed_server() -> McpServer {
+        let mut server = McpServer::new();
+        server.register_resource_provider(Arc::new(Prefixed("avx://")));
+        server.register_resource_provider(Arc::new(Prefixed("avx://config/")));
+        server.register_resource_provider(Arc::new(Prefixed("aviladb://")));
+        server
+    }
+
+    async fn read(server: &McpServer, uri: &str) -> JsonRpcResponse {
+        request(server, "resources/read", json!({ "uri": uri })).await
+    }
+
+    #[tokio::test]
+    async fn resources_list_gathers_every_provider() {
+        let response = request(&routed_server(), "resources/list", json!({})).await;
+        let uris: Vec<Value> = response.result.unwrap()["resources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["uri"].clone())
+            .collect();
+        assert_eq!(
+            uris,
+            [
+                json!("avx://item"),
+                json!("avx://config/item"),
+                json!("aviladb://item")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn resources_read_goes_to_the_longest_matching_prefix() {
+        let server = routed_server();
+        for (uri, provider) in [
+            ("avx://config/env", "avx://config/"),
+            ("avx://cluster/local", "avx://"),
+            ("aviladb://production/users", "aviladb://"),
+        ] {
+            let response = read(&server, uri).await;
+            let contents = &response.result.unwrap()["contents"][0];
+            assert_eq!(contents["uri"], uri);
+            assert_eq!(contents["text"], provider, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn resources_read_errors() {
+        let server = routed_server();
+        let error = |response: JsonRpcResponse| {
+            let error = response.error.expect("an error response");
+            (error.code, error.message)
+        };
+
+        let (code, message) = error(read(&server, "s3://bucket/key").await);
+        assert_eq!(code, error_codes::RESOURCE_NOT_FOUND);
+        assert_eq!(message, "Resource not found: s3://bucket/key");
+        let (code, _) = error(read(&server, "avx://config/missing").await);
+        assert_eq!(code, error_codes::RESOURCE_NOT_FOUND);
+        let (code, message) = error(read(&server, "avx://broken").await);
+        assert_eq!(code, error_codes::INTERNAL_ERROR);
+        assert_eq!(message, "backend unavailable: connection refused");
+        let (code, _) = error(request(&server, "resources/read", json!({})).await);
+        assert_eq!(code, error_codes::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn environment_sets_limits_and_timeouts() {
+        let server = configured(&[
+            (REFRESH_SECS_ENV_VAR, "5"),
+            (TOOL_TIMEOUT_SECS_ENV_VAR, "300"),
+            (TOOL_TIMEOUTS_ENV_VAR, "avx_deploy=1800, avx_query = 60"),
+            (MAX_IN_FLIGHT_ENV_VAR, "8"),
+        ]);
+
+        assert_eq!(server.refresh_interval, Duration::from_secs(5));
+        assert_eq!(server.max_in_flight, 8);
+        assert_eq!(
+            server.handlers.default_tool_timeout,
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            server.handlers.tool_timeouts,
+            HashMap::from([
+                ("avx_deploy".to_string(), Duration::from_secs(1800)),
+                ("avx_query".to_string(), Duration::from_secs(60)),
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_values_keep_the_defaults() {
+        let server = configured(&[
+            (REFRESH_SECS_ENV_VAR, "0"),
+            (TOOL_TIMEOUT_SECS_ENV_VAR, "ten"),
+            (
+                TOOL_TIMEOUTS_ENV_VAR,
+                "avx_deploy,avx_query=-1,=5,avx_telemetry=30",
+            ),
+            (MAX_IN_FLIGHT_ENV_VAR, ""),
+        ]);
+
+        assert_eq!(server.refresh_interval, DEFAULT_REFRESH_INTERVAL);
+        assert_eq!(server.max_in_flight, DEFAULT_MAX_IN_FLIGHT);
+        assert_eq!(server.handlers.default_tool_timeout, DEFAULT_TOOL_TIMEOUT);
+        assert_eq!(
+            server.handlers.tool_timeouts,
+            HashMap::from([("avx_telemetry".to_string(), Duration::from_secs(30))])
+        );
+    }
+}
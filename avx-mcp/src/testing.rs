@@ -0,0 +1,138 @@
+//! In-memory [`ClusterBackend`] shared by the deploy tool and cluster
+//! resource tests.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use avx_k8s::{
+    AppliedObject, ClusterBackend, ClusterError, DeploymentInfo, EventInfo, NodeInfo, Rollback,
+    RolloutStatus,
+};
+use serde_json::Value;
+
+/// Answers `rollout_status` with the queued statuses in order, repeating the
+/// last one, and records every `apply` and `rollback` call.
+#[derive(Default)]
+pub(crate) struct FakeCluster {
+    pub statuses: Mutex<VecDeque<RolloutStatus>>,
+    pub polls: Mutex<usize>,
+    /// Objects and `dry_run` of each `apply`.
+    pub applied: Mutex<Vec<(Vec<Value>, bool)>>,
+    /// Requested revision and `dry_run` of each `rollback`.
+    pub rollbacks: Mutex<Vec<(Option<i64>, bool)>>,
+    pub nodes: Vec<NodeInfo>,
+    pub namespaces: Vec<String>,
+    pub deployments: Vec<DeploymentInfo>,
+    pub events: Vec<EventInfo>,
+}
+
+impl FakeCluster {
+    pub fn with_statuses(statuses: impl IntoIterator<Item = RolloutStatus>) -> Self {
+        Self {
+            statuses: Mutex::new(statuses.into_iter().collect()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Generation 1 with `updated` of `replicas` updated, all of them available.
+pub(crate) fn rollout(replicas: i32, updated: i32) -> RolloutStatus {
+    RolloutStatus {
+        generation: 1,
+        observed_generation: 1,
+        replicas,
+        total_replicas: replicas,
+        updated_replicas: updated,
+        ready_replicas: updated,
+        available_replicas: updated,
+        revision: Some(3),
+        failure: None,
+    }
+}
+
+#[async_trait]
+impl ClusterBackend for FakeCluster {
+    async fn apply(
+        &self,
+        objects: &[Value],
+        dry_run: bool,
+    ) -> Result<Vec<AppliedObject>, ClusterError> {
+        self.applied
+            .lock()
+            .unwrap()
+            .push((objects.to_vec(), dry_run));
+        Ok(objects
+            .iter()
+            .map(|object| AppliedObject {
+                kind: object["kind"].as_str().unwrap_or_default().to_string(),
+                name: object["metadata"]["name"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                namespace: object["metadata"]["namespace"].as_str().map(str::to_string),
+            })
+            .collect())
+    }
+
+    async fn rollout_status(
+        &self,
+        _namespace: &str,
+        _name: &str,
+    ) -> Result<RolloutStatus, ClusterError> {
+        *self.polls.lock().unwrap() += 1;
+        let mut statuses = self.statuses.lock().unwrap();
+        let status = if statuses.len() > 1 {
+            statuses.pop_front()
+        } else {
+            statuses.front().cloned()
+        };
+        Ok(status.unwrap_or_default())
+    }
+
+    async fn rollback(
+        &self,
+        _namespace: &str,
+        _name: &str,
+        revision: Option<i64>,
+        dry_run: bool,
+    ) -> Result<Rollback, ClusterError> {
+        self.rollbacks.lock().unwrap().push((revision, dry_run));
+        Ok(Rollback {
+            from_revision: Some(3),
+            to_revision: revision.unwrap_or(2),
+        })
+    }
+
+    async fn nodes(&self) -> Result<Vec<NodeInfo>, ClusterError> {
+        Ok(self.nodes.clone())
+    }
+
+    async fn namespaces(&self) -> Result<Vec<String>, ClusterError> {
+        Ok(self.namespaces.clone())
+    }
+
+    async fn deployments(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<DeploymentInfo>, ClusterError> {
+        Ok(self
+            .deployments
+            .iter()
+            .filter(|d| namespace.is_none_or(|ns| d.namespace == ns))
+            .cloned()
+            .collect())
+    }
+
+    async fn warning_events(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<EventInfo>, ClusterError> {
+        Ok(self
+            .events
+            .iter()
+            .filter(|e| namespace.is_none_or(|ns| e.namespace == ns))
+            .cloned()
+            .collect())
+    }
+}
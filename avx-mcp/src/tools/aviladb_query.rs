@@ -1,5 +1,17 @@
-use crate::protocol::Tool;
-use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use super::{ProgressReporter, ToolHandler};
+use crate::protocol::{CallToolResult, Tool, ToolContent};
+
+/// Documents requested per [`QueryBackend::query`] call.
+pub const PAGE_SIZE: usize = 100;
+
+/// `limit` when the arguments do not set it.
+const DEFAULT_LIMIT: u64 = 100;
 
 pub fn get_query_tool() -> Tool {
     Tool {
@@ -22,7 +34,7 @@ pub fn get_query_tool() -> Tool {
                 },
                 "limit": {
                     "type": "number",
-                    "description": "Maximum number of results",
+                    "description": "Maximum number of results, fetched in pages of 100",
                     "default": 100
                 }
             },
@@ -30,3 +42,139 @@ pub fn get_query_tool() -> Tool {
         }),
     }
 }
+
+/// One page of documents from [`QueryBackend::query`].
+#[derive(Debug, Clone, Default)]
+pub struct QueryPage {
+    pub documents: Vec<Value>,
+    /// Passed back to fetch the next page; `None` on the last one.
+    pub next_cursor: Option<String>,
+    /// Documents matching the filter, when the backend knows.
+    pub total: Option<u64>,
+}
+
+/// Where `avx_query` reads documents from.
+#[async_trait]
+pub trait QueryBackend: Send + Sync {
+    /// Fetches up to `page_size` documents of `database/collection` matching
+    /// `filter`, from `cursor` on (the first page when `None`).
+    async fn query(
+        &self,
+        database: &str,
+        collection: &str,
+        filter: &Value,
+        cursor: Option<&str>,
+        page_size: usize,
+    ) -> anyhow::Result<QueryPage>;
+}
+
+#[derive(Default)]
+pub struct QueryTool {
+    backend: Option<Arc<dyn QueryBackend>>,
+}
+
+impl QueryTool {
+    /// Without a backend the tool only echoes the query, as there is no
+    /// AvilaDB client yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs queries against `backend`, a page at a time.
+    pub fn with_backend(mut self, backend: Arc<dyn QueryBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Pages through the results until `limit` documents were fetched or the
+    /// backend has no more, reporting each page as progress.
+    async fn run(
+        &self,
+        backend: &dyn QueryBackend,
+        args: &HashMap<String, Value>,
+        progress: &ProgressReporter,
+    ) -> Result<CallToolResult, String> {
+        let str_arg = |name: &str| args.get(name).and_then(Value::as_str).unwrap_or_default();
+        let (database, collection) = (str_arg("database"), str_arg("collection"));
+        let filter: Value = serde_json::from_str(str_arg("query"))
+            .map_err(|e| format!("invalid query filter: {}", e))?;
+        let limit = args
+            .get("limit")
+            .and_then(Value::as_u64)
+            .unwrap_or(DEFAULT_LIMIT);
+        let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+
+        let mut documents: Vec<Value> = Vec::new();
+        let mut cursor: Option<String> = None;
+        while documents.len() < limit {
+            let page_size = PAGE_SIZE.min(limit - documents.len());
+            let page = backend
+                .query(database, collection, &filter, cursor.as_deref(), page_size)
+                .await
+                .map_err(|e| format!("query failed: {:#}", e))?;
+            let last_page = page.next_cursor.is_none() || page.documents.is_empty();
+            documents.extend(page.documents);
+            documents.truncate(limit);
+
+            let total = page.total.map(|total| total.min(limit as u64) as f64);
+            progress.report(
+                documents.len() as f64,
+                total,
+                Some(format!(
+                    "Fetched {} documents from {}/{}",
+                    documents.len(),
+                    database,
+                    collection
+                )),
+            );
+
+            if last_page {
+                break;
+            }
+            cursor = page.next_cursor;
+        }
+
+        let json = serde_json::to_string_pretty(&documents).map_err(|e| e.to_string())?;
+        Ok(CallToolResult {
+            content: vec![
+                ToolContent::Text {
+                    text: format!(
+                        "🔍 {} documents from {}/{}",
+                        documents.len(),
+                        database,
+                        collection
+                    ),
+                },
+                ToolContent::Text { text: json },
+            ],
+            is_error: Some(false),
+        })
+    }
+}
+
+#[async_trait]
+impl ToolHandler for QueryTool {
+    fn definition(&self) -> Tool {
+        get_query_tool()
+    }
+
+    async fn call(&self, args: HashMap<String, serde_json::Value>) -> CallToolResult {
+        self.call_with_progress(args, ProgressReporter::disabled())
+            .await
+    }
+
+    async fn call_with_progress(
+        &self,
+        args: HashMap<String, serde_json::Value>,
+        progress: ProgressReporter,
+    ) -> CallToolResult {
+        let Some(backend) = &self.backend else {
+            let query = args.get("query").and_then(|v| v.as_str()).unwrap_or("");
+            return CallToolResult::text(format!("🔍 Executed query: {}", query));
+        };
+        match self.run(backend.as_ref(), &args, &progress).await {
+            Ok(result) => result,
+            Err(message) => CallToolResult::error(message),
+        }
+    }
+}
@@ -1,38 +1,653 @@
-use crate::protocol::Tool;
-use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-pub fn get_deploy_tool() -> Tool {
+use async_trait::async_trait;
+use avx_config::{check_rfc1123_label, AvxConfig, ConfigFile, ConfigLoader};
+use avx_k8s::{
+    generate_manifests, to_yaml, wait_for_rollout_with, ClusterBackend, KubeBackend,
+    ManifestOptions, RolloutStatus, ServiceCatalog, ServiceDef,
+};
+use serde_json::{json, Value};
+
+use super::{validate_arguments, ProgressReporter, ToolHandler};
+use crate::error_chain;
+use crate::protocol::{CallToolResult, ResourceContents, Tool, ToolContent};
+
+/// How long `avx_deploy` waits for a rollout when `timeout_seconds` is not set.
+const DEFAULT_TIMEOUT_SECS: u64 = 300;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// RFC 1123 label, as `cluster` and `namespace` end up in object names and
+/// label values.
+const LABEL_PATTERN: &str = "^[a-z0-9]([-a-z0-9]*[a-z0-9])?$";
+
+/// Arguments checked with [`check_rfc1123_label`] on top of the schema.
+const LABEL_ARGUMENTS: [&str; 2] = ["cluster", "namespace"];
+
+/// The `avx_deploy` definition; the `service` enum lists the catalog ids.
+pub fn get_deploy_tool(catalog: &ServiceCatalog) -> Tool {
     Tool {
         name: "avx_deploy".to_string(),
-        description: "Deploy services to AVX clusters with automatic K8s manifest generation".to_string(),
+        description: "Render the Kubernetes manifests for an AVX service (same generator as `avx-cli k8s`) and, with dry_run: false, apply them to the cluster; also reports rollout status and rolls back".to_string(),
         input_schema: json!({
             "type": "object",
             "properties": {
                 "service": {
                     "type": "string",
-                    "enum": ["gateway", "api-core", "events"],
+                    "enum": catalog.ids(),
                     "description": "Service to deploy"
                 },
                 "cluster": {
                     "type": "string",
-                    "description": "Target cluster (e.g., 'production', 'staging')"
+                    "description": "Target cluster (e.g., 'production', 'staging'); mapped to a kubeconfig context",
+                    "pattern": LABEL_PATTERN,
+                    "maxLength": 63
+                },
+                "action": {
+                    "type": "string",
+                    "enum": ["apply", "status", "rollback"],
+                    "description": "apply the manifests, report the rollout status, or roll back to a previous revision",
+                    "default": "apply"
                 },
                 "namespace": {
                     "type": "string",
-                    "description": "Kubernetes namespace",
-                    "default": "avx-core"
+                    "description": "Kubernetes namespace (defaults to the configured namespace)",
+                    "pattern": LABEL_PATTERN,
+                    "maxLength": 63
                 },
                 "replicas": {
-                    "type": "number",
+                    "type": "integer",
                     "description": "Number of replicas",
+                    "minimum": 1,
                     "default": 2
                 },
                 "image": {
                     "type": "string",
-                    "description": "Container image (optional, uses default if not specified)"
+                    "description": "Container image (optional, uses the catalog image if not specified)"
+                },
+                "to_revision": {
+                    "type": "integer",
+                    "description": "Revision to roll back to (defaults to the previous one)",
+                    "minimum": 1
+                },
+                "timeout_seconds": {
+                    "type": "integer",
+                    "description": "How long to wait for the rollout to finish",
+                    "minimum": 1,
+                    "default": DEFAULT_TIMEOUT_SECS
+                },
+                "dry_run": {
+                    "type": "boolean",
+                    "description": "Do not change the cluster: apply only renders the manifests, rollback only validates on the API server. Set to false to mutate the cluster",
+                    "default": true
                 }
             },
-            "required": ["service", "cluster"]
+            "required": ["service", "cluster"],
+            "additionalProperties": false
         }),
     }
 }
+
+pub struct DeployTool {
+    catalog: ServiceCatalog,
+    loader: ConfigLoader,
+    backend: Option<Arc<dyn ClusterBackend>>,
+}
+
+impl DeployTool {
+    pub fn new(catalog: ServiceCatalog) -> Self {
+        Self {
+            catalog,
+            loader: ConfigLoader::new(),
+            backend: None,
+        }
+    }
+
+    /// Uses the catalog found from the working directory (see
+    /// [`ServiceCatalog::discover`]), falling back to the built-in services
+    /// when it cannot be loaded.
+    pub fn discover() -> Self {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let catalog = ServiceCatalog::discover(&cwd).unwrap_or_else(|e| {
+            tracing::warn!("ignoring service catalog: {}", e);
+            ServiceCatalog::builtin()
+        });
+        Self::new(catalog)
+    }
+
+    /// Reads the configuration through `loader` instead of the default
+    /// system, user and project files.
+    pub fn with_loader(mut self, loader: ConfigLoader) -> Self {
+        self.loader = loader;
+        self
+    }
+
+    /// Sends every cluster call to `backend` instead of connecting through
+    /// the kubeconfig context of the `cluster` argument.
+    pub fn with_backend(mut self, backend: Arc<dyn ClusterBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    async fn connect(&self, cluster: &str) -> Result<Arc<dyn ClusterBackend>, String> {
+        if let Some(backend) = &self.backend {
+            return Ok(backend.clone());
+        }
+        let backend = KubeBackend::for_cluster(&self.loader, cluster)
+            .await
+            .map_err(|e| error_chain(&e))?;
+        Ok(Arc::new(backend))
+    }
+
+    async fn run(
+        &self,
+        args: &HashMap<String, Value>,
+        progress: &ProgressReporter,
+    ) -> Result<CallToolResult, String> {
+        let str_arg = |name: &str| args.get(name).and_then(Value::as_str).map(str::to_string);
+        let int_arg = |name: &str| args.get(name).and_then(Value::as_u64);
+
+        let service_id = str_arg("service").unwrap_or_default();
+        let service = self
+            .catalog
+            .get(&service_id)
+            .map_err(|e| e.to_string())?
+            .clone();
+        let cluster = str_arg("cluster").unwrap_or_default();
+        let dry_run = args.get("dry_run").and_then(Value::as_bool).unwrap_or(true);
+        let timeout =
+            Duration::from_secs(int_arg("timeout_seconds").unwrap_or(DEFAULT_TIMEOUT_SECS));
+
+        // Another cluster than the configured one is rendered with the env
+        // and mesh of the profile targeting it
+        let config_error =
+            |e: &dyn std::error::Error| format!("failed to load configuration: {}", error_chain(e));
+        let profile = self
+            .loader
+            .profile_for_cluster(&cluster)
+            .map_err(|e| config_error(&e))?;
+        let loader = self.loader.clone().overrides(ConfigFile {
+            cluster: Some(cluster.clone()),
+            namespace: str_arg("namespace"),
+            profile,
+            ..ConfigFile::default()
+        });
+        let cfg = loader.load().map_err(|e| config_error(&e))?;
+        // Same gate as `avx-cli k8s`: nothing is rendered from a config it rejects
+        cfg.validate()
+            .map_err(|e| format!("invalid configuration: {}", e))?;
+        let namespace = cfg.namespace.clone();
+
+        match str_arg("action").as_deref().unwrap_or("apply") {
+            "status" => {
+                let backend = self.connect(&cluster).await?;
+                let status = backend
+                    .rollout_status(&namespace, &service.name)
+                    .await
+                    .map_err(|e| error_chain(&e))?;
+                Ok(CallToolResult::text(format!(
+                    "📊 {}/{} on {}: {:?}, {}",
+                    namespace,
+                    service.name,
+                    cluster,
+                    status.state(),
+                    status
+                )))
+            }
+            "rollback" => {
+                let backend = self.connect(&cluster).await?;
+                let revision = int_arg("to_revision").map(|r| r as i64);
+                let rollback = backend
+                    .rollback(&namespace, &service.name, revision, dry_run)
+                    .await
+                    .map_err(|e| error_chain(&e))?;
+                let from = rollback
+                    .from_revision
+                    .map(|r| r.to_string())
+                    .unwrap_or_else(|| "?".into());
+                if dry_run {
+                    return Ok(CallToolResult::text(format!(
+                        "⏪ {}/{} on {} would roll back from revision {} to {} (dry run, nothing changed)",
+                        namespace, service.name, cluster, from, rollback.to_revision
+                    )));
+                }
+                let status = wait(
+                    backend.as_ref(),
+                    &namespace,
+                    &service.name,
+                    timeout,
+                    progress,
+                )
+                .await?;
+                Ok(CallToolResult::text(format!(
+                    "⏪ Rolled back {}/{} on {} from revision {} to {}: {}",
+                    namespace, service.name, cluster, from, rollback.to_revision, status
+                )))
+            }
+            _ => {
+                self.apply(args, &cfg, service, dry_run, timeout, progress)
+                    .await
+            }
+        }
+    }
+
+    async fn apply(
+        &self,
+        args: &HashMap<String, Value>,
+        cfg: &AvxConfig,
+        service: ServiceDef,
+        dry_run: bool,
+        timeout: Duration,
+        progress: &ProgressReporter,
+    ) -> Result<CallToolResult, String> {
+        let mut opts = ManifestOptions::new(service, cfg.namespace.clone());
+        opts.image = args
+            .get("image")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        if let Some(replicas) = args.get("replicas").and_then(Value::as_u64) {
+            opts.replicas = u32::try_from(replicas).unwrap_or(u32::MAX);
+        }
+
+        let render_error =
+            |e: &dyn std::error::Error| format!("failed to render manifests: {}", error_chain(e));
+        let manifests = generate_manifests(cfg, &opts).map_err(|e| render_error(&e))?;
+        let yaml = to_yaml(cfg, &manifests).map_err(|e| render_error(&e))?;
+        let resource = ToolContent::Resource {
+            resource: ResourceContents {
+                uri: format!(
+                    "avx://manifests/{}/{}/{}.yaml",
+                    cfg.cluster, opts.namespace, opts.service.id
+                ),
+                mime_type: Some("application/yaml".to_string()),
+                text: Some(yaml),
+                blob: None,
+            },
+        };
+
+        let summary = if dry_run {
+            format!(
+                "📄 Rendered manifests for {} ({} replicas) in {}/{} (dry run, nothing applied)",
+                opts.service.name, opts.replicas, cfg.cluster, opts.namespace
+            )
+        } else {
+            let mut objects = Vec::with_capacity(manifests.len());
+            for manifest in &manifests {
+                objects.push(serde_json::to_value(manifest).map_err(|e| render_error(&e))?);
+            }
+            let backend = self.connect(&cfg.cluster).await?;
+            let applied = backend
+                .apply(&objects, false)
+                .await
+                .map_err(|e| error_chain(&e))?;
+            progress.report(
+                0.0,
+                Some(f64::from(opts.replicas)),
+                Some(format!(
+                    "Applied {} objects, waiting for the rollout",
+                    applied.len()
+                )),
+            );
+            let status = wait(
+                backend.as_ref(),
+                &opts.namespace,
+                &opts.service.name,
+                timeout,
+                progress,
+            )
+            .await?;
+            format!(
+                "🚀 Applied {} objects for {} in {}/{}: {}",
+                applied.len(),
+                opts.service.name,
+                cfg.cluster,
+                opts.namespace,
+                status
+            )
+        };
+
+        Ok(CallToolResult {
+            content: vec![ToolContent::Text { text: summary }, resource],
+            is_error: Some(false),
+        })
+    }
+}
+
+/// Waits for the rollout of `namespace/name`, reporting the updated replicas
+/// that are available out of the desired ones.
+async fn wait(
+    backend: &dyn ClusterBackend,
+    namespace: &str,
+    name: &str,
+    timeout: Duration,
+    progress: &ProgressReporter,
+) -> Result<RolloutStatus, String> {
+    wait_for_rollout_with(backend, namespace, name, timeout, POLL_INTERVAL, |status| {
+        let done = status
+            .updated_replicas
+            .min(status.available_replicas)
+            .max(0);
+        progress.report(
+            f64::from(done),
+            Some(f64::from(status.replicas)),
+            Some(status.to_string()),
+        );
+    })
+    .await
+    .map_err(|e| error_chain(&e))
+}
+
+#[async_trait]
+impl ToolHandler for DeployTool {
+    fn definition(&self) -> Tool {
+        get_deploy_tool(&self.catalog)
+    }
+
+    async fn call(&self, args: HashMap<String, Value>) -> CallToolResult {
+        self.call_with_progress(args, ProgressReporter::disabled())
+            .await
+    }
+
+    async fn call_with_progress(
+        &self,
+        args: HashMap<String, Value>,
+        progress: ProgressReporter,
+    ) -> CallToolResult {
+        let mut problems = validate_arguments(&self.definition().input_schema, &args)
+            .err()
+            .unwrap_or_default();
+        for name in LABEL_ARGUMENTS {
+            if let Some(Err(message)) = args
+                .get(name)
+                .and_then(Value::as_str)
+                .map(check_rfc1123_label)
+            {
+                problems.push(format!(
+                    "argument '{}' is not a valid RFC 1123 label: {}",
+                    name, message
+                ));
+            }
+        }
+        if !problems.is_empty() {
+            return CallToolResult::error(format!("invalid arguments: {}", problems.join("; ")));
+        }
+        match self.run(&args, &progress).await {
+            Ok(result) => result,
+            Err(message) => CallToolResult::error(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::Outgoing;
+    use crate::testing::{rollout, FakeCluster};
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+
+    /// Reads only `<dir>/config.toml`, which selects the staging profile.
+    fn staging_loader(dir: &TempDir) -> ConfigLoader {
+        loader(
+            dir,
+            "profile = \"staging\"\n\
+             [profiles.staging]\nenv = \"staging\"\ncluster = \"staging-1\"\n\
+             [profiles.production]\nenv = \"prod\"\ncluster = \"us-east-1\"\nmesh = \"istio\"\n",
+        )
+    }
+
+    /// Reads only `<dir>/config.toml`, written with `text`.
+    fn loader(dir: &TempDir, text: &str) -> ConfigLoader {
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, text).unwrap();
+        ConfigLoader::new()
+            .system_path(None)
+            .user_path(Some(path))
+            .cwd(None)
+            .use_env(false)
+    }
+
+    fn args(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn text(result: &CallToolResult) -> &str {
+        match &result.content[0] {
+            ToolContent::Text { text } => text,
+            other => panic!("unexpected content {:?}", other),
+        }
+    }
+
+    fn manifests(result: &CallToolResult) -> &str {
+        match &result.content[1] {
+            ToolContent::Resource { resource } => resource.text.as_deref().unwrap(),
+            other => panic!("unexpected content {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_configuration_is_a_tool_error() {
+        let dir = TempDir::new().unwrap();
+        let tool = DeployTool::new(ServiceCatalog::builtin())
+            .with_loader(loader(&dir, "mesh = \"consul\"\nstack = \"Core_Stack\"\n"));
+
+        let result = tool
+            .call(args(json!({"service": "gateway", "cluster": "local"})))
+            .await;
+
+        assert_eq!(result.is_error, Some(true));
+        let message = text(&result);
+        assert!(
+            message.starts_with("invalid configuration: 2 invalid config value(s)"),
+            "{}",
+            message
+        );
+        assert!(message.contains("mesh"), "{}", message);
+        assert!(message.contains("stack"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn renders_with_the_profile_of_the_target_cluster() {
+        let dir = TempDir::new().unwrap();
+        let tool = DeployTool::new(ServiceCatalog::builtin()).with_loader(staging_loader(&dir));
+
+        let result = tool
+            .call(args(json!({"service": "gateway", "cluster": "us-east-1"})))
+            .await;
+        assert_eq!(result.is_error, Some(false), "{}", text(&result));
+        let yaml = manifests(&result);
+        assert!(yaml.contains("cluster: us-east-1"), "{}", yaml);
+        assert!(yaml.contains("env: prod"), "{}", yaml);
+        assert!(yaml.contains("mesh: istio"), "{}", yaml);
+
+        let result = tool
+            .call(args(json!({"service": "gateway", "cluster": "staging-1"})))
+            .await;
+        let yaml = manifests(&result);
+        assert!(yaml.contains("env: staging"), "{}", yaml);
+        assert!(yaml.contains("mesh: none"), "{}", yaml);
+    }
+
+    #[tokio::test]
+    async fn cluster_and_namespace_must_be_rfc1123_labels() {
+        let tool = DeployTool::new(ServiceCatalog::builtin());
+        let result = tool
+            .call(args(json!({
+                "service": "gateway",
+                "cluster": "Prod_1",
+                "namespace": "avx:core",
+            })))
+            .await;
+
+        assert_eq!(result.is_error, Some(true));
+        let message = text(&result);
+        assert!(
+            message.contains("argument 'cluster' is not a valid RFC 1123 label"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("argument 'namespace' is not a valid RFC 1123 label"),
+            "{}",
+            message
+        );
+    }
+
+    /// Deploy tool on the staging profile, talking to `cluster`.
+    fn fake_tool(dir: &TempDir, cluster: &Arc<FakeCluster>) -> DeployTool {
+        DeployTool::new(ServiceCatalog::builtin())
+            .with_loader(staging_loader(dir))
+            .with_backend(cluster.clone())
+    }
+
+    /// Calls `tool`, returning its result and the progress values it sent.
+    async fn call_with_progress(
+        tool: &DeployTool,
+        arguments: Value,
+    ) -> (CallToolResult, Vec<(f64, Option<f64>)>) {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let result = tool
+            .call_with_progress(args(arguments), ProgressReporter::new(json!("deploy"), tx))
+            .await;
+        let mut progress = Vec::new();
+        while let Ok(Outgoing::Notification(notification)) = rx.try_recv() {
+            let params = notification.params.unwrap();
+            progress.push((
+                params["progress"].as_f64().unwrap(),
+                params["total"].as_f64(),
+            ));
+        }
+        (result, progress)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn apply_waits_for_the_rollout() {
+        let dir = TempDir::new().unwrap();
+        let cluster = Arc::new(FakeCluster::with_statuses([
+            rollout(2, 0),
+            rollout(2, 1),
+            rollout(2, 2),
+        ]));
+        let tool = fake_tool(&dir, &cluster);
+
+        let (result, progress) = call_with_progress(
+            &tool,
+            json!({"service": "gateway", "cluster": "staging-1", "dry_run": false}),
+        )
+        .await;
+
+        assert_eq!(result.is_error, Some(false), "{}", text(&result));
+        assert!(
+            text(&result).starts_with(
+                "🚀 Applied 2 objects for avx-gateway in staging-1/avx-core: 2/2 updated"
+            ),
+            "{}",
+            text(&result)
+        );
+        let applied = cluster.applied.lock().unwrap();
+        assert_eq!(applied.len(), 1);
+        let (objects, dry_run) = &applied[0];
+        assert!(!dry_run);
+        assert_eq!(objects[0]["kind"], "Deployment");
+        assert_eq!(objects[0]["metadata"]["labels"]["env"], "staging");
+        assert_eq!(*cluster.polls.lock().unwrap(), 3);
+        assert_eq!(
+            progress,
+            vec![(0.0, Some(2.0)), (1.0, Some(2.0)), (2.0, Some(2.0))]
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_apply_does_not_touch_the_cluster() {
+        let dir = TempDir::new().unwrap();
+        let cluster = Arc::new(FakeCluster::default());
+        let tool = fake_tool(&dir, &cluster);
+
+        let result = tool
+            .call(args(json!({"service": "gateway", "cluster": "staging-1"})))
+            .await;
+
+        assert_eq!(result.is_error, Some(false), "{}", text(&result));
+        assert!(cluster.applied.lock().unwrap().is_empty());
+        assert_eq!(*cluster.polls.lock().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn dry_run_rollback_is_only_validated() {
+        let dir = TempDir::new().unwrap();
+        let cluster = Arc::new(FakeCluster::default());
+        let tool = fake_tool(&dir, &cluster);
+
+        let result = tool
+            .call(args(json!({
+                "service": "gateway",
+                "cluster": "staging-1",
+                "action": "rollback",
+            })))
+            .await;
+
+        assert_eq!(
+            text(&result),
+            "⏪ avx-core/avx-gateway on staging-1 would roll back from revision 3 to 2 (dry run, nothing changed)"
+        );
+        assert_eq!(*cluster.rollbacks.lock().unwrap(), vec![(None, true)]);
+        assert_eq!(*cluster.polls.lock().unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rollback_waits_for_the_rollout() {
+        let dir = TempDir::new().unwrap();
+        let cluster = Arc::new(FakeCluster::with_statuses([rollout(2, 1), rollout(2, 2)]));
+        let tool = fake_tool(&dir, &cluster);
+
+        let result = tool
+            .call(args(json!({
+                "service": "gateway",
+                "cluster": "staging-1",
+                "action": "rollback",
+                "to_revision": 1,
+                "dry_run": false,
+            })))
+            .await;
+
+        assert_eq!(result.is_error, Some(false), "{}", text(&result));
+        assert!(
+            text(&result).starts_with(
+                "⏪ Rolled back avx-core/avx-gateway on staging-1 from revision 3 to 1: 2/2 updated"
+            ),
+            "{}",
+            text(&result)
+        );
+        assert_eq!(*cluster.rollbacks.lock().unwrap(), vec![(Some(1), false)]);
+        assert_eq!(*cluster.polls.lock().unwrap(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rollout_timeout_is_a_tool_error() {
+        let dir = TempDir::new().unwrap();
+        let cluster = Arc::new(FakeCluster::with_statuses([rollout(2, 1)]));
+        let tool = fake_tool(&dir, &cluster);
+
+        let started = tokio::time::Instant::now();
+        let result = tool
+            .call(args(json!({
+                "service": "gateway",
+                "cluster": "staging-1",
+                "dry_run": false,
+                "timeout_seconds": 5,
+            })))
+            .await;
+
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            text(&result),
+            "timed out after 5s waiting for avx-core/avx-gateway: 1/2 updated, 1 ready, 1 available, \
+             1 old replicas pending termination (revision 3)"
+        );
+        // Polls at 0s, 2s and 4s; the next one would land past the deadline
+        assert_eq!(*cluster.polls.lock().unwrap(), 3);
+        assert_eq!(started.elapsed(), 2 * POLL_INTERVAL);
+    }
+}
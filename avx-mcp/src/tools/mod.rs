@@ -1,13 +1,143 @@
 pub mod aviladb_query;
 pub mod deploy;
+pub mod progress;
 pub mod telemetry;
 
-use crate::protocol::Tool;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-pub fn get_all_tools() -> Vec<Tool> {
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::protocol::{CallToolResult, Tool};
+
+pub use progress::ProgressReporter;
+
+/// A tool the MCP server can list and execute.
+///
+/// Implement this in your own crate and hand it to
+/// [`McpServer::register_tool`](crate::McpServer::register_tool) to expose
+/// in-house tools without touching the server.
+#[async_trait]
+pub trait ToolHandler: Send + Sync {
+    /// Metadata advertised through `tools/list`.
+    fn definition(&self) -> Tool;
+
+    /// Executes the tool with the arguments sent in `tools/call`.
+    async fn call(&self, args: HashMap<String, serde_json::Value>) -> CallToolResult;
+
+    /// Executes the tool like [`call`](Self::call), reporting progress to
+    /// clients that asked for it. The server calls this one; long-running
+    /// tools override it, the default ignores the reporter.
+    async fn call_with_progress(
+        &self,
+        args: HashMap<String, serde_json::Value>,
+        _progress: ProgressReporter,
+    ) -> CallToolResult {
+        self.call(args).await
+    }
+}
+
+pub fn get_all_tools() -> Vec<Arc<dyn ToolHandler>> {
     vec![
-        aviladb_query::get_query_tool(),
-        deploy::get_deploy_tool(),
-        telemetry::get_telemetry_tool(),
+        // No AvilaDB client yet: `avx_query` only echoes the query
+        Arc::new(aviladb_query::QueryTool::new()),
+        Arc::new(deploy::DeployTool::discover()),
+        Arc::new(telemetry::TelemetryTool),
     ]
 }
+
+/// Checks `args` against the subset of JSON Schema used by the tool
+/// definitions: `required`, `additionalProperties: false`, and per-property
+/// `type`, `enum`, `minimum` and `maximum`.
+///
+/// Returns one message per problem so the caller can report them all at once.
+pub fn validate_arguments(
+    schema: &Value,
+    args: &HashMap<String, Value>,
+) -> Result<(), Vec<String>> {
+    let mut problems = Vec::new();
+    let properties = schema.get("properties").and_then(Value::as_object);
+
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !args.contains_key(name) {
+                problems.push(format!("missing required argument '{}'", name));
+            }
+        }
+    }
+
+    let mut names: Vec<&String> = args.keys().collect();
+    names.sort();
+    for name in names {
+        let value = &args[name];
+        let Some(property) = properties.and_then(|p| p.get(name)) else {
+            if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+                problems.push(format!("unknown argument '{}'", name));
+            }
+            continue;
+        };
+
+        if let Some(expected) = property.get("type").and_then(Value::as_str) {
+            if !has_type(value, expected) {
+                problems.push(format!(
+                    "argument '{}' must be of type {}, got {}",
+                    name, expected, value
+                ));
+                continue;
+            }
+        }
+        if let Some(allowed) = property.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+                problems.push(format!(
+                    "argument '{}' must be one of {}, got {}",
+                    name,
+                    allowed.join(", "),
+                    value
+                ));
+            }
+        }
+        if let (Some(min), Some(n)) = (
+            property.get("minimum").and_then(Value::as_f64),
+            value.as_f64(),
+        ) {
+            if n < min {
+                problems.push(format!(
+                    "argument '{}' must be >= {}, got {}",
+                    name, min, value
+                ));
+            }
+        }
+        if let (Some(max), Some(n)) = (
+            property.get("maximum").and_then(Value::as_f64),
+            value.as_f64(),
+        ) {
+            if n > max {
+                problems.push(format!(
+                    "argument '{}' must be <= {}, got {}",
+                    name, max, value
+                ));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
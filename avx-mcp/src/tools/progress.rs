@@ -0,0 +1,89 @@
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::protocol::{JsonRpcNotification, ProgressParams};
+use crate::server::Outgoing;
+
+/// Sends `notifications/progress` for one `tools/call`, through the
+/// server's writer. Clones report for the same call.
+///
+/// Updates are dropped when the client sent no `_meta.progressToken`, when
+/// `progress` does not increase (MCP requires it to), and once the call has
+/// been answered.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    target: Option<Arc<Target>>,
+}
+
+struct Target {
+    token: Value,
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+    state: Mutex<ReportState>,
+}
+
+#[derive(Default)]
+struct ReportState {
+    last: Option<f64>,
+    finished: bool,
+}
+
+impl ProgressReporter {
+    /// A reporter that drops every update, e.g. to run a tool outside the
+    /// server.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn new(token: Value, outgoing: mpsc::UnboundedSender<Outgoing>) -> Self {
+        Self {
+            target: Some(Arc::new(Target {
+                token,
+                outgoing,
+                state: Mutex::new(ReportState::default()),
+            })),
+        }
+    }
+
+    /// Whether the client asked for progress; tools can skip building
+    /// messages nobody reads.
+    pub fn is_enabled(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Reports `progress` out of `total` (when known), e.g. documents
+    /// fetched out of the query limit.
+    pub fn report(&self, progress: f64, total: Option<f64>, message: Option<String>) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        let mut state = target.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.finished || state.last.is_some_and(|last| progress <= last) {
+            return;
+        }
+        state.last = Some(progress);
+
+        let params = ProgressParams {
+            progress_token: target.token.clone(),
+            progress,
+            total,
+            message,
+        };
+        let notification =
+            JsonRpcNotification::new("notifications/progress", serde_json::to_value(params).ok());
+        // Only fails once the server has stopped
+        let _ = target.outgoing.send(Outgoing::Notification(notification));
+    }
+
+    /// Stops sending updates; called when the call is answered.
+    pub(crate) fn finish(&self) {
+        if let Some(target) = &self.target {
+            target
+                .state
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .finished = true;
+        }
+    }
+}
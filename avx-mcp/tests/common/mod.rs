@@ -0,0 +1,203 @@
+//! Drives an `McpServer` through an in-memory duplex stream, with a few
+//! test tools. Shared by the integration tests; each uses part of it.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use avx_mcp::{CallToolResult, McpServer, Tool, ToolHandler};
+use serde_json::{json, Value};
+use tokio::io::{
+    AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf, WriteHalf,
+};
+use tokio::sync::Barrier;
+use tokio::task::JoinHandle;
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Echo;
+
+#[async_trait]
+impl ToolHandler for Echo {
+    fn definition(&self) -> Tool {
+        Tool {
+            name: "echo".to_string(),
+            description: "Returns its `text` argument".to_string(),
+            input_schema: json!({"type": "object"}),
+        }
+    }
+
+    async fn call(&self, args: HashMap<String, Value>) -> CallToolResult {
+        match args.get("text").and_then(Value::as_str) {
+            Some(text) => CallToolResult::text(text),
+            None => CallToolResult::error("missing text"),
+        }
+    }
+}
+
+/// Returns once two calls are in flight at the same time, so it only
+/// completes when calls run concurrently.
+pub struct Rendezvous(Arc<Barrier>);
+
+#[async_trait]
+impl ToolHandler for Rendezvous {
+    fn definition(&self) -> Tool {
+        Tool {
+            name: "rendezvous".to_string(),
+            description: "Waits for a second concurrent call".to_string(),
+            input_schema: json!({"type": "object"}),
+        }
+    }
+
+    async fn call(&self, _args: HashMap<String, Value>) -> CallToolResult {
+        self.0.wait().await;
+        CallToolResult::text("met")
+    }
+}
+
+/// Never returns on its own.
+pub struct Hang;
+
+#[async_trait]
+impl ToolHandler for Hang {
+    fn definition(&self) -> Tool {
+        Tool {
+            name: "hang".to_string(),
+            description: "Runs until cancelled or timed out".to_string(),
+            input_schema: json!({"type": "object"}),
+        }
+    }
+
+    async fn call(&self, _args: HashMap<String, Value>) -> CallToolResult {
+        std::future::pending().await
+    }
+}
+
+pub struct Client {
+    writer: WriteHalf<DuplexStream>,
+    lines: Lines<BufReader<ReadHalf<DuplexStream>>>,
+    server: JoinHandle<anyhow::Result<()>>,
+}
+
+impl Client {
+    pub fn start() -> Self {
+        Self::start_with(|_| {})
+    }
+
+    /// Starts a server with the test tools, set up by `configure`.
+    pub fn start_with(configure: impl FnOnce(&mut McpServer) + Send + 'static) -> Self {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (server_reader, server_writer) = tokio::io::split(server);
+        let server = tokio::spawn(async move {
+            let mut mcp = McpServer::new();
+            mcp.register_tool(Arc::new(Echo));
+            mcp.register_tool(Arc::new(Rendezvous(Arc::new(Barrier::new(2)))));
+            mcp.register_tool(Arc::new(Hang));
+            configure(&mut mcp);
+            mcp.serve(server_reader, server_writer).await
+        });
+        let (reader, writer) = tokio::io::split(client);
+        Self {
+            writer,
+            lines: BufReader::new(reader).lines(),
+            server,
+        }
+    }
+
+    pub async fn send(&mut self, line: &str) {
+        self.writer.write_all(line.as_bytes()).await.unwrap();
+        self.writer.write_all(b"\n").await.unwrap();
+        self.writer.flush().await.unwrap();
+    }
+
+    pub async fn recv(&mut self) -> Value {
+        let line = tokio::time::timeout(RESPONSE_TIMEOUT, self.lines.next_line())
+            .await
+            .expect("no message from the server")
+            .unwrap()
+            .expect("server closed the stream");
+        serde_json::from_str(&line).unwrap()
+    }
+
+    pub async fn call(&mut self, line: &str) -> Value {
+        self.send(line).await;
+        self.recv().await
+    }
+
+    /// Runs the `initialize` handshake with `protocol_version`, returning
+    /// the `initialize` result.
+    pub async fn initialize_with(&mut self, protocol_version: &str) -> Value {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": "init",
+            "method": "initialize",
+            "params": {
+                "protocolVersion": protocol_version,
+                "capabilities": {"roots": {"listChanged": true}},
+                "clientInfo": {"name": "conformance", "version": "1.0"}
+            }
+        });
+        let response = self.call(&request.to_string()).await;
+        assert_eq!(response["id"], "init", "{}", response);
+        self.send(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+            .await;
+        response["result"].clone()
+    }
+
+    pub async fn initialize(&mut self) {
+        self.initialize_with("2024-11-05").await;
+    }
+
+    /// Sends a `ping` and checks that its response is the next message, so
+    /// nothing was written for whatever was sent before.
+    pub async fn assert_silent(&mut self) {
+        let response = self
+            .call(r#"{"jsonrpc":"2.0","id":"sync","method":"ping"}"#)
+            .await;
+        assert_eq!(
+            response,
+            json!({"jsonrpc": "2.0", "id": "sync", "result": {}})
+        );
+    }
+
+    /// Checks the server stopped on its own and closed its output.
+    pub async fn stopped(mut self) {
+        let result = tokio::time::timeout(RESPONSE_TIMEOUT, self.server)
+            .await
+            .expect("server did not stop")
+            .unwrap();
+        result.unwrap();
+        assert_eq!(self.lines.next_line().await.unwrap(), None);
+    }
+
+    /// Closes the input and checks the server stops cleanly.
+    pub async fn finish(mut self) {
+        self.writer.shutdown().await.unwrap();
+        drop(self.writer);
+        let result = tokio::time::timeout(RESPONSE_TIMEOUT, self.server)
+            .await
+            .expect("server did not stop at EOF")
+            .unwrap();
+        result.unwrap();
+    }
+}
+
+pub fn assert_error(response: &Value, id: Value, code: i32) {
+    assert_eq!(response["jsonrpc"], "2.0");
+    assert_eq!(response["id"], id, "{}", response);
+    assert_eq!(response["error"]["code"], code, "{}", response);
+    assert!(response["error"]["message"].is_string(), "{}", response);
+    assert!(response.get("result").is_none(), "{}", response);
+}
+
+pub fn tool_call(id: Value, tool: &str) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "method": "tools/call", "params": {"name": tool, "arguments": {"text": "hi"}}})
+        .to_string()
+}
+
+pub fn cancel(id: Value) -> String {
+    json!({"jsonrpc": "2.0", "method": "notifications/cancelled", "params": {"requestId": id, "reason": "test"}})
+        .to_string()
+}
@@ -0,0 +1,519 @@
+//! JSON-RPC 2.0 conformance of `McpServer::serve`, driven through an
+//! in-memory duplex stream.
+
+mod common;
+
+use std::time::Duration;
+
+use avx_mcp::{error_codes, PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS};
+use common::{assert_error, cancel, tool_call, Client};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn invalid_json_is_a_parse_error_with_null_id() {
+    let mut client = Client::start();
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":1,"method":"ping""#)
+        .await;
+    assert_error(&response, Value::Null, error_codes::PARSE_ERROR);
+    // the stream is still usable afterwards
+    client.assert_silent().await;
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn wrong_jsonrpc_version_is_an_invalid_request() {
+    let mut client = Client::start();
+    let response = client
+        .call(r#"{"jsonrpc":"1.0","id":7,"method":"ping"}"#)
+        .await;
+    assert_error(&response, json!(7), error_codes::INVALID_REQUEST);
+
+    let response = client.call(r#"{"id":"a","method":"ping"}"#).await;
+    assert_error(&response, json!("a"), error_codes::INVALID_REQUEST);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn malformed_requests_are_invalid_requests() {
+    let mut client = Client::start();
+    for (line, id) in [
+        (r#"42"#, Value::Null),
+        (r#""ping""#, Value::Null),
+        (r#"{"jsonrpc":"2.0","id":3}"#, json!(3)),
+        (r#"{"jsonrpc":"2.0","id":4,"method":5}"#, json!(4)),
+        (
+            r#"{"jsonrpc":"2.0","id":{"n":1},"method":"ping"}"#,
+            Value::Null,
+        ),
+        (
+            r#"{"jsonrpc":"2.0","id":null,"method":"ping"}"#,
+            Value::Null,
+        ),
+        (
+            r#"{"jsonrpc":"2.0","id":5,"method":"ping","params":"x"}"#,
+            json!(5),
+        ),
+    ] {
+        let response = client.call(line).await;
+        assert_error(&response, id, error_codes::INVALID_REQUEST);
+    }
+    // a malformed notification still gets an error, since its id is unknown
+    let response = client
+        .call(r#"{"jsonrpc":"1.0","method":"notifications/initialized"}"#)
+        .await;
+    assert_error(&response, Value::Null, error_codes::INVALID_REQUEST);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn unknown_method_is_method_not_found() {
+    let mut client = Client::start();
+    client.initialize().await;
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":9,"method":"no/such/method"}"#)
+        .await;
+    assert_error(&response, json!(9), error_codes::METHOD_NOT_FOUND);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn notifications_get_no_response() {
+    let mut client = Client::start();
+    client
+        .send(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+        .await;
+    client
+        .send(r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":1,"reason":"user"}}"#)
+        .await;
+    client
+        .send(r#"{"jsonrpc":"2.0","method":"notifications/unknown"}"#)
+        .await;
+    // not even for methods that would fail as requests
+    client
+        .send(r#"{"jsonrpc":"2.0","method":"tools/call"}"#)
+        .await;
+    client.assert_silent().await;
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn response_ids_echo_the_request_ids() {
+    let mut client = Client::start();
+    for id in [json!(1), json!(-2), json!(3.5), json!("abc"), json!("")] {
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": "ping"});
+        let response = client.call(&request.to_string()).await;
+        assert_eq!(response, json!({"jsonrpc": "2.0", "id": id, "result": {}}));
+    }
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn initialize_handshake() {
+    let mut client = Client::start();
+    let response = client
+        .call(
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"0"}}}"#,
+        )
+        .await;
+    assert_eq!(response["id"], 1);
+    let result = &response["result"];
+    assert_eq!(result["protocolVersion"], "2024-11-05");
+    assert_eq!(result["serverInfo"]["name"], "avx-mcp");
+    assert_eq!(result["capabilities"]["resources"]["listChanged"], true);
+    assert_eq!(result["capabilities"]["tools"]["listChanged"], false);
+
+    client
+        .send(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+        .await;
+    client.assert_silent().await;
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn tool_results_use_camel_case() {
+    let mut client = Client::start();
+    client.initialize().await;
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"echo","arguments":{"text":"hi"}}}"#)
+        .await;
+    assert_eq!(
+        response["result"],
+        json!({"content": [{"type": "text", "text": "hi"}], "isError": false})
+    );
+
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"echo","arguments":{}}}"#)
+        .await;
+    assert_eq!(response["result"]["isError"], true);
+
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":3,"method":"tools/call"}"#)
+        .await;
+    assert_error(&response, json!(3), error_codes::INVALID_PARAMS);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn blank_lines_are_ignored() {
+    let mut client = Client::start();
+    client.send("").await;
+    client.send("   ").await;
+    client.assert_silent().await;
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn batch_responses_follow_request_order_without_notifications() {
+    let mut client = Client::start();
+    client.initialize().await;
+    let response = client
+        .call(
+            r#"[
+                {"jsonrpc":"2.0","id":1,"method":"ping"},
+                {"jsonrpc":"2.0","method":"notifications/initialized"},
+                {"jsonrpc":"2.0","id":"two","method":"tools/call","params":{"name":"echo","arguments":{"text":"hi"}}},
+                {"jsonrpc":"1.0","id":3,"method":"ping"},
+                {"jsonrpc":"2.0","id":4,"method":"no/such/method"}
+            ]"#
+            .replace('\n', "")
+            .as_str(),
+        )
+        .await;
+    let responses = response.as_array().expect("batch response is an array");
+    assert_eq!(responses.len(), 4, "{}", response);
+    assert_eq!(
+        responses[0],
+        json!({"jsonrpc": "2.0", "id": 1, "result": {}})
+    );
+    assert_eq!(responses[1]["id"], "two");
+    assert_eq!(responses[1]["result"]["content"][0]["text"], "hi");
+    assert_error(&responses[2], json!(3), error_codes::INVALID_REQUEST);
+    assert_error(&responses[3], json!(4), error_codes::METHOD_NOT_FOUND);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn empty_batch_is_a_single_invalid_request() {
+    let mut client = Client::start();
+    let response = client.call("[]").await;
+    assert_error(&response, Value::Null, error_codes::INVALID_REQUEST);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn invalid_batch_elements_get_one_error_each() {
+    let mut client = Client::start();
+    let response = client.call("[1, 2, []]").await;
+    let responses = response.as_array().expect("batch response is an array");
+    assert_eq!(responses.len(), 3, "{}", response);
+    for response in responses {
+        assert_error(response, Value::Null, error_codes::INVALID_REQUEST);
+    }
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn malformed_batch_is_a_parse_error() {
+    let mut client = Client::start();
+    let response = client
+        .call(r#"[{"jsonrpc":"2.0","id":1,"method":"ping"},"#)
+        .await;
+    assert_error(&response, Value::Null, error_codes::PARSE_ERROR);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn batch_of_notifications_gets_no_response() {
+    let mut client = Client::start();
+    client
+        .send(
+            r#"[{"jsonrpc":"2.0","method":"notifications/initialized"},{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":1}}]"#,
+        )
+        .await;
+    client.assert_silent().await;
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn batch_requests_run_concurrently() {
+    let mut client = Client::start();
+    client.initialize().await;
+    let call = |id: u32| json!({"jsonrpc": "2.0", "id": id, "method": "tools/call", "params": {"name": "rendezvous"}});
+    let response = client.call(&json!([call(1), call(2)]).to_string()).await;
+    let responses = response.as_array().expect("batch response is an array");
+    assert_eq!(responses.len(), 2, "{}", response);
+    for (response, id) in responses.iter().zip([1, 2]) {
+        assert_eq!(response["id"], id);
+        assert_eq!(response["result"]["content"][0]["text"], "met");
+    }
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn requests_wait_for_the_initialize_handshake() {
+    let mut client = Client::start();
+    let tools_list = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+    let response = client.call(tools_list).await;
+    assert_error(&response, json!(1), error_codes::INVALID_REQUEST);
+    // ping is always answered
+    client.assert_silent().await;
+
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":2,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"0"}}}"#)
+        .await;
+    assert!(response.get("result").is_some(), "{}", response);
+    let response = client.call(tools_list).await;
+    assert_error(&response, json!(1), error_codes::INVALID_REQUEST);
+
+    client
+        .send(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+        .await;
+    let response = client.call(tools_list).await;
+    assert_eq!(
+        response["result"]["tools"].as_array().unwrap().len(),
+        3,
+        "{}",
+        response
+    );
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn protocol_version_is_negotiated() {
+    for version in SUPPORTED_PROTOCOL_VERSIONS {
+        let mut client = Client::start();
+        assert_eq!(
+            client.initialize_with(version).await["protocolVersion"],
+            *version
+        );
+        client.finish().await;
+    }
+
+    let mut client = Client::start();
+    assert_eq!(
+        client.initialize_with("1999-01-01").await["protocolVersion"],
+        PROTOCOL_VERSION
+    );
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn initialize_is_only_accepted_once() {
+    let mut client = Client::start();
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":1,"method":"initialize"}"#)
+        .await;
+    assert_error(&response, json!(1), error_codes::INVALID_PARAMS);
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":2,"method":"initialize","params":{"protocolVersion":"2024-11-05"}}"#)
+        .await;
+    assert_error(&response, json!(2), error_codes::INVALID_PARAMS);
+
+    // invalid params leave the server uninitialized
+    client.initialize().await;
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":3,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"0"}}}"#)
+        .await;
+    assert_error(&response, json!(3), error_codes::INVALID_REQUEST);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn shutdown_answers_then_stops_the_server() {
+    let mut client = Client::start();
+    client.initialize().await;
+    let response = client
+        .call(
+            r#"[{"jsonrpc":"2.0","id":1,"method":"shutdown"},{"jsonrpc":"2.0","id":2,"method":"tools/list"},{"jsonrpc":"2.0","id":3,"method":"ping"}]"#,
+        )
+        .await;
+    let responses = response.as_array().expect("batch response is an array");
+    assert_eq!(
+        responses[0],
+        json!({"jsonrpc": "2.0", "id": 1, "result": {}})
+    );
+    assert_error(&responses[1], json!(2), error_codes::INVALID_REQUEST);
+    assert_eq!(
+        responses[2],
+        json!({"jsonrpc": "2.0", "id": 3, "result": {}})
+    );
+    client.stopped().await;
+}
+
+#[tokio::test]
+async fn exit_notification_stops_the_server() {
+    let mut client = Client::start();
+    client.send(r#"{"jsonrpc":"2.0","method":"exit"}"#).await;
+    client.stopped().await;
+}
+
+#[tokio::test]
+async fn slow_calls_do_not_block_other_requests() {
+    let mut client = Client::start();
+    client.initialize().await;
+    client.send(&tool_call(json!(1), "hang")).await;
+    client.assert_silent().await;
+    let response = client.call(&tool_call(json!(2), "echo")).await;
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["result"]["content"][0]["text"], "hi");
+    client.send(&cancel(json!(1))).await;
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn cancelled_requests_get_no_response() {
+    let mut client = Client::start();
+    client.initialize().await;
+    client.send(&tool_call(json!("slow"), "hang")).await;
+    // ids that are not in flight are ignored
+    client.send(&cancel(json!(7))).await;
+    client.send(&cancel(json!("slow"))).await;
+    client.assert_silent().await;
+    // cancelling something that already finished is ignored
+    client.send(&cancel(json!("slow"))).await;
+    client.assert_silent().await;
+    // the server stops right away: nothing is left in flight
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn cancelled_batch_entries_are_left_out() {
+    let mut client = Client::start();
+    client.initialize().await;
+    client
+        .send(&format!(
+            "[{},{}]",
+            tool_call(json!("a"), "hang"),
+            tool_call(json!("b"), "echo")
+        ))
+        .await;
+    client.send(&cancel(json!("a"))).await;
+    let response = client.recv().await;
+    let responses = response.as_array().expect("batch response is an array");
+    assert_eq!(responses.len(), 1, "{}", response);
+    assert_eq!(responses[0]["id"], "b");
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn tool_timeouts_produce_error_results() {
+    let mut client =
+        Client::start_with(|server| server.set_tool_timeout("hang", Duration::from_millis(50)));
+    client.initialize().await;
+    let response = client.call(&tool_call(json!(1), "hang")).await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["isError"], true, "{}", response);
+    let text = response["result"]["content"][0]["text"].as_str().unwrap();
+    assert!(text.contains("timed out"), "{}", text);
+
+    // other tools keep the default limit
+    let response = client.call(&tool_call(json!(2), "echo")).await;
+    assert_eq!(response["result"]["isError"], false, "{}", response);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn default_tool_timeout_applies_to_every_tool() {
+    let mut client =
+        Client::start_with(|server| server.set_default_tool_timeout(Duration::from_millis(50)));
+    client.initialize().await;
+    let response = client.call(&tool_call(json!(1), "hang")).await;
+    assert_eq!(response["result"]["isError"], true, "{}", response);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn in_flight_limit_queues_calls_but_not_pings() {
+    let mut client = Client::start_with(|server| {
+        server.set_max_in_flight(1);
+        server.set_tool_timeout("hang", Duration::from_millis(200));
+    });
+    client.initialize().await;
+    client.send(&tool_call(json!(1), "hang")).await;
+    // waits for the first call to finish
+    client.send(&tool_call(json!(2), "hang")).await;
+    client
+        .send(r#"{"jsonrpc":"2.0","id":3,"method":"ping"}"#)
+        .await;
+
+    let ids: Vec<Value> = [
+        client.recv().await,
+        client.recv().await,
+        client.recv().await,
+    ]
+    .iter()
+    .map(|response| response["id"].clone())
+    .collect();
+    assert_eq!(ids, [json!(3), json!(1), json!(2)]);
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn calls_over_a_full_queue_are_rejected_as_busy() {
+    let mut client = Client::start_with(|server| server.set_max_in_flight(1));
+    client.initialize().await;
+    client.send(&tool_call(json!(1), "hang")).await;
+    client.send(&tool_call(json!(2), "hang")).await;
+
+    let response = client.call(&tool_call(json!(3), "echo")).await;
+    assert_error(&response, json!(3), -32000);
+    // only the calls of a batch are rejected
+    let response = client
+        .call(&format!(
+            r#"[{{"jsonrpc":"2.0","id":4,"method":"ping"}},{}]"#,
+            tool_call(json!(5), "echo")
+        ))
+        .await;
+    assert_eq!(
+        response[0],
+        json!({"jsonrpc": "2.0", "id": 4, "result": {}})
+    );
+    assert_error(&response[1], json!(5), -32000);
+
+    client.send(&cancel(json!(2))).await;
+    client.send(&cancel(json!(1))).await;
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn cancellations_are_read_while_saturated() {
+    let mut client = Client::start_with(|server| server.set_max_in_flight(1));
+    client.initialize().await;
+    client.send(&tool_call(json!("a"), "hang")).await;
+    client
+        .send(&format!(
+            r#"[{},{{"jsonrpc":"2.0","id":"p","method":"ping"}}]"#,
+            tool_call(json!("b"), "hang")
+        ))
+        .await;
+
+    // the queued call is dropped and the rest of its batch answered
+    client.send(&cancel(json!("b"))).await;
+    let response = client.recv().await;
+    assert_eq!(
+        response,
+        json!([{"jsonrpc": "2.0", "id": "p", "result": {}}])
+    );
+
+    // cancelling the running call frees its slot
+    client.send(&cancel(json!("a"))).await;
+    let response = client.call(&tool_call(json!("c"), "echo")).await;
+    assert_eq!(response["id"], "c");
+    assert_eq!(response["result"]["content"][0]["text"], "hi");
+    client.assert_silent().await;
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn calls_in_flight_are_answered_before_stopping() {
+    let mut client =
+        Client::start_with(|server| server.set_tool_timeout("hang", Duration::from_millis(50)));
+    client.initialize().await;
+    client.send(&tool_call(json!(1), "hang")).await;
+    client.send(r#"{"jsonrpc":"2.0","method":"exit"}"#).await;
+    let response = client.recv().await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["isError"], true, "{}", response);
+    client.stopped().await;
+}
@@ -0,0 +1,197 @@
+//! `_meta.progressToken` on `tools/call` and the `notifications/progress`
+//! tools send while they run.
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use avx_mcp::tools::aviladb_query::{QueryBackend, QueryPage, QueryTool};
+use avx_mcp::{CallToolResult, ProgressReporter, Tool, ToolHandler};
+use common::Client;
+use serde_json::{json, Value};
+
+/// Reports steps 1, 1, 2 and 3 of 3; the repeated step must be dropped.
+struct Steps;
+
+#[async_trait]
+impl ToolHandler for Steps {
+    fn definition(&self) -> Tool {
+        Tool {
+            name: "steps".to_string(),
+            description: "Reports progress through three steps".to_string(),
+            input_schema: json!({"type": "object"}),
+        }
+    }
+
+    async fn call(&self, args: HashMap<String, Value>) -> CallToolResult {
+        self.call_with_progress(args, ProgressReporter::disabled())
+            .await
+    }
+
+    async fn call_with_progress(
+        &self,
+        _args: HashMap<String, Value>,
+        progress: ProgressReporter,
+    ) -> CallToolResult {
+        for step in [1.0, 1.0, 2.0, 3.0] {
+            progress.report(step, Some(3.0), Some(format!("step {}", step)));
+        }
+        CallToolResult::text("done")
+    }
+}
+
+/// Serves `total` numbered documents, `page_size` at a time, with the page
+/// offset as the cursor.
+struct Numbers {
+    total: u64,
+}
+
+#[async_trait]
+impl QueryBackend for Numbers {
+    async fn query(
+        &self,
+        _database: &str,
+        _collection: &str,
+        _filter: &Value,
+        cursor: Option<&str>,
+        page_size: usize,
+    ) -> anyhow::Result<QueryPage> {
+        let start: u64 = cursor.map(str::parse).transpose()?.unwrap_or(0);
+        let end = (start + page_size as u64).min(self.total);
+        Ok(QueryPage {
+            documents: (start..end).map(|n| json!({ "n": n })).collect(),
+            next_cursor: (end < self.total).then(|| end.to_string()),
+            total: Some(self.total),
+        })
+    }
+}
+
+fn start() -> Client {
+    Client::start_with(|server| {
+        server.register_tool(Arc::new(Steps));
+        server.register_tool(Arc::new(
+            QueryTool::new().with_backend(Arc::new(Numbers { total: 250 })),
+        ));
+    })
+}
+
+fn call_with_token(id: u64, name: &str, arguments: Value, token: Value) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": "tools/call",
+        "params": {"name": name, "arguments": arguments, "_meta": {"progressToken": token}},
+    })
+    .to_string()
+}
+
+fn query(limit: u64) -> Value {
+    json!({"database": "production", "collection": "events", "query": "{}", "limit": limit})
+}
+
+#[tokio::test]
+async fn progress_is_sent_before_the_response_with_the_token() {
+    let mut client = start();
+    client.initialize().await;
+
+    client
+        .send(&call_with_token(1, "steps", json!({}), json!("steps-1")))
+        .await;
+    for step in [1.0, 2.0, 3.0] {
+        let notification = client.recv().await;
+        assert_eq!(notification["method"], "notifications/progress");
+        assert!(notification.get("id").is_none());
+        let params = &notification["params"];
+        assert_eq!(params["progressToken"], "steps-1");
+        assert_eq!(params["progress"], step);
+        assert_eq!(params["total"], 3.0);
+        assert_eq!(params["message"], format!("step {}", step));
+    }
+    let response = client.recv().await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["content"][0]["text"], "done");
+
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn numeric_tokens_are_echoed_as_numbers() {
+    let mut client = start();
+    client.initialize().await;
+
+    client
+        .send(&call_with_token(1, "steps", json!({}), json!(42)))
+        .await;
+    assert_eq!(client.recv().await["params"]["progressToken"], 42);
+
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn no_progress_without_a_token() {
+    let mut client = start();
+    client.initialize().await;
+
+    let response = client
+        .call(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"steps","arguments":{}}}"#)
+        .await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["content"][0]["text"], "done");
+    client.assert_silent().await;
+
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn query_reports_each_page() {
+    let mut client = start();
+    client.initialize().await;
+
+    client
+        .send(&call_with_token(
+            1,
+            "avx_query",
+            query(1000),
+            json!("query"),
+        ))
+        .await;
+    for fetched in [100, 200, 250] {
+        let params = client.recv().await["params"].clone();
+        assert_eq!(params["progressToken"], "query");
+        assert_eq!(params["progress"], fetched as f64);
+        assert_eq!(params["total"], 250.0);
+    }
+    let response = client.recv().await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["isError"], false);
+    assert_eq!(
+        response["result"]["content"][0]["text"],
+        "🔍 250 documents from production/events"
+    );
+
+    client.finish().await;
+}
+
+#[tokio::test]
+async fn query_progress_total_is_capped_by_the_limit() {
+    let mut client = start();
+    client.initialize().await;
+
+    client
+        .send(&call_with_token(1, "avx_query", query(150), json!("query")))
+        .await;
+    for fetched in [100, 150] {
+        let params = client.recv().await["params"].clone();
+        assert_eq!(params["progress"], fetched as f64);
+        assert_eq!(params["total"], 150.0);
+    }
+    let response = client.recv().await;
+    let documents: Vec<Value> =
+        serde_json::from_str(response["result"]["content"][1]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(documents.len(), 150);
+    assert_eq!(documents[149], json!({"n": 149}));
+
+    client.finish().await;
+}
@@ -0,0 +1,77 @@
+//! `notifications/resources/list_changed` from the periodic re-listing of
+//! resource providers.
+
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use avx_mcp::resources::{ResourceError, ResourceProvider};
+use avx_mcp::{Resource, ResourceContents};
+use common::Client;
+use tokio::sync::Semaphore;
+
+/// Lists nothing the first time, then waits for `gate` to open and lists
+/// `test://a`.
+struct Gated {
+    gate: Arc<Semaphore>,
+    listed: AtomicBool,
+}
+
+#[async_trait]
+impl ResourceProvider for Gated {
+    fn prefix(&self) -> &str {
+        "test://"
+    }
+
+    async fn list(&self) -> Vec<Resource> {
+        if !self.listed.swap(true, Ordering::SeqCst) {
+            return Vec::new();
+        }
+        let _open = self.gate.acquire().await;
+        vec![Resource {
+            uri: "test://a".to_string(),
+            name: "a".to_string(),
+            description: None,
+            mime_type: None,
+        }]
+    }
+
+    async fn read(&self, uri: &str) -> Result<ResourceContents, ResourceError> {
+        Err(ResourceError::NotFound(uri.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn slow_refreshes_do_not_block_requests() {
+    let gate = Arc::new(Semaphore::new(0));
+    let provider = Arc::new(Gated {
+        gate: gate.clone(),
+        listed: Default::default(),
+    });
+    let mut client = Client::start_with(move |server| {
+        server.set_refresh_interval(Duration::from_millis(10));
+        server.register_resource_provider(provider);
+    });
+    client.initialize().await;
+
+    // a refresh is stuck in `list` by now
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    client.assert_silent().await;
+
+    gate.add_permits(1);
+    let notification = client.recv().await;
+    assert_eq!(
+        notification["method"], "notifications/resources/list_changed",
+        "{}",
+        notification
+    );
+    assert!(notification.get("id").is_none());
+    // announced once, not on every later refresh
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    client.assert_silent().await;
+
+    client.finish().await;
+}